@@ -0,0 +1,63 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use kajar_import::cc::tim::{rgba5551_to_rgba8888, TIMImage};
+
+const W: usize = 1024;
+const H: usize = 1024;
+
+/// Pseudo-random RGBA5551 pixels for a 1024x1024 image
+fn pixels() -> Vec<u16> {
+    let mut seed = 0x19000000u32;
+    (0..(W * H))
+        .map(|_| {
+            seed = seed.wrapping_mul(0x41C64E6D).wrapping_add(12345);
+            (seed >> 16) as u16
+        })
+        .collect()
+}
+
+/// A 16bpp TIM file holding `pixels`
+fn tim(pixels: &[u16]) -> Vec<u8> {
+    let mut tim = Vec::with_capacity(20 + pixels.len() * 2);
+    tim.extend_from_slice(&16u32.to_le_bytes());
+    tim.extend_from_slice(&2u32.to_le_bytes());
+    tim.extend_from_slice(&(12 + pixels.len() as u32 * 2).to_le_bytes());
+    tim.extend_from_slice(&[0, 0, 0, 0]);
+    tim.extend_from_slice(&(W as u16).to_le_bytes());
+    tim.extend_from_slice(&(H as u16).to_le_bytes());
+    pixels
+        .iter()
+        .for_each(|px| tim.extend_from_slice(&px.to_le_bytes()));
+    tim
+}
+
+/// The previous per-channel `push` expansion, which the loader's output must match
+fn expand_push(src: &[u16]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(src.len() * 4);
+    for i in src.iter() {
+        let (r, g, b, a) = rgba5551_to_rgba8888(*i as u32);
+        data.push(r);
+        data.push(g);
+        data.push(b);
+        data.push(a);
+    }
+    data
+}
+
+fn bench_load(c: &mut Criterion) {
+    let src = pixels();
+    let tim = tim(&src);
+
+    let img = TIMImage::from_bytes(&tim[..]).unwrap();
+    assert_eq!(img.rgba_bytes(), &expand_push(&src)[..]);
+
+    let mut group = c.benchmark_group("tim_rgba_1024x1024");
+    group.bench_function("push", |b| b.iter(|| expand_push(black_box(&src))));
+    group.bench_function("from_bytes", |b| {
+        b.iter(|| TIMImage::from_bytes(black_box(&tim[..])).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_load);
+criterion_main!(benches);
@@ -1,45 +1,128 @@
-use bytemuck::{
-	bytes_of_mut,
-	Pod,
-	Zeroable
-};
+use bytemuck::{bytes_of_mut, Pod};
+use bytemuck_derive::{Pod, Zeroable};
+
+use std::io::{self, Read};
+
+/// Enemy AI script error
+#[derive(Debug)]
+pub enum AIErr {
+	ConditionRead(io::Error),
+	UnknownCondition(u8),
+}
 
 /// HP less than half
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 #[repr(C)]
-struct HPLessThanHalf 
+pub struct HPLessThanHalf {
+	pub target: u8,
+	_1: [u8; 2],
+}
 
 /// Check for status
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 #[repr(C)]
-struct CheckForStatus {
-	target: u8,
-	offs: u8,
-	check_bits: u8,
+pub struct CheckForStatus {
+	pub target: u8,
+	pub offs: u8,
+	pub check_bits: u8,
 }
 
 /// Check if something moved
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 #[repr(C)]
-struct CheckIfMoved {
-	target: u8,
-	entity: u8,
+pub struct CheckIfMoved {
+	pub target: u8,
+	pub entity: u8,
 	_2: u8,
 }
 
 /// Check status of entity
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 #[repr(C)]
-struct CheckEntityStatus {
+pub struct CheckEntityStatus {
 	_0: u8,
-	entity: u8,
-	is_dead: bool,
+	pub entity: u8,
+	is_dead: u8,
+}
+
+impl CheckEntityStatus {
+	/// Whether the check passes on the entity being dead rather than alive
+	pub fn is_dead(&self) -> bool {
+		self.is_dead != 0
+	}
 }
 
 /// Checks for max number of living enemies
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
 #[repr(C)]
-struct CheckMaxLivingEntities {
-	n: u8,
+pub struct CheckMaxLivingEntities {
+	pub n: u8,
 	_1: [u8; 2],
-}
\ No newline at end of file
+}
+
+/// A condition an enemy AI script checks before acting
+#[derive(Clone, Copy, Debug)]
+pub enum AICondition {
+	HPLessThanHalf(HPLessThanHalf),
+	CheckForStatus(CheckForStatus),
+	CheckIfMoved(CheckIfMoved),
+	CheckEntityStatus(CheckEntityStatus),
+	CheckMaxLivingEntities(CheckMaxLivingEntities),
+}
+
+impl AICondition {
+	/// Reads the arguments of the condition with opcode `kind`
+	pub fn parse(kind: u8, buf: &mut impl Read) -> Result<AICondition, AIErr> {
+		match kind {
+			0 => read_args(buf).map(AICondition::HPLessThanHalf),
+			1 => read_args(buf).map(AICondition::CheckForStatus),
+			2 => read_args(buf).map(AICondition::CheckIfMoved),
+			3 => read_args(buf).map(AICondition::CheckEntityStatus),
+			4 => read_args(buf).map(AICondition::CheckMaxLivingEntities),
+			_ => Err(AIErr::UnknownCondition(kind)),
+		}
+	}
+}
+
+/// Reads a condition's fixed-size arguments
+fn read_args<T: Pod>(buf: &mut impl Read) -> Result<T, AIErr> {
+	let mut args = T::zeroed();
+	buf.read_exact(bytes_of_mut(&mut args))
+		.map_err(|e| AIErr::ConditionRead(e))?;
+
+	Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{AICondition, AIErr};
+
+	#[test]
+	fn test_parse_condition() {
+		let mut buf = &[0x01, 0x1D, 0x80, 0x07, 0x00, 0x00][..];
+
+		let AICondition::CheckForStatus(status) = AICondition::parse(1, &mut buf).unwrap() else {
+			panic!("wrong condition");
+		};
+		assert_eq!(
+			(status.target, status.offs, status.check_bits),
+			(0x01, 0x1D, 0x80)
+		);
+
+		let AICondition::CheckMaxLivingEntities(living) = AICondition::parse(4, &mut buf).unwrap()
+		else {
+			panic!("wrong condition");
+		};
+		assert_eq!(living.n, 7);
+		assert!(buf.is_empty());
+
+		assert!(matches!(
+			AICondition::parse(0, &mut buf),
+			Err(AIErr::ConditionRead(_))
+		));
+		assert!(matches!(
+			AICondition::parse(0xFF, &mut buf),
+			Err(AIErr::UnknownCondition(0xFF))
+		));
+	}
+}
@@ -0,0 +1,307 @@
+#[cfg(feature = "ogg")]
+use vorbis_rs::{VorbisEncoderBuilder, VorbisError};
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
+#[cfg(feature = "ogg")]
+use std::num::{NonZeroU32, NonZeroU8};
+
+/// Output container for extracted audio
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum AudioFormat {
+    #[default]
+    Wav,
+
+    #[cfg(feature = "ogg")]
+    Ogg,
+}
+
+/// A MIDI event at an absolute time in ticks
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MidiEvent {
+    pub tick: u32,
+    pub data: Vec<u8>,
+}
+
+/// Audio export errors
+#[derive(Debug)]
+pub enum AudioErr {
+    Channels(u8),
+    FileWrite(io::Error),
+    SampleRate(u32),
+
+    #[cfg(feature = "ogg")]
+    Encode(VorbisError),
+}
+
+/// Writes interleaved 16-bit PCM samples in the given format. Loop points, as a start and end
+/// sample, are kept where the format supports them.
+pub fn write_audio(
+    format: AudioFormat,
+    samples: &[i16],
+    sample_rate: u32,
+    channels: u8,
+    loop_pts: Option<(u32, u32)>,
+    path: &str,
+) -> Result<(), AudioErr> {
+    match format {
+        AudioFormat::Wav => write_wav(samples, sample_rate, channels, loop_pts, path),
+
+        #[cfg(feature = "ogg")]
+        AudioFormat::Ogg => write_ogg(samples, sample_rate, channels, loop_pts, path),
+    }
+}
+
+/// Writes interleaved 16-bit PCM samples to a RIFF/WAVE file, with loop points in a `smpl` chunk
+pub fn write_wav(
+    samples: &[i16],
+    sample_rate: u32,
+    channels: u8,
+    loop_pts: Option<(u32, u32)>,
+    path: &str,
+) -> Result<(), AudioErr> {
+    if channels == 0 {
+        return Err(AudioErr::Channels(channels));
+    }
+
+    let file = File::create(path).map_err(|e| AudioErr::FileWrite(e))?;
+    let mut w = BufWriter::new(file);
+
+    let data_size = (samples.len() * 2) as u32;
+    let block_align = channels as u16 * 2;
+    let smpl = loop_pts.map(|(start, end)| smpl_chunk(sample_rate, start, end));
+    let smpl_size = smpl.as_ref().map_or(0, |c| c.len() as u32);
+
+    let mut hdr = Vec::with_capacity(44);
+    hdr.extend_from_slice(b"RIFF");
+    hdr.extend_from_slice(&(36 + data_size + smpl_size).to_le_bytes());
+    hdr.extend_from_slice(b"WAVEfmt ");
+    hdr.extend_from_slice(&16u32.to_le_bytes());
+    hdr.extend_from_slice(&1u16.to_le_bytes());
+    hdr.extend_from_slice(&(channels as u16).to_le_bytes());
+    hdr.extend_from_slice(&sample_rate.to_le_bytes());
+    hdr.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    hdr.extend_from_slice(&block_align.to_le_bytes());
+    hdr.extend_from_slice(&16u16.to_le_bytes());
+    hdr.extend_from_slice(b"data");
+    hdr.extend_from_slice(&data_size.to_le_bytes());
+
+    w.write_all(&hdr[..]).map_err(|e| AudioErr::FileWrite(e))?;
+    for s in samples.iter() {
+        w.write_all(&s.to_le_bytes())
+            .map_err(|e| AudioErr::FileWrite(e))?;
+    }
+    if let Some(smpl) = smpl {
+        w.write_all(&smpl[..]).map_err(|e| AudioErr::FileWrite(e))?;
+    }
+
+    w.flush().map_err(|e| AudioErr::FileWrite(e))
+}
+
+/// Builds a `smpl` chunk holding a single forward loop. The end sample is exclusive, while the
+/// chunk stores the last sample played.
+pub fn smpl_chunk(sample_rate: u32, loop_start: u32, loop_end: u32) -> Vec<u8> {
+    let mut chk = Vec::with_capacity(68);
+    chk.extend_from_slice(b"smpl");
+    chk.extend_from_slice(&60u32.to_le_bytes());
+
+    // manufacturer, product, sample period in ns, MIDI unity note, pitch fraction, SMPTE format
+    // and offset, loop count and sampler data size
+    let period = 1_000_000_000 / sample_rate.max(1);
+    for v in [0, 0, period, 60, 0, 0, 0, 1, 0] {
+        chk.extend_from_slice(&u32::to_le_bytes(v));
+    }
+
+    // cue ID, forward loop type, start, end, fraction and infinite play count
+    for v in [0, 0, loop_start, loop_end.saturating_sub(1), 0, 0] {
+        chk.extend_from_slice(&u32::to_le_bytes(v));
+    }
+
+    chk
+}
+
+/// Vorbis comments marking a loop, as honoured by common players
+pub fn loop_comments(loop_start: u32, loop_end: u32) -> [(&'static str, String); 2] {
+    [
+        ("LOOPSTART", loop_start.to_string()),
+        (
+            "LOOPLENGTH",
+            loop_end.saturating_sub(loop_start).to_string(),
+        ),
+    ]
+}
+
+/// Writes a format 1 Standard MIDI File with one track per event list, at `division` ticks per
+/// quarter note. Events are stored in time order, and each track is closed with an end of track
+/// event.
+pub fn write_midi(tracks: &[Vec<MidiEvent>], division: u16, path: &str) -> Result<(), AudioErr> {
+    let file = File::create(path).map_err(|e| AudioErr::FileWrite(e))?;
+    let mut w = BufWriter::new(file);
+
+    let mut smf = Vec::new();
+    smf.extend_from_slice(b"MThd");
+    smf.extend_from_slice(&6u32.to_be_bytes());
+    smf.extend_from_slice(&1u16.to_be_bytes());
+    smf.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+    smf.extend_from_slice(&division.to_be_bytes());
+
+    for events in tracks.iter() {
+        let mut events = events.iter().collect::<Vec<_>>();
+        events.sort_by_key(|e| e.tick);
+
+        let mut trk = Vec::new();
+        let mut last = 0;
+        for e in events.iter() {
+            put_vlq(&mut trk, e.tick - last);
+            trk.extend_from_slice(&e.data[..]);
+            last = e.tick;
+        }
+        trk.extend_from_slice(&[0, 0xFF, 0x2F, 0]);
+
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(trk.len() as u32).to_be_bytes());
+        smf.extend(trk);
+    }
+
+    w.write_all(&smf[..])
+        .and_then(|_| w.flush())
+        .map_err(|e| AudioErr::FileWrite(e))
+}
+
+/// Appends a MIDI variable-length quantity, 7 bits per byte with the most significant first
+fn put_vlq(buf: &mut Vec<u8>, mut n: u32) {
+    let mut bytes = vec![(n & 0x7F) as u8];
+    n >>= 7;
+    while n != 0 {
+        bytes.push((n & 0x7F) as u8 | 0x80);
+        n >>= 7;
+    }
+
+    buf.extend(bytes.iter().rev());
+}
+
+/// Encodes interleaved 16-bit PCM samples to an Ogg Vorbis file
+#[cfg(feature = "ogg")]
+pub fn write_ogg(
+    samples: &[i16],
+    sample_rate: u32,
+    channels: u8,
+    loop_pts: Option<(u32, u32)>,
+    path: &str,
+) -> Result<(), AudioErr> {
+    let rate = NonZeroU32::new(sample_rate).ok_or(AudioErr::SampleRate(sample_rate))?;
+    let nch = NonZeroU8::new(channels).ok_or(AudioErr::Channels(channels))?;
+    let file = File::create(path).map_err(|e| AudioErr::FileWrite(e))?;
+
+    let mut builder = VorbisEncoderBuilder::new(rate, nch, BufWriter::new(file))
+        .map_err(|e| AudioErr::Encode(e))?;
+    if let Some((start, end)) = loop_pts {
+        for (tag, value) in loop_comments(start, end) {
+            builder.comment_tag(tag, value);
+        }
+    }
+    let mut enc = builder.build().map_err(|e| AudioErr::Encode(e))?;
+
+    // the encoder takes planar float blocks
+    let mut planes = vec![Vec::with_capacity(samples.len() / channels as usize); channels as usize];
+    for frame in samples.chunks_exact(channels as usize) {
+        for (p, s) in planes.iter_mut().zip(frame.iter()) {
+            p.push(*s as f32 / 32768.0);
+        }
+    }
+
+    enc.encode_audio_block(&planes[..])
+        .map_err(|e| AudioErr::Encode(e))?;
+    enc.finish().map_err(|e| AudioErr::Encode(e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_smpl_chunk() {
+        let chk = super::smpl_chunk(44100, 1000, 5000);
+        assert_eq!(chk.len(), 68);
+        assert_eq!(&chk[..4], b"smpl");
+
+        let word = |i: usize| u32::from_le_bytes(chk[i..(i + 4)].try_into().unwrap());
+        assert_eq!(word(4), 60);
+        assert_eq!(word(36), 1);
+        assert_eq!(word(52), 1000);
+        assert_eq!(word(56), 4999);
+    }
+
+    #[test]
+    fn test_write_midi() {
+        use super::MidiEvent;
+
+        let mut vlq = Vec::new();
+        for n in [0, 0x7F, 0x80, 0x3FFF, 0x0FFF_FFFF] {
+            super::put_vlq(&mut vlq, n);
+        }
+        assert_eq!(
+            vlq,
+            [0x00, 0x7F, 0x81, 0x00, 0xFF, 0x7F, 0xFF, 0xFF, 0xFF, 0x7F]
+        );
+
+        let f = tempfile::NamedTempFile::new().unwrap();
+        let note = |tick, status| MidiEvent {
+            tick,
+            data: vec![status, 60, 100],
+        };
+        super::write_midi(
+            &[vec![note(200, 0x80), note(0, 0x90)]],
+            480,
+            f.path().to_str().unwrap(),
+        )
+        .unwrap();
+
+        let smf = std::fs::read(f.path()).unwrap();
+        assert_eq!(&smf[..4], b"MThd");
+        assert_eq!(&smf[8..14], &[0, 1, 0, 1, 0x01, 0xE0]);
+        assert_eq!(&smf[14..18], b"MTrk");
+        assert_eq!(&smf[18..22], &13u32.to_be_bytes());
+        assert_eq!(
+            &smf[22..],
+            &[0, 0x90, 60, 100, 0x81, 0x48, 0x80, 60, 100, 0, 0xFF, 0x2F, 0]
+        );
+    }
+
+    #[test]
+    fn test_loop_comments() {
+        let tags = super::loop_comments(44100, 441000);
+        assert_eq!(tags[0], ("LOOPSTART", "44100".to_owned()));
+        assert_eq!(tags[1], ("LOOPLENGTH", "396900".to_owned()));
+    }
+
+    #[cfg(feature = "ogg")]
+    #[test]
+    fn test_write_ogg() {
+        use super::AudioFormat;
+
+        let f = tempfile::NamedTempFile::new().unwrap();
+        let samples = (0..88200)
+            .map(|i| ((i as f32 * 0.05).sin() * 8000.0) as i16)
+            .collect::<Vec<i16>>();
+        super::write_audio(
+            AudioFormat::Ogg,
+            &samples[..],
+            44100,
+            2,
+            Some((4410, 22050)),
+            f.path().to_str().unwrap(),
+        )
+        .unwrap();
+
+        // the comment header is stored as plain text in the second page
+        let ogg = std::fs::read(f.path()).unwrap();
+        let has = |tag: &[u8]| ogg.windows(tag.len()).any(|w| w == tag);
+        assert_eq!(&ogg[..4], b"OggS");
+        assert!(has(b"LOOPSTART=4410"));
+        assert!(has(b"LOOPLENGTH=17640"));
+    }
+}
@@ -0,0 +1,271 @@
+// Dialogue as shipped in the `string_*.bin` resources. Each file starts with a little endian u32
+// string count, followed by that many u32 offsets from the start of the file. Each string is
+// Shift-JIS text broken up by control bytes below 0x20, and ends with a 0 byte. Shift-JIS never
+// uses bytes below 0x40 for the second byte of a character, so a control byte can't land in the
+// middle of a character.
+//
+// Control bytes:
+//   01        line break
+//   02        page
+//   03        auto page
+//   04        auto end
+//   05 nn     wait nn
+//   06 nn     nn spaces
+//   07        narration
+//   08        sharp
+//   09        number
+//   0A nn     name (see NAMES)
+//   0B nn     icon, indexing IconId::ALL
+//   0C nn     button (see BUTTONS)
+//   0D-10 ... 11  choice 1-4, with the choice's text running up to the 11
+
+use encoding_rs::SHIFT_JIS;
+use indexmap::IndexMap;
+
+use crate::{
+    encoding,
+    markup::{IconId, Token},
+};
+
+const CTL_END: u8 = 0x00;
+const CTL_LINE_BREAK: u8 = 0x01;
+const CTL_PAGE: u8 = 0x02;
+const CTL_AUTO_PAGE: u8 = 0x03;
+const CTL_AUTO_END: u8 = 0x04;
+const CTL_WAIT: u8 = 0x05;
+const CTL_SPACE: u8 = 0x06;
+const CTL_NARRATE: u8 = 0x07;
+const CTL_SHARP: u8 = 0x08;
+const CTL_NUMBER: u8 = 0x09;
+const CTL_NAME: u8 = 0x0A;
+const CTL_ICON: u8 = 0x0B;
+const CTL_BUTTON: u8 = 0x0C;
+const CTL_CHOICE1: u8 = 0x0D;
+const CTL_CHOICE4: u8 = 0x10;
+const CTL_CHOICE_END: u8 = 0x11;
+
+/// First byte that isn't a control byte
+const CTL_MAX: u8 = 0x20;
+
+/// Name placeholders, by the argument of `CTL_NAME`
+const NAMES: [Token; 14] = [
+    Token::CronoName,
+    Token::MarleName,
+    Token::LuccaName,
+    Token::RoboName,
+    Token::FrogName,
+    Token::AylaName,
+    Token::MagusName,
+    Token::EpochName,
+    Token::PartyCharacter1,
+    Token::PartyCharacter2,
+    Token::PartyCharacter3,
+    Token::ItemName,
+    Token::TechName,
+    Token::Name,
+];
+
+/// Button prompts, by the argument of `CTL_BUTTON`
+const BUTTONS: [Token; 6] = [
+    Token::Config,
+    Token::Dash,
+    Token::L,
+    Token::R,
+    Token::Menu,
+    Token::Warp,
+];
+
+/// Binary dialogue error. Positions are byte offsets into the string or file.
+#[derive(Debug, Eq, PartialEq)]
+pub enum DialogueErr {
+    ControlArg(u8, usize),
+    Offset(usize),
+    Truncated(usize),
+    UnknownControl(u8, usize),
+}
+
+/// Decodes one string into the tokens the markup parser gives for the same dialogue. Decoding
+/// stops at the terminating 0 byte, or the end of `buf` if there isn't one.
+pub fn decode_string(buf: &[u8]) -> Result<Vec<Token>, DialogueErr> {
+    let mut toks = Vec::new();
+    let mut pos = 0;
+
+    while let Some(&c) = buf.get(pos) {
+        if c >= CTL_MAX {
+            let len = text_len(&buf[pos..]);
+            toks.push(Token::Text(encoding::decode(
+                &buf[pos..pos + len],
+                SHIFT_JIS,
+            )));
+            pos += len;
+            continue;
+        }
+
+        let arg = || buf.get(pos + 1).copied().ok_or(DialogueErr::Truncated(pos));
+        let (tok, len) = match c {
+            CTL_END => break,
+            CTL_LINE_BREAK => (Token::LineBreak, 1),
+            CTL_PAGE => (Token::Page, 1),
+            CTL_AUTO_PAGE => (Token::AutoPage, 1),
+            CTL_AUTO_END => (Token::AutoEnd, 1),
+            CTL_NARRATE => (Token::Narrate, 1),
+            CTL_SHARP => (Token::Sharp, 1),
+            CTL_NUMBER => (Token::Number, 1),
+            CTL_WAIT => (Token::Wait(arg()?), 2),
+            CTL_SPACE => (Token::Space(arg()?), 2),
+            CTL_NAME => (lookup(&NAMES, c, arg()?, pos)?, 2),
+            CTL_BUTTON => (lookup(&BUTTONS, c, arg()?, pos)?, 2),
+            CTL_ICON => {
+                let icon = IconId::ALL
+                    .get(arg()? as usize)
+                    .ok_or(DialogueErr::ControlArg(c, pos))?;
+                (icon.to_token(), 2)
+            }
+            CTL_CHOICE1..=CTL_CHOICE4 => {
+                let start = pos + 1;
+                let len = buf[start..]
+                    .iter()
+                    .position(|b| *b == CTL_CHOICE_END)
+                    .ok_or(DialogueErr::Truncated(pos))?;
+                let txt = encoding::decode(&buf[start..start + len], SHIFT_JIS);
+                pos = start + len + 1;
+
+                toks.push(match c - CTL_CHOICE1 {
+                    0 => Token::Choice1(txt),
+                    1 => Token::Choice2(txt),
+                    2 => Token::Choice3(txt),
+                    _ => Token::Choice4(txt),
+                });
+                continue;
+            }
+            _ => return Err(DialogueErr::UnknownControl(c, pos)),
+        };
+
+        pos += len;
+        toks.push(tok);
+    }
+
+    Ok(toks)
+}
+
+/// Decodes every string in a `string_*.bin` file, keyed by its index in the file. Empty strings
+/// are left out, as with the markup parser.
+pub fn parse_string_table(buf: &[u8]) -> Result<IndexMap<u16, Vec<Token>>, DialogueErr> {
+    let n = read_u32(buf, 0)? as usize;
+    let mut entmap = IndexMap::new();
+
+    for i in 0..n {
+        let ptr = 4 + i * 4;
+        let offs = read_u32(buf, ptr)? as usize;
+        let s = buf.get(offs..).ok_or(DialogueErr::Offset(ptr))?;
+
+        let toks = decode_string(s)?;
+        if !toks.is_empty() {
+            entmap.insert(i as u16, toks);
+        }
+    }
+
+    Ok(entmap)
+}
+
+/// Length of the text run at the start of `buf`
+fn text_len(buf: &[u8]) -> usize {
+    buf.iter().position(|b| *b < CTL_MAX).unwrap_or(buf.len())
+}
+
+/// Looks up the token a control byte's argument selects
+fn lookup(table: &[Token], c: u8, arg: u8, pos: usize) -> Result<Token, DialogueErr> {
+    table
+        .get(arg as usize)
+        .cloned()
+        .ok_or(DialogueErr::ControlArg(c, pos))
+}
+
+/// Reads a little endian u32 at `pos`
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32, DialogueErr> {
+    buf.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or(DialogueErr::Truncated(pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_string, parse_string_table, DialogueErr};
+    use crate::markup::{ident_array, Token};
+
+    /// "<NAME_MAR>: クロノ<WAIT>10</WAIT>\<ICON_FIRE> hot!<PAGE><C1>Yes</C1><C2>No</C2>"
+    const MARLE: &[u8] = b"\x0a\x01: \x83\x4e\x83\x8d\x83\x6d\x05\x10\x01\x0b\x04 hot!\x02\
+\x0dYes\x11\x0eNo\x11\x00";
+
+    #[test]
+    fn test_decode_string() {
+        let toks = decode_string(MARLE).unwrap();
+        assert_eq!(
+            toks,
+            [
+                Token::MarleName,
+                Token::Text(": クロノ".to_owned()),
+                Token::Wait(0x10),
+                Token::LineBreak,
+                Token::FireIcon,
+                Token::Text(" hot!".to_owned()),
+                Token::Page,
+                Token::Choice1("Yes".to_owned()),
+                Token::Choice2("No".to_owned()),
+            ]
+        );
+
+        // both sources give the same token stream
+        let (_, markup) = ident_array(
+            "STR_00,<NAME_MAR>: クロノ<WAIT>10</WAIT>\\<ICON_FIRE> hot!<PAGE><C1>Yes</C1><C2>No</C2>",
+        )
+        .unwrap();
+        assert_eq!(markup[&0], toks);
+
+        assert_eq!(
+            decode_string(b"\x06\x03\x0c\x01\x09\x00ignored").unwrap(),
+            [Token::Space(3), Token::Dash, Token::Number]
+        );
+        assert_eq!(
+            decode_string(b"Hi\x1f"),
+            Err(DialogueErr::UnknownControl(0x1f, 2))
+        );
+        assert_eq!(
+            decode_string(b"\x0a\x40"),
+            Err(DialogueErr::ControlArg(0x0a, 0))
+        );
+        assert_eq!(decode_string(b"\x05"), Err(DialogueErr::Truncated(0)));
+        assert_eq!(decode_string(b"\x0dYes"), Err(DialogueErr::Truncated(0)));
+    }
+
+    #[test]
+    fn test_parse_string_table() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&3u32.to_le_bytes());
+        buf.extend_from_slice(&16u32.to_le_bytes());
+        buf.extend_from_slice(&17u32.to_le_bytes());
+        buf.extend_from_slice(&(17 + MARLE.len() as u32).to_le_bytes());
+        buf.push(0);
+        buf.extend_from_slice(MARLE);
+        buf.extend_from_slice(b"\x07Long ago...\x04\x00");
+
+        let table = parse_string_table(&buf[..]).unwrap();
+        assert_eq!(table.keys().copied().collect::<Vec<u16>>(), [1, 2]);
+        assert_eq!(table[&1], decode_string(MARLE).unwrap());
+        assert_eq!(
+            table[&2],
+            [
+                Token::Narrate,
+                Token::Text("Long ago...".to_owned()),
+                Token::AutoEnd
+            ]
+        );
+
+        buf[12..16].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert_eq!(parse_string_table(&buf[..]), Err(DialogueErr::Offset(12)));
+        assert_eq!(
+            parse_string_table(&buf[..6]),
+            Err(DialogueErr::Truncated(4))
+        );
+    }
+}
@@ -1,68 +1,199 @@
 #[cfg(feature = "cc_psx")]
-mod atim;
+pub mod atim;
 
 #[cfg(feature = "cc_psx")]
-mod drp;
+pub mod drp;
 
 #[cfg(feature = "cc_psx")]
-mod tim;
-
+pub mod mesh;
 
 #[cfg(feature = "cc_psx")]
-use atim;
+pub mod tim;
 
 #[cfg(feature = "cc_psx")]
-use drp;
+use std::{
+    fs,
+    io::{self, Cursor, Read},
+};
 
 #[cfg(feature = "cc_psx")]
-use tim;
-
+use crate::{Archive, ArchiveEntry};
 
 /// CPT errors
 #[cfg(feature = "cc_psx")]
 #[derive(Debug)]
 pub enum CPTErr {
     ArchiveRead(io::Error),
+    ArchiveWrite(io::Error),
     ChildRead(io::Error),
+    TableRead(io::Error),
 }
 
+/// The inner files of a .cpt file, in file order
+#[cfg(feature = "cc_psx")]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Cpt(pub Vec<Vec<u8>>);
+
 /// Loads the inner files of a .cpt file from the specified path
 #[cfg(feature = "cc_psx")]
-pub fn load_cpt(path: &str) -> Result<Vec<Vec<u8>>, CPTErr> {
+pub fn load_cpt(path: &str) -> Result<Cpt, CPTErr> {
     let cpt = fs::read(path).map_err(|e| CPTErr::ArchiveRead(e))?;
-    let n = cpt.get_u32_le() as usize;
-    let ptrs = (0..n)
-        .iter()
-        .map(|_| cpt.get_u32_le() as usize)
-        .collect::<Vec<usize>>();
-    let has_eof = ptrs[n] == cpt.len();
-
-    let files = if has_eof {
-        (0..(n - 1))
-            .iter()
-            .map(|i| {
-                let mut bin = vec![0; ptrs[i + 1] - ptrs[i]];
-                cpt.read_exact(&mut bin[..])
-                    .map_err(|e| CPTErr::ChildRead(e))?;
-                bin
-            })
-            .collect::<Vec<Vec<u8>>>();
-    } else {
-        (0..n)
+    cpt_from_bytes(&cpt[..])
+}
+
+/// Splits an in-memory .cpt file into its inner files. The file starts with a count and a table
+/// of offsets to each child; the table may end with an extra offset marking the end of the file.
+#[cfg(feature = "cc_psx")]
+pub fn cpt_from_bytes(cpt: &[u8]) -> Result<Cpt, CPTErr> {
+    let mut c = Cursor::new(cpt);
+    let mut read_u32 = || -> Result<usize, CPTErr> {
+        let mut b = [0; 4];
+        c.read_exact(&mut b).map_err(|e| CPTErr::TableRead(e))?;
+        Ok(u32::from_le_bytes(b) as usize)
+    };
+
+    let n = read_u32()?;
+    let mut ptrs = (0..n)
+        .map(|_| read_u32())
+        .collect::<Result<Vec<usize>, CPTErr>>()?;
+
+    if ptrs.last() == Some(&cpt.len()) {
+        ptrs.pop();
+    }
+
+    (0..ptrs.len())
+        .map(|i| {
+            let end = ptrs.get(i + 1).copied().unwrap_or(cpt.len());
+            cpt.get(ptrs[i]..end)
+                .map(|bin| bin.to_vec())
+                .ok_or(CPTErr::ChildRead(io::Error::from(
+                    io::ErrorKind::UnexpectedEof,
+                )))
+        })
+        .collect::<Result<Vec<Vec<u8>>, CPTErr>>()
+        .map(Cpt)
+}
+
+/// Packs files into a .cpt file at the specified path, in the layout `load_cpt` reads
+#[cfg(feature = "cc_psx")]
+pub fn save_cpt(files: &[Vec<u8>], path: &str) -> Result<(), CPTErr> {
+    fs::write(path, write_cpt(files)).map_err(|e| CPTErr::ArchiveWrite(e))
+}
+
+/// Packs files into an in-memory .cpt file: the count, each child's offset and the end of file
+/// offset, then the children. The end offset keeps an empty last child from being read back as
+/// the end marker.
+#[cfg(feature = "cc_psx")]
+fn write_cpt(files: &[Vec<u8>]) -> Vec<u8> {
+    let table_size = 4 + (files.len() + 1) * 4;
+    let mut cpt = Vec::with_capacity(table_size + files.iter().map(|f| f.len()).sum::<usize>());
+    cpt.extend_from_slice(&(files.len() as u32 + 1).to_le_bytes());
+
+    let mut ptr = table_size;
+    for f in files.iter() {
+        cpt.extend_from_slice(&(ptr as u32).to_le_bytes());
+        ptr += f.len();
+    }
+    cpt.extend_from_slice(&(ptr as u32).to_le_bytes());
+
+    for f in files.iter() {
+        cpt.extend_from_slice(&f[..]);
+    }
+
+    cpt
+}
+
+/// CPT children have no names, so they are listed by index
+#[cfg(feature = "cc_psx")]
+impl Archive for Cpt {
+    fn entries(&self) -> Vec<ArchiveEntry> {
+        self.0
             .iter()
-            .map(|i| {
-                let mut bin = if i == n {
-                    vec![0; cpt.len() - ptrs[i]]
-                } else {
-                    vec![0; ptrs[i + 1] - ptrs[i]]
-                };
-
-                cpt.read_exact(&mut bin[..])
-                    .map_err(|e| CPTErr::ChildRead(e))?;
-                bin
+            .enumerate()
+            .map(|(i, f)| ArchiveEntry {
+                name: i.to_string(),
+                size: f.len(),
+                kind: String::new(),
             })
-            .collect::<Vec<Vec<u8>>>();
-    };
+            .collect()
+    }
+
+    fn extract(&self, name: &str) -> Option<Vec<u8>> {
+        self.0.get(name.parse::<usize>().ok()?).cloned()
+    }
+}
+
+#[cfg(all(test, feature = "cc_psx"))]
+mod tests {
+    /// Builds a CPT file, optionally ending the offset table with the file's size
+    fn cpt(files: &[&[u8]], eof: bool) -> Vec<u8> {
+        let n = files.len() + eof as usize;
+        let mut buf = (n as u32).to_le_bytes().to_vec();
+
+        let mut ptr = 4 + n * 4;
+        for f in files {
+            buf.extend_from_slice(&(ptr as u32).to_le_bytes());
+            ptr += f.len();
+        }
+        if eof {
+            buf.extend_from_slice(&(ptr as u32).to_le_bytes());
+        }
+
+        for f in files {
+            buf.extend_from_slice(f);
+        }
+
+        buf
+    }
+
+    #[test]
+    fn test_cpt_archive() {
+        use crate::Archive;
+
+        let files: [&[u8]; 3] = [b"first child", b"second", b""];
+        let children = super::cpt_from_bytes(&cpt(&files, true)[..]).unwrap();
+
+        let entries = children.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((&entries[1].name[..], entries[1].size), ("1", 6));
+
+        assert_eq!(children.extract("0").unwrap(), files[0]);
+        assert!(children.extract("3").is_none());
+        assert!(children.extract("first").is_none());
+    }
+
+    #[test]
+    fn test_load_cpt() {
+        let files: [&[u8]; 2] = [b"first child", b"second"];
+
+        for eof in [false, true] {
+            let children = super::cpt_from_bytes(&cpt(&files, eof)[..]).unwrap();
+            assert_eq!(children.0, files);
+        }
+
+        assert!(matches!(
+            super::cpt_from_bytes(&[2, 0, 0, 0, 12, 0, 0, 0]),
+            Err(super::CPTErr::TableRead(_))
+        ));
+    }
+
+    #[test]
+    fn test_save_cpt() {
+        let files: [&[u8]; 3] = [b"abc", b"", b"the last child"];
+        let f = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(f.path(), cpt(&files, true)).unwrap();
+        let path = f.path().to_str().unwrap();
+
+        let children = super::load_cpt(path).unwrap();
+        super::save_cpt(&children.0[..], path).unwrap();
+        assert_eq!(super::load_cpt(path).unwrap().0, files);
 
-    Ok(files)
+        // an empty last child survives the round trip
+        let files = [b"abc".to_vec(), Vec::new()];
+        super::save_cpt(&files[..], path).unwrap();
+        let children = super::load_cpt(path).unwrap();
+        assert_eq!(children.0, files);
+        super::save_cpt(&children.0[..], path).unwrap();
+        assert_eq!(super::load_cpt(path).unwrap().0, files);
+    }
 }
@@ -0,0 +1,207 @@
+// Credits: https://www.chronocompendium.com/Term/Atim.html
+
+use png::{BitDepth, ColorType, Encoder, EncodingError};
+
+use std::{
+	fs::{self, File},
+	io::{self, BufWriter, Cursor, Read},
+};
+
+use crate::{color::rgba5551_to_rgba8888, Image};
+
+/// Altered TIM image import error
+#[derive(Debug)]
+pub enum ATIMErr {
+	BlockCount(usize),
+	BlockRead(io::Error),
+	ColorIndex(usize),
+	FileRead(io::Error),
+	FileWrite(EncodingError),
+	IndexCount(usize),
+	PathWrite,
+	PtrRead(io::Error),
+}
+
+/// Altered TIM image
+#[derive(Debug)]
+pub struct AlteredTIMImage {
+	clut: Vec<u16>,
+	idx: Vec<u8>,
+	w: u16,
+	h: u16,
+}
+
+impl AlteredTIMImage {
+	/// Image width in pixels
+	pub fn width(&self) -> u16 {
+		self.w
+	}
+
+	/// Image height in pixels
+	pub fn height(&self) -> u16 {
+		self.h
+	}
+
+	/// The RGBA5551 palette
+	pub fn palette(&self) -> &[u16] {
+		&self.clut[..]
+	}
+
+	/// The palette index of every pixel, one per byte
+	pub fn indices(&self) -> &[u8] {
+		&self.idx[..]
+	}
+
+	/// Expands the indices through the palette into RGBA8888 data
+	pub fn to_rgba(&self) -> Result<Vec<u8>, ATIMErr> {
+		let mut data = vec![0; self.idx.len() * 4];
+		for (i, px) in self.idx.iter().zip(data.chunks_exact_mut(4)) {
+			let c = *self
+				.clut
+				.get(*i as usize)
+				.ok_or(ATIMErr::ColorIndex(*i as usize))?;
+			let (r, g, b, a) = rgba5551_to_rgba8888(c as u32);
+			px.copy_from_slice(&[r, g, b, a]);
+		}
+
+		Ok(data)
+	}
+
+	/// Parses an altered TIM held in memory. The file starts with a block count and a table of
+	/// block offsets; the first block is the RGBA5551 palette and the second holds the image's
+	/// width and height followed by its indices, one byte per pixel, or packed two per byte (low
+	/// nibble first) when there is only room for half that.
+	pub fn from_bytes(buf: &[u8]) -> Result<AlteredTIMImage, ATIMErr> {
+		let mut c = Cursor::new(buf);
+
+		let n = read_u32(&mut c).map_err(|e| ATIMErr::PtrRead(e))? as usize;
+		if n < 2 {
+			return Err(ATIMErr::BlockCount(n));
+		}
+
+		let ptrs = (0..n)
+			.map(|_| read_u32(&mut c).map(|p| p as usize))
+			.collect::<Result<Vec<usize>, io::Error>>()
+			.map_err(|e| ATIMErr::PtrRead(e))?;
+
+		let block = |i: usize| -> Result<&[u8], ATIMErr> {
+			let end = ptrs.get(i + 1).copied().unwrap_or(buf.len());
+			buf.get(ptrs[i]..end)
+				.ok_or(ATIMErr::BlockRead(io::Error::from(
+					io::ErrorKind::UnexpectedEof,
+				)))
+		};
+
+		let clut = block(0)?
+			.chunks_exact(2)
+			.map(|c| u16::from_le_bytes([c[0], c[1]]))
+			.collect::<Vec<u16>>();
+
+		let mut c = Cursor::new(block(1)?);
+		let mut dims = [0; 4];
+		c.read_exact(&mut dims).map_err(|e| ATIMErr::BlockRead(e))?;
+		let w = u16::from_le_bytes([dims[0], dims[1]]);
+		let h = u16::from_le_bytes([dims[2], dims[3]]);
+
+		let npx = w as usize * h as usize;
+		let packed = &c.get_ref()[4..];
+		let idx = if packed.len() >= npx {
+			packed[..npx].to_vec()
+		} else if packed.len() * 2 >= npx {
+			packed
+				.iter()
+				.flat_map(|i| [i & 15, i >> 4])
+				.take(npx)
+				.collect::<Vec<u8>>()
+		} else {
+			return Err(ATIMErr::IndexCount(packed.len()));
+		};
+
+		Ok(AlteredTIMImage { clut, idx, w, h })
+	}
+}
+
+impl Image for AlteredTIMImage {
+	type ImageError = ATIMErr;
+
+	/// Loads an altered TIM file
+	fn load(path: &str) -> Result<AlteredTIMImage, ATIMErr> {
+		let buf = fs::read(path).map_err(|e| ATIMErr::FileRead(e))?;
+		AlteredTIMImage::from_bytes(&buf[..])
+	}
+
+	fn save_png(&self, path: &str) -> Result<(), ATIMErr> {
+		let data = self.to_rgba()?;
+
+		let file = File::create(path).map_err(|_| ATIMErr::PathWrite)?;
+		let ref mut w = BufWriter::new(file);
+		let mut enc = Encoder::new(w, self.w as u32, self.h as u32);
+
+		enc.set_color(ColorType::Rgba);
+		enc.set_depth(BitDepth::Eight);
+
+		enc.write_header()
+			.map_err(|e| ATIMErr::FileWrite(e))?
+			.write_image_data(&data[..])
+			.map_err(|e| ATIMErr::FileWrite(e))?;
+
+		Ok(())
+	}
+}
+
+/// Reads a little endian 32-bit integer
+fn read_u32(c: &mut Cursor<&[u8]>) -> io::Result<u32> {
+	let mut b = [0; 4];
+	c.read_exact(&mut b)?;
+	Ok(u32::from_le_bytes(b))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ATIMErr, AlteredTIMImage};
+	use crate::Image;
+
+	use std::io::Write;
+	use tempfile::NamedTempFile;
+
+	/// Writes out an altered TIM with the given palette, dimensions and index block
+	fn atim(clut: &[u16], w: u16, h: u16, idx: &[u8]) -> NamedTempFile {
+		let mut buf = Vec::new();
+		buf.extend_from_slice(&2u32.to_le_bytes());
+		buf.extend_from_slice(&12u32.to_le_bytes());
+		buf.extend_from_slice(&(12 + clut.len() as u32 * 2).to_le_bytes());
+		buf.extend(clut.iter().flat_map(|c| c.to_le_bytes()));
+		buf.extend_from_slice(&w.to_le_bytes());
+		buf.extend_from_slice(&h.to_le_bytes());
+		buf.extend_from_slice(idx);
+
+		let mut f = NamedTempFile::new().unwrap();
+		f.write_all(&buf[..]).unwrap();
+		f
+	}
+
+	#[test]
+	fn test_atim_load() {
+		let clut = [0x0000, 0x001F, 0x03E0, 0x7C00];
+
+		let f = atim(&clut, 2, 2, &[0, 1, 2, 3]);
+		let img = AlteredTIMImage::load(f.path().to_str().unwrap()).unwrap();
+		assert_eq!((img.width(), img.height()), (2, 2));
+		assert_eq!(img.palette(), clut);
+		assert_eq!(
+			img.to_rgba().unwrap(),
+			[0, 0, 0, 0, 255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255]
+		);
+
+		let packed = atim(&clut, 4, 1, &[0x10, 0x32]);
+		let img = AlteredTIMImage::load(packed.path().to_str().unwrap()).unwrap();
+		assert_eq!(img.indices(), [0, 1, 2, 3]);
+
+		let out = NamedTempFile::new().unwrap();
+		img.save_png(out.path().to_str().unwrap()).unwrap();
+
+		let bad = atim(&clut, 1, 1, &[9]);
+		let img = AlteredTIMImage::load(bad.path().to_str().unwrap()).unwrap();
+		assert!(matches!(img.to_rgba(), Err(ATIMErr::ColorIndex(9))));
+	}
+}
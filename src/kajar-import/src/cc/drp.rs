@@ -0,0 +1,605 @@
+// Credit: https://www.chronocompendium.com/Term/Drp.html
+
+use bytemuck::{bytes_of_mut, pod_read_unaligned, Zeroable};
+use bytemuck_derive::{Pod, Zeroable};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	fs,
+	io::{self, Cursor, Read},
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	cc::{
+		mesh::{parse_mesh, Mesh, MeshErr},
+		tim::{TIMErr, TIMImage},
+	},
+	encoding::decode_sjis,
+	fourcc, Archive, ArchiveEntry,
+};
+
+/// Deepest nesting of DRP files followed by `load_drp_recursive`
+const MAX_DEPTH: usize = 8;
+
+/// LZSS ring buffer size
+const LZSS_WINDOW: usize = 4096;
+
+/// Longest LZSS back-reference
+const LZSS_MAX_MATCH: usize = 18;
+
+/// Shortest LZSS back-reference
+const LZSS_MIN_MATCH: usize = 3;
+
+/// File header
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+struct Header {
+	sig: u32,
+	_4: u32,
+	n: u16,
+	_a: u16,
+}
+
+/// Subfile type
+#[derive(Clone, Copy, Debug)]
+#[repr(u8)]
+enum SubType {
+	DRP = 1,
+	GenericMesh,
+	TIMInfo,
+	TIM,
+	MInst,
+	Unknown07 = 7,
+	Unknown0A = 10,
+	MDL,
+	Unknown0C,
+	Unknown10 = 16,
+	BattlefieldMesh = 18,
+	LightTIMInfo = 21,
+	MSeq,
+	Anim = 25,
+	Unknown1A,
+	LZSS = 37,
+}
+
+impl TryFrom<u8> for SubType {
+	type Error = DRPErr;
+
+	fn try_from(kind: u8) -> Result<Self, Self::Error> {
+		match kind {
+			1 => Ok(SubType::DRP),
+			2 => Ok(SubType::GenericMesh),
+			3 => Ok(SubType::TIMInfo),
+			4 => Ok(SubType::TIM),
+			5 => Ok(SubType::MInst),
+			7 => Ok(SubType::Unknown07),
+			10 => Ok(SubType::Unknown0A),
+			11 => Ok(SubType::MDL),
+			12 => Ok(SubType::Unknown0C),
+			16 => Ok(SubType::Unknown10),
+			18 => Ok(SubType::BattlefieldMesh),
+			21 => Ok(SubType::LightTIMInfo),
+			22 => Ok(SubType::MSeq),
+			25 => Ok(SubType::Anim),
+			26 => Ok(SubType::Unknown1A),
+			37 => Ok(SubType::LZSS),
+			_ => Err(DRPErr::UnknownSubType(kind)),
+		}
+	}
+}
+
+/// Subfile header
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+struct SubHeader {
+	_0: u32,
+	name: u32,
+	kind: u8,
+	size: [u8; 3],
+}
+
+/// Placement and true dimensions of a TIM subfile, which its header doesn't always give
+/// correctly
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+pub struct TIMInfo {
+	pub x: u16,
+	pub y: u16,
+	pub w: u16,
+	pub h: u16,
+}
+
+/// DRP errors
+#[derive(Debug)]
+pub enum DRPErr {
+	FileRead(io::Error),
+	FileWrite(io::Error),
+	HeaderRead(io::Error),
+	Magic(u32),
+	PtrRead(io::Error),
+	ResRead(io::Error),
+	SubHeaderRead(io::Error),
+	TooDeep,
+	UnknownSubType(u8),
+}
+
+/// Extracted subfile
+#[derive(Debug)]
+pub struct DynRes {
+	name: String,
+	kind: SubType,
+	data: Vec<u8>,
+	info: Option<TIMInfo>,
+}
+
+impl DynRes {
+	/// The subfile's name
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// The TIMINFO of a TIMINFO subfile, or the one that came with a TIM subfile
+	pub fn as_timinfo(&self) -> Option<TIMInfo> {
+		match self.kind {
+			SubType::TIMInfo | SubType::LightTIMInfo => self
+				.data
+				.get(..std::mem::size_of::<TIMInfo>())
+				.map(pod_read_unaligned),
+			SubType::TIM => self.info,
+			_ => None,
+		}
+	}
+
+	/// Parses a mesh subfile, or `None` if the subfile isn't a mesh
+	pub fn as_mesh(&self) -> Option<Result<Mesh, MeshErr>> {
+		match self.kind {
+			SubType::GenericMesh | SubType::BattlefieldMesh => Some(parse_mesh(&self.data[..])),
+			_ => None,
+		}
+	}
+
+	/// Decodes a TIM subfile, cropped to the width given by its TIMINFO if it came with one
+	pub fn decode_tim(&self) -> Result<TIMImage, TIMErr> {
+		let mut img = TIMImage::from_bytes(&self.data[..])?;
+		if let Some(info) = self.info {
+			if info.w < img.width() {
+				img.crop_width(info.w)?;
+			}
+		}
+
+		Ok(img)
+	}
+
+	/// The subfile's data, decompressed if it is an LZSS subfile
+	pub fn decompressed(&self) -> Cow<'_, [u8]> {
+		match self.kind {
+			SubType::LZSS => Cow::Owned(decompress_lzss(&self.data[..])),
+			_ => Cow::Borrowed(&self.data[..]),
+		}
+	}
+
+	/// Dumps a file into the specified directory, named after the subfile
+	pub fn dump(&self, path: &str) -> Result<(), DRPErr> {
+		self.dump_with(path, false)
+	}
+
+	/// Dumps a file into the specified directory, optionally decompressing LZSS subfiles first
+	pub fn dump_with(&self, path: &str, decompress: bool) -> Result<(), DRPErr> {
+		let ext = match self.kind {
+			SubType::DRP => ".drp",
+			SubType::GenericMesh | SubType::BattlefieldMesh => ".mesh",
+			SubType::TIMInfo | SubType::LightTIMInfo => ".timinfo",
+			SubType::TIM => ".tim",
+			SubType::MInst => ".minst",
+			SubType::MDL => ".mdl",
+			SubType::MSeq => ".mseq",
+			SubType::Anim => ".anim",
+			SubType::LZSS if !decompress => ".lz",
+			_ => ".dat",
+		};
+
+		let mut out_path = PathBuf::from(path);
+		out_path.push(format!("{}{}", self.name, ext));
+
+		let data = if decompress {
+			self.decompressed()
+		} else {
+			Cow::Borrowed(&self.data[..])
+		};
+		fs::write(out_path, &data[..]).map_err(|e| DRPErr::FileWrite(e))?;
+
+		Ok(())
+	}
+}
+
+/// Decompresses an LZSS subfile.
+///
+/// The data starts with the decompressed size, followed by groups of a flag byte and 8 items. Flag
+/// bits are read LSB first: a set bit is a literal byte, a clear bit a 2-byte reference into a 4KB
+/// ring buffer holding a 12-bit position and a 4-bit length.
+pub fn decompress_lzss(data: &[u8]) -> Vec<u8> {
+	if data.len() < 4 {
+		return Vec::new();
+	}
+
+	let size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+	// each 2 byte match expands to at most LZSS_MAX_MATCH bytes
+	let mut out = Vec::with_capacity(size.min((data.len() - 4) / 2 * LZSS_MAX_MATCH));
+	let mut ring = [0; LZSS_WINDOW];
+	let mut r = LZSS_WINDOW - LZSS_MAX_MATCH;
+	let mut src = data[4..].iter().copied();
+
+	'groups: while out.len() < size {
+		let Some(flags) = src.next() else {
+			break;
+		};
+
+		for bit in 0..8 {
+			if out.len() >= size {
+				break 'groups;
+			}
+
+			if flags & (1 << bit) != 0 {
+				let Some(c) = src.next() else {
+					break 'groups;
+				};
+				out.push(c);
+				ring[r] = c;
+				r = (r + 1) % LZSS_WINDOW;
+			} else {
+				let (Some(lo), Some(hi)) = (src.next(), src.next()) else {
+					break 'groups;
+				};
+				let pos = lo as usize | ((hi as usize & 0xF0) << 4);
+				let len = (hi as usize & 0xF) + LZSS_MIN_MATCH;
+
+				for i in 0..len.min(size - out.len()) {
+					let c = ring[(pos + i) % LZSS_WINDOW];
+					out.push(c);
+					ring[r] = c;
+					r = (r + 1) % LZSS_WINDOW;
+				}
+			}
+		}
+	}
+
+	out
+}
+
+/// The subfiles of a DRP file, keyed by name
+#[derive(Debug, Default)]
+pub struct Drp(pub HashMap<String, DynRes>);
+
+/// Loads a DRP file, returning its subfiles
+pub fn load_drp(path: &str) -> Result<Drp, DRPErr> {
+	#[cfg(feature = "tracing")]
+	let _span = tracing::info_span!("load_drp", path).entered();
+
+	let data = fs::read(path).map_err(|e| DRPErr::FileRead(e))?;
+	from_bytes(&data[..])
+}
+
+/// Loads a DRP file, unpacking nested DRP subfiles into a single hashmap keyed by their paths
+/// within the file, e.g. `outer/inner`
+pub fn load_drp_recursive(path: &str) -> Result<HashMap<PathBuf, DynRes>, DRPErr> {
+	#[cfg(feature = "tracing")]
+	let _span = tracing::info_span!("load_drp_recursive", path).entered();
+
+	let data = fs::read(path).map_err(|e| DRPErr::FileRead(e))?;
+	from_bytes_recursive(&data[..])
+}
+
+/// Parses an in-memory DRP file, unpacking nested DRP subfiles as `load_drp_recursive` does
+pub fn from_bytes_recursive(data: &[u8]) -> Result<HashMap<PathBuf, DynRes>, DRPErr> {
+	let mut filemap = HashMap::new();
+	flatten_drp(data, Path::new(""), 0, &mut filemap)?;
+
+	Ok(filemap)
+}
+
+/// Adds the subfiles of an in-memory DRP file to `filemap`, descending into nested DRP files
+fn flatten_drp(
+	data: &[u8],
+	prefix: &Path,
+	depth: usize,
+	filemap: &mut HashMap<PathBuf, DynRes>,
+) -> Result<(), DRPErr> {
+	if depth > MAX_DEPTH {
+		return Err(DRPErr::TooDeep);
+	}
+
+	for (name, res) in from_bytes(data)?.0 {
+		let path = prefix.join(name);
+		if res.data.starts_with(b"drp\0") {
+			flatten_drp(&res.data[..], &path, depth + 1, filemap)?;
+		} else {
+			filemap.insert(path, res);
+		}
+	}
+
+	Ok(())
+}
+
+impl Archive for Drp {
+	/// Lists the subfiles, with their subfile type as their kind
+	fn entries(&self) -> Vec<ArchiveEntry> {
+		self.0
+			.values()
+			.map(|res| ArchiveEntry {
+				name: res.name.clone(),
+				size: res.data.len(),
+				kind: format!("{:?}", res.kind),
+			})
+			.collect()
+	}
+
+	/// Reads out a subfile's data, decompressed if it is an LZSS subfile
+	fn extract(&self, name: &str) -> Option<Vec<u8>> {
+		self.0.get(name).map(|res| res.decompressed().into_owned())
+	}
+}
+
+/// Parses the subfiles out of an in-memory DRP file, e.g. one extracted from resources.bin. Each
+/// TIM subfile is paired with the TIMINFO subfile before it, if any.
+pub fn from_bytes(data: &[u8]) -> Result<Drp, DRPErr> {
+	let mut buf = Cursor::new(data);
+
+	let mut hdr = Header::zeroed();
+	buf.read_exact(bytes_of_mut(&mut hdr))
+		.map_err(|e| DRPErr::HeaderRead(e))?;
+
+	if hdr.sig != fourcc(*b"drp\0") {
+		return Err(DRPErr::Magic(hdr.sig));
+	}
+
+	// the low 6 bits of the count field are flags
+	let n = (hdr.n >> 6) as usize;
+	let ptrs = (0..n)
+		.map(|_| {
+			let mut ptr = [0; 4];
+			buf.read_exact(&mut ptr).map_err(|e| DRPErr::PtrRead(e))?;
+			Ok(u32::from_le_bytes(ptr) as u64)
+		})
+		.collect::<Result<Vec<u64>, DRPErr>>()?;
+
+	let mut filemap = HashMap::new();
+	let mut info = None;
+	for ptr in ptrs {
+		buf.set_position(ptr);
+
+		let mut fh = SubHeader::zeroed();
+		buf.read_exact(bytes_of_mut(&mut fh))
+			.map_err(|e| DRPErr::SubHeaderRead(e))?;
+
+		let kind = SubType::try_from(fh.kind)?;
+
+		// names are up to 4 characters, stored in file order and padded with nulls
+		let name = fh.name.to_le_bytes();
+		let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+		let name = decode_sjis(&name[..len]);
+
+		let size = (u32::from_le_bytes([fh.size[0], fh.size[1], fh.size[2], 0]) as usize) >> 4;
+		let mut data = vec![0; size];
+		buf.read_exact(&mut data[..])
+			.map_err(|e| DRPErr::ResRead(e))?;
+
+		#[cfg(feature = "tracing")]
+		tracing::debug!(name = %name, ?kind, size, "read subfile");
+
+		let mut res = DynRes {
+			name,
+			kind,
+			data,
+			info: None,
+		};
+		match kind {
+			SubType::TIMInfo | SubType::LightTIMInfo => info = res.as_timinfo(),
+			SubType::TIM => res.info = info.take(),
+			_ => (),
+		}
+
+		filemap.insert(res.name.clone(), res);
+	}
+
+	Ok(Drp(filemap))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		decompress_lzss, flatten_drp, from_bytes, from_bytes_recursive, DRPErr, SubType, TIMInfo,
+		MAX_DEPTH,
+	};
+	use crate::{cc::tim::TIMImage, Archive};
+
+	use bytemuck::bytes_of;
+
+	use std::{collections::HashMap, fs, path::Path};
+
+	/// Builds a DRP file from (name, kind, data) subfiles
+	fn drp(files: &[(&[u8; 4], u8, &[u8])]) -> Vec<u8> {
+		let mut buf = b"drp\0".to_vec();
+		buf.extend_from_slice(&0u32.to_le_bytes());
+		buf.extend_from_slice(&((files.len() as u16) << 6).to_le_bytes());
+		buf.extend_from_slice(&0u16.to_le_bytes());
+
+		let mut ptr = buf.len() + files.len() * 4;
+		for (_, _, data) in files {
+			buf.extend_from_slice(&(ptr as u32).to_le_bytes());
+			ptr += 12 + data.len();
+		}
+
+		for (name, kind, data) in files {
+			buf.extend_from_slice(&0u32.to_le_bytes());
+			buf.extend_from_slice(&name[..]);
+			buf.push(*kind);
+			buf.extend_from_slice(&((data.len() as u32) << 4).to_le_bytes()[..3]);
+			buf.extend_from_slice(data);
+		}
+
+		buf
+	}
+
+	#[test]
+	fn test_drp_archive() {
+		let files = from_bytes(
+			&drp(&[
+				(b"0tex", 4, &[0x10, 0, 0, 0][..]),
+				(b"mdl\0", 11, &[1, 2, 3][..]),
+			])[..],
+		)
+		.unwrap();
+
+		let mut entries = files.entries();
+		entries.sort_by(|a, b| a.name.cmp(&b.name));
+		assert_eq!(entries.len(), 2);
+		assert_eq!(entries[1].name, "mdl");
+		assert_eq!((entries[1].size, &entries[1].kind[..]), (3, "MDL"));
+
+		assert_eq!(files.extract("mdl").unwrap(), [1, 2, 3]);
+		assert!(files.extract("none").is_none());
+	}
+
+	#[test]
+	fn test_load_drp() {
+		let tim = [0x10, 0, 0, 0, 2, 0, 0, 0];
+		let files = from_bytes(&drp(&[(b"0tex", 4, &tim[..])])[..]).unwrap().0;
+		assert_eq!(files.len(), 1);
+
+		let res = &files["0tex"];
+		assert_eq!(res.name(), "0tex");
+		assert!(matches!(res.kind, SubType::TIM));
+		assert_eq!(res.data, tim);
+		assert!(res.as_mesh().is_none());
+
+		assert!(matches!(
+			from_bytes(&drp(&[(b"0bad", 0x7F, &[])])[..]),
+			Err(DRPErr::UnknownSubType(0x7F))
+		));
+	}
+
+	#[test]
+	fn test_drp_tim_from_bytes() {
+		// a 1x1 16bpp TIM holding a single red pixel
+		let mut tim = Vec::new();
+		for word in [16u32, 2, 14, 0] {
+			tim.extend_from_slice(&word.to_le_bytes());
+		}
+		tim.extend_from_slice(&[1, 0, 1, 0, 0x1F, 0]);
+
+		let files = from_bytes(&drp(&[(b"0tex", 4, &tim[..])])[..]).unwrap().0;
+		let img = TIMImage::from_bytes(&files["0tex"].data[..]).unwrap();
+		assert_eq!((img.width(), img.height()), (1, 1));
+		assert_eq!(img.rgba_bytes(), [255, 0, 0, 255]);
+	}
+
+	#[test]
+	fn test_drp_timinfo() {
+		// a 4bpp TIM 2 VRAM units (8 pixels) wide, holding a 5 pixel wide sprite
+		let mut tim = Vec::new();
+		for word in [16u32, 8, 12 + 32] {
+			tim.extend_from_slice(&word.to_le_bytes());
+		}
+		tim.extend_from_slice(&[0, 0, 0, 0, 16, 0, 1, 0]);
+		tim.extend((0..16u16).flat_map(|i| (i << 10 | i << 5 | i).to_le_bytes()));
+		tim.extend_from_slice(&(12u32 + 4).to_le_bytes());
+		tim.extend_from_slice(&[0, 0, 0, 0, 2, 0, 1, 0, 0x10, 0x32, 0x54, 0x76]);
+
+		let info = TIMInfo {
+			x: 320,
+			y: 256,
+			w: 5,
+			h: 1,
+		};
+		let files = from_bytes(
+			&drp(&[
+				(b"info", 3, bytes_of(&info)),
+				(b"0tex", 4, &tim[..]),
+				(b"1tex", 4, &tim[..]),
+			])[..],
+		)
+		.unwrap()
+		.0;
+
+		assert_eq!(files["info"].as_timinfo(), Some(info));
+		assert_eq!(files["0tex"].as_timinfo(), Some(info));
+		assert_eq!(files["0tex"].decode_tim().unwrap().width(), 5);
+
+		// the TIMINFO only goes with the TIM straight after it
+		assert_eq!(files["1tex"].as_timinfo(), None);
+		assert_eq!(files["1tex"].decode_tim().unwrap().width(), 8);
+	}
+
+	#[test]
+	fn test_drp_dump() {
+		let files = from_bytes(&drp(&[(b"tex0", 4, &[1, 2][..]), (b"tx1\0", 4, &[3][..])])[..])
+			.unwrap()
+			.0;
+
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().to_str().unwrap();
+		for res in files.values() {
+			res.dump(path).unwrap();
+		}
+
+		assert_eq!(fs::read(dir.path().join("tex0.tim")).unwrap(), [1, 2]);
+		assert_eq!(fs::read(dir.path().join("tx1.tim")).unwrap(), [3]);
+		assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+	}
+
+	#[test]
+	fn test_drp_recursive() {
+		let inner = drp(&[(b"tex0", 4, &[1][..]), (b"mdl0", 11, &[2][..])]);
+		let outer = drp(&[(b"btl\0", 1, &inner[..]), (b"tex0", 4, &[3][..])]);
+
+		let mut files = HashMap::new();
+		flatten_drp(&outer[..], Path::new(""), 0, &mut files).unwrap();
+		assert_eq!(files.len(), 3);
+		assert_eq!(files[Path::new("btl/tex0")].data, [1]);
+		assert_eq!(files[Path::new("btl/mdl0")].data, [2]);
+		assert_eq!(files[Path::new("tex0")].data, [3]);
+		assert_eq!(from_bytes_recursive(&outer[..]).unwrap().len(), 3);
+
+		let mut nested = drp(&[(b"tex0", 4, &[1][..])]);
+		for _ in 0..=MAX_DEPTH {
+			nested = drp(&[(b"drp\0", 1, &nested[..])]);
+		}
+		assert!(matches!(
+			flatten_drp(&nested[..], Path::new(""), 0, &mut HashMap::new()),
+			Err(DRPErr::TooDeep)
+		));
+	}
+
+	#[test]
+	fn test_lzss() {
+		// 3 literals, then a 9-byte reference back to the start of the window
+		let lz = [12, 0, 0, 0, 0x07, b'A', b'B', b'C', 0xEE, 0xF6];
+		assert_eq!(decompress_lzss(&lz), b"ABCABCABCABC");
+
+		// references into the untouched window read zeroes
+		let lz = [4, 0, 0, 0, 0x01, b'X', 0x00, 0x00];
+		assert_eq!(decompress_lzss(&lz), b"X\0\0\0");
+
+		// a corrupt size doesn't reserve more than the data could expand to
+		let out = decompress_lzss(&[0xFF, 0xFF, 0xFF, 0xFF, 0x01, b'X']);
+		assert_eq!(out, b"X");
+		assert!(out.capacity() <= 18);
+
+		let files = from_bytes(&drp(&[(b"pack", 37, &lz[..])])[..]).unwrap().0;
+		assert_eq!(&files["pack"].decompressed()[..], b"X\0\0\0");
+
+		let dir = tempfile::tempdir().unwrap();
+		files["pack"]
+			.dump_with(dir.path().to_str().unwrap(), true)
+			.unwrap();
+		assert_eq!(fs::read(dir.path().join("pack.dat")).unwrap(), b"X\0\0\0");
+	}
+}
@@ -0,0 +1,203 @@
+// Mesh subfiles (GenericMesh and BattlefieldMesh) in DRP files.
+//
+// EXPERIMENTAL: there is no published spec for this format, and the layout below is inferred
+// rather than checked against the game's renderer. It is enough to recover geometry: a count
+// header, then the vertices as PSX SVECTORs, then textured triangles and quads. Face UVs are
+// texel coordinates into the TIM that accompanies the mesh. The order of the face fields is the
+// least certain part; triangles keep the texture page in the word after their 3 indices, while
+// quads have no spare word there and keep it after their UVs.
+
+use bytemuck::{bytes_of_mut, Pod, Zeroable};
+use bytemuck_derive::{Pod, Zeroable};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::io::{self, Cursor, Read};
+
+/// Mesh header
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+struct Header {
+	n_verts: u16,
+	n_tris: u16,
+	n_quads: u16,
+	_6: u16,
+}
+
+/// Mesh vertex
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+pub struct Vertex {
+	pub x: i16,
+	pub y: i16,
+	pub z: i16,
+	_6: i16,
+}
+
+/// Textured triangle
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+pub struct Tri {
+	/// Vertex indices
+	pub idx: [u16; 3],
+
+	/// Texture page in VRAM
+	pub tpage: u16,
+
+	/// Texel coordinates in the paired TIM, one per vertex
+	pub uv: [[u8; 2]; 3],
+
+	/// Palette row in the paired TIM
+	pub clut: u16,
+}
+
+/// Textured quad
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+pub struct Quad {
+	/// Vertex indices
+	pub idx: [u16; 4],
+
+	/// Texel coordinates in the paired TIM, one per vertex
+	pub uv: [[u8; 2]; 4],
+
+	/// Texture page in VRAM
+	pub tpage: u16,
+
+	/// Palette row in the paired TIM
+	pub clut: u16,
+}
+
+/// Mesh errors
+#[derive(Debug)]
+pub enum MeshErr {
+	FaceRead(io::Error),
+	HeaderRead(io::Error),
+
+	/// A face refers to a vertex past the end of the list; the face's position among all
+	/// faces, triangles first, is given
+	VertexIndex(usize),
+	VertexRead(io::Error),
+}
+
+/// Geometry of a mesh subfile
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+	pub vertices: Vec<Vertex>,
+	pub tris: Vec<Tri>,
+	pub quads: Vec<Quad>,
+}
+
+/// Parses a mesh subfile. Experimental: the layout is inferred, see the module notes
+pub fn parse_mesh(data: &[u8]) -> Result<Mesh, MeshErr> {
+	let mut c = Cursor::new(data);
+
+	let mut header = Header::zeroed();
+	c.read_exact(bytes_of_mut(&mut header))
+		.map_err(|e| MeshErr::HeaderRead(e))?;
+
+	let vertices = read_n::<Vertex>(&mut c, header.n_verts).map_err(|e| MeshErr::VertexRead(e))?;
+	let tris = read_n::<Tri>(&mut c, header.n_tris).map_err(|e| MeshErr::FaceRead(e))?;
+	let quads = read_n::<Quad>(&mut c, header.n_quads).map_err(|e| MeshErr::FaceRead(e))?;
+
+	let faces = tris
+		.iter()
+		.map(|t| &t.idx[..])
+		.chain(quads.iter().map(|q| &q.idx[..]));
+	for (i, idx) in faces.enumerate() {
+		if idx.iter().any(|v| *v >= header.n_verts) {
+			return Err(MeshErr::VertexIndex(i));
+		}
+	}
+
+	Ok(Mesh {
+		vertices,
+		tris,
+		quads,
+	})
+}
+
+/// Reads `n` records of a fixed-size type
+fn read_n<T: Pod>(c: &mut Cursor<&[u8]>, n: u16) -> io::Result<Vec<T>> {
+	let mut recs = vec![T::zeroed(); n as usize];
+	for r in recs.iter_mut() {
+		c.read_exact(bytes_of_mut(r))?;
+	}
+
+	Ok(recs)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_mesh, MeshErr, Vertex};
+
+	/// A square floor split into a triangle and a quad
+	fn floor() -> Vec<u8> {
+		let mut buf = Vec::new();
+		for n in [4u16, 1, 1, 0] {
+			buf.extend_from_slice(&n.to_le_bytes());
+		}
+
+		for (x, z) in [(-64i16, -64i16), (64, -64), (64, 64), (-64, 64)] {
+			for c in [x, 0, z, 0] {
+				buf.extend_from_slice(&c.to_le_bytes());
+			}
+		}
+
+		// triangle
+		for i in [0u16, 1, 2, 0x10] {
+			buf.extend_from_slice(&i.to_le_bytes());
+		}
+		buf.extend_from_slice(&[0, 0, 63, 0, 63, 63]);
+		buf.extend_from_slice(&0x7C00u16.to_le_bytes());
+
+		// quad
+		for i in [0u16, 1, 2, 3] {
+			buf.extend_from_slice(&i.to_le_bytes());
+		}
+		buf.extend_from_slice(&[0, 0, 63, 0, 63, 63, 0, 63]);
+		buf.extend_from_slice(&0x10u16.to_le_bytes());
+		buf.extend_from_slice(&0x7C00u16.to_le_bytes());
+
+		buf
+	}
+
+	#[test]
+	fn test_parse_mesh() {
+		let buf = floor();
+		let mesh = parse_mesh(&buf[..]).unwrap();
+
+		assert_eq!(mesh.vertices.len(), 4);
+		assert_eq!(
+			(mesh.vertices[2].x, mesh.vertices[2].y, mesh.vertices[2].z),
+			(64, 0, 64)
+		);
+		assert_eq!(mesh.tris.len(), 1);
+		assert_eq!(mesh.tris[0].idx, [0, 1, 2]);
+		assert_eq!(mesh.tris[0].uv[2], [63, 63]);
+		assert_eq!((mesh.tris[0].tpage, mesh.tris[0].clut), (0x10, 0x7C00));
+		assert_eq!(mesh.quads.len(), 1);
+		assert_eq!(mesh.quads[0].idx, [0, 1, 2, 3]);
+		assert_eq!(mesh.quads[0].uv[3], [0, 63]);
+		assert_eq!(std::mem::size_of::<Vertex>(), 8);
+
+		let mut bad = buf.clone();
+		bad[8 + 4 * 8 + 16 + 6..][..2].copy_from_slice(&4u16.to_le_bytes());
+		assert!(matches!(parse_mesh(&bad[..]), Err(MeshErr::VertexIndex(1))));
+
+		assert!(matches!(
+			parse_mesh(&buf[..buf.len() - 1]),
+			Err(MeshErr::FaceRead(_))
+		));
+		assert!(matches!(
+			parse_mesh(&buf[..12]),
+			Err(MeshErr::VertexRead(_))
+		));
+		assert!(matches!(parse_mesh(&buf[..4]), Err(MeshErr::HeaderRead(_))));
+	}
+}
@@ -0,0 +1,1166 @@
+// Credit: https://www.chronocompendium.com/Term/Tim.html
+
+use bitflags::bitflags;
+use bytemuck::{bytes_of, bytes_of_mut, Zeroable};
+use bytemuck_derive::{Pod, Zeroable};
+use png::{BitDepth, ColorType, Encoder, EncodingError};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+pub use crate::color::{rgba5551_to_rgba8888, rgba8888_to_rgba5551};
+use crate::Image;
+
+bitflags! {
+    /// Image attributes
+    #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[repr(C)]
+    struct Flags: u32 {
+        const BPP_4 = 0;
+        const BPP_8 = 1;
+        const BPP_16 = 2;
+        const BPP_24 = 3;
+        const MIXED = 4;
+        const INDEXED = 8;
+
+        /// CLUT entries are RGB888 rather than RGBA5551
+        const CLUT_24 = 16;
+    }
+}
+
+/// Indexed TIM file header
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+struct IndexedHeader {
+    magic: u32,
+    flags: Flags,
+    clut_size: u32,
+    _palx: u16,
+    _paly: u16,
+    ncolors: u16,
+    ncluts: u16,
+}
+
+/// Indexed image header
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+struct IndexedImageHeader {
+    size: u32,
+    addr_x: u16,
+    addr_y: u16,
+    w: u16,
+    h: u16,
+}
+
+/// Non-indexed TIM file header
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+struct NonIndexedHeader {
+    magic: u32,
+    flags: Flags,
+    size: u32,
+    addr_x: u16,
+    addr_y: u16,
+    w: u16,
+    h: u16,
+}
+
+/// TIM file header
+#[derive(Debug)]
+enum Header {
+    Indexed(IndexedHeader, IndexedImageHeader),
+    NonIndexed(NonIndexedHeader),
+}
+
+/// TIM image import error
+#[derive(Debug)]
+pub enum TIMErr {
+    BitsPerPixel(u32),
+    ClutFormat(u32),
+    ClutRead(io::Error),
+    ColorCount(usize),
+    DataSize(usize),
+    DirCreate(io::Error),
+    DirRead(io::Error),
+    FileRead(io::Error),
+    FileWrite(EncodingError),
+    FlagsInvalid,
+    HeaderRead(io::Error),
+    ImageHeaderRead(io::Error),
+    IndexRead(io::Error),
+    Magic(u32),
+    PaletteIndex(usize),
+    PathWrite,
+    PixelRead(io::Error),
+    TIMWrite(io::Error),
+    Width(u16),
+}
+
+/// Colour lookup table, kept in the entry width it was stored with
+#[derive(Clone, Debug)]
+pub enum Clut {
+    Rgba5551(Vec<u16>),
+    Rgb888(Vec<[u8; 3]>),
+}
+
+impl Clut {
+    /// Reads the CLUT entries, with the entry width given by the header flags, and leaves the
+    /// cursor at the end of the CLUT block
+    fn read(c: &mut Cursor<&[u8]>, header: &IndexedHeader) -> Result<Clut, TIMErr> {
+        let n = header.ncolors as u64 * header.ncluts as u64;
+        let rgb888 = header.flags.contains(Flags::CLUT_24);
+        let width = if rgb888 { 3 } else { 2 };
+
+        // The CLUT block size includes its own 12 byte header, and starts after the magic and
+        // flags
+        let end = 8 + header.clut_size as u64;
+        if (header.clut_size as u64).saturating_sub(12) < n * width as u64 {
+            return Err(TIMErr::ClutFormat(width));
+        }
+        if end > c.get_ref().len() as u64 {
+            return Err(TIMErr::ClutRead(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        }
+
+        let clut = if rgb888 {
+            let mut clut = Vec::with_capacity(n as usize);
+            for _ in 0..n {
+                let mut e = [0; 3];
+                c.read_exact(&mut e).map_err(|e| TIMErr::ClutRead(e))?;
+                clut.push(e);
+            }
+            Clut::Rgb888(clut)
+        } else {
+            let mut clut = Vec::with_capacity(n as usize);
+            for _ in 0..n {
+                let mut e = [0; 2];
+                c.read_exact(&mut e).map_err(|e| TIMErr::ClutRead(e))?;
+                clut.push(u16::from_le_bytes(e));
+            }
+            Clut::Rgba5551(clut)
+        };
+
+        c.set_position(end);
+        Ok(clut)
+    }
+
+    /// Number of entries across all palettes
+    pub fn len(&self) -> usize {
+        match self {
+            Clut::Rgba5551(clut) => clut.len(),
+            Clut::Rgb888(clut) => clut.len(),
+        }
+    }
+
+    /// Whether the table has no entries
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Entry `i` as RGBA8888
+    fn rgba(&self, i: usize) -> Result<[u8; 4], TIMErr> {
+        match self {
+            Clut::Rgba5551(clut) => {
+                let c = clut.get(i).ok_or(TIMErr::PaletteIndex(i))?;
+                let (r, g, b, a) = rgba5551_to_rgba8888(*c as u32);
+                Ok([r, g, b, a])
+            }
+            Clut::Rgb888(clut) => {
+                let [r, g, b] = clut.get(i).ok_or(TIMErr::PaletteIndex(i))?;
+                Ok([*r, *g, *b, 255])
+            }
+        }
+    }
+}
+
+#[cfg(feature = "image-interop")]
+impl TryFrom<&TIMImage> for image::RgbaImage {
+    type Error = TIMErr;
+
+    /// Copies the decoded RGBA8 pixels into an `image` buffer
+    fn try_from(img: &TIMImage) -> Result<Self, Self::Error> {
+        image::RgbaImage::from_raw(img.w as u32, img.h as u32, img.data.clone())
+            .ok_or(TIMErr::DataSize(img.data.len()))
+    }
+}
+
+/// Area of an image in pixels
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub w: u16,
+    pub h: u16,
+}
+
+/// Outcome of a `convert_dir` batch
+#[derive(Debug, Default)]
+pub struct ConvertReport {
+    pub converted: usize,
+    pub failed: Vec<(PathBuf, TIMErr)>,
+}
+
+/// TIM image
+#[derive(Debug)]
+pub struct TIMImage {
+    header: Header,
+    data: Vec<u8>,
+    clut: Option<Clut>,
+    idx: Vec<u8>,
+    palette: usize,
+    bpp: u32,
+    vram_w: u16,
+    w: u16,
+    h: u16,
+}
+
+impl TIMImage {
+    /// Loads a TIM file, expanding indexed pixels through CLUT number `palette`
+    pub fn load_with_palette(path: &str, palette: usize) -> Result<TIMImage, TIMErr> {
+        let data = fs::read(path).map_err(|e| TIMErr::FileRead(e))?;
+        TIMImage::from_bytes_with_palette(&data[..], palette)
+    }
+
+    /// Parses a TIM image held in memory, e.g. a DRP subfile
+    pub fn from_bytes(data: &[u8]) -> Result<TIMImage, TIMErr> {
+        TIMImage::from_bytes_with_palette(data, 0)
+    }
+
+    /// Parses a TIM image held in memory, expanding indexed pixels through CLUT number `palette`
+    pub fn from_bytes_with_palette(data: &[u8], palette: usize) -> Result<TIMImage, TIMErr> {
+        let mut c = Cursor::new(data);
+
+        let mut read_u32 = || -> Result<u32, TIMErr> {
+            let mut b = [0; 4];
+            c.read_exact(&mut b).map_err(|e| TIMErr::HeaderRead(e))?;
+            Ok(u32::from_le_bytes(b))
+        };
+
+        let magic = read_u32()?;
+        if magic != 16 {
+            return Err(TIMErr::Magic(magic));
+        }
+
+        let flags = read_u32()?;
+        let flags = Flags::from_bits(flags).ok_or(TIMErr::FlagsInvalid)?;
+        let bpp = match flags.bits() & 3 {
+            0 => 4,
+            1 => 8,
+            2 => 16,
+            _ => 24,
+        };
+
+        c.set_position(0);
+        if flags.contains(Flags::INDEXED) {
+            let mut header = IndexedHeader::zeroed();
+            c.read_exact(bytes_of_mut(&mut header))
+                .map_err(|e| TIMErr::HeaderRead(e))?;
+
+            if palette >= header.ncluts as usize {
+                return Err(TIMErr::PaletteIndex(palette));
+            }
+
+            let clut = Clut::read(&mut c, &header)?;
+
+            let mut imgh = IndexedImageHeader::zeroed();
+            c.read_exact(bytes_of_mut(&mut imgh))
+                .map_err(|e| TIMErr::ImageHeaderRead(e))?;
+            // the image width is given in 16-bit VRAM units
+            let w = match bpp {
+                4 => imgh.w << 2,
+                8 => imgh.w << 1,
+                _ => return Err(TIMErr::BitsPerPixel(bpp)),
+            };
+
+            let mut idx = match bpp {
+                4 => vec![0; w as usize * imgh.h as usize / 2],
+                8 => vec![0; w as usize * imgh.h as usize],
+                _ => return Err(TIMErr::BitsPerPixel(bpp)),
+            };
+
+            c.read_exact(&mut idx[..])
+                .map_err(|e| TIMErr::IndexRead(e))?;
+
+            let base = palette * header.ncolors as usize;
+            let data = expand_indexed(&clut, base, &idx[..], bpp)?;
+
+            Ok(TIMImage {
+                header: Header::Indexed(header, imgh),
+                data,
+                clut: Some(clut),
+                idx,
+                palette,
+                bpp,
+                vram_w: imgh.w,
+                w,
+                h: imgh.h,
+            })
+        } else {
+            let mut header = NonIndexedHeader::zeroed();
+            c.read_exact(bytes_of_mut(&mut header))
+                .map_err(|e| TIMErr::HeaderRead(e))?;
+
+            let (w, data) = match bpp {
+                16 => {
+                    let npixels = header.w as usize * header.h as usize;
+                    let mut raw = vec![0; npixels * 2];
+                    c.read_exact(&mut raw[..])
+                        .map_err(|e| TIMErr::PixelRead(e))?;
+
+                    let mut data = vec![0; npixels * 4];
+                    for (px, raw) in data.chunks_exact_mut(4).zip(raw.chunks_exact(2)) {
+                        put_pixel(px, u16::from_le_bytes([raw[0], raw[1]]) as u32);
+                    }
+                    (header.w, data)
+                }
+                24 => {
+                    // rows are stored in 16-bit units, with 2 pixels to every 3 units
+                    let w = (header.w as u32 * 2 / 3) as u16;
+                    let mut row = vec![0; header.w as usize * 2];
+                    let mut data = Vec::with_capacity(w as usize * header.h as usize * 4);
+                    for _ in 0..header.h {
+                        c.read_exact(&mut row[..])
+                            .map_err(|e| TIMErr::PixelRead(e))?;
+                        for px in row.chunks_exact(3).take(w as usize) {
+                            data.extend_from_slice(&[px[0], px[1], px[2], 255]);
+                        }
+                    }
+                    (w, data)
+                }
+                _ => return Err(TIMErr::BitsPerPixel(bpp)),
+            };
+
+            Ok(TIMImage {
+                header: Header::NonIndexed(header),
+                data,
+                clut: None,
+                idx: Vec::new(),
+                palette: 0,
+                bpp,
+                vram_w: header.w,
+                w,
+                h: header.h,
+            })
+        }
+    }
+
+    /// Width in pixels
+    pub fn width(&self) -> u16 {
+        self.w
+    }
+
+    /// Width of the stored rows in 16-bit VRAM units
+    pub fn vram_width(&self) -> u16 {
+        self.vram_w
+    }
+
+    /// Width in pixels of the stored rows, before any cropping
+    fn stored_width(&self) -> u16 {
+        match self.bpp {
+            4 => self.vram_w << 2,
+            8 => self.vram_w << 1,
+            24 => (self.vram_w as u32 * 2 / 3) as u16,
+            _ => self.vram_w,
+        }
+    }
+
+    /// Crops every row to `w` pixels. Rows are stored in whole VRAM units, so images whose true
+    /// width (e.g. from the accompanying TIMINFO) isn't a multiple of the unit carry extra
+    /// columns on the right.
+    pub fn crop_width(&mut self, w: u16) -> Result<(), TIMErr> {
+        if w > self.w {
+            return Err(TIMErr::Width(w));
+        }
+
+        self.data = crop_rows(&self.data[..], self.w, w);
+        self.w = w;
+
+        Ok(())
+    }
+
+    /// Height in pixels
+    pub fn height(&self) -> u16 {
+        self.h
+    }
+
+    /// Cuts the image into sprites, one per rectangle, e.g. from the TIMINFO of a sprite sheet.
+    /// Rectangles are clipped to the image. Each sprite keeps the CLUT and bit depth, and is
+    /// placed in VRAM at its offset within the page.
+    pub fn split_sprites(&self, rects: &[Rect]) -> Vec<TIMImage> {
+        rects.iter().map(|r| self.sprite(r)).collect()
+    }
+
+    /// Crops out a single sprite for `split_sprites`
+    fn sprite(&self, r: &Rect) -> TIMImage {
+        let x = r.x.min(self.w);
+        let y = r.y.min(self.h);
+        let w = r.w.min(self.w - x);
+        let h = r.h.min(self.h - y);
+
+        let data = self
+            .data
+            .chunks_exact(self.w as usize * 4)
+            .skip(y as usize)
+            .take(h as usize)
+            .flat_map(|row| &row[(x as usize * 4)..((x + w) as usize * 4)])
+            .copied()
+            .collect();
+
+        // rows take up whole 16-bit VRAM units
+        let vram_x = (x as u32 * self.bpp / 16) as u16;
+        let vram_w = (w as u32 * self.bpp).div_ceil(16) as u16;
+        let header = match self.header {
+            Header::Indexed(header, imgh) => Header::Indexed(
+                header,
+                IndexedImageHeader {
+                    addr_x: imgh.addr_x + vram_x,
+                    addr_y: imgh.addr_y + y,
+                    w: vram_w,
+                    h,
+                    ..imgh
+                },
+            ),
+            Header::NonIndexed(header) => Header::NonIndexed(NonIndexedHeader {
+                addr_x: header.addr_x + vram_x,
+                addr_y: header.addr_y + y,
+                w: vram_w,
+                h,
+                ..header
+            }),
+        };
+
+        TIMImage {
+            header,
+            data,
+            clut: self.clut.clone(),
+            idx: self.crop_indices(x, y, w, h, vram_w),
+            palette: self.palette,
+            bpp: self.bpp,
+            vram_w,
+            w,
+            h,
+        }
+    }
+
+    /// Crops the packed indices of an indexed image, with each row padded out to `vram_w` units
+    fn crop_indices(&self, x: u16, y: u16, w: u16, h: u16, vram_w: u16) -> Vec<u8> {
+        let stride = self.stored_width() as usize;
+        let px = match self.bpp {
+            4 => self
+                .idx
+                .iter()
+                .flat_map(|i| [i & 15, i >> 4])
+                .collect::<Vec<u8>>(),
+            8 => self.idx.clone(),
+            _ => return Vec::new(),
+        };
+
+        let row_len = vram_w as usize * 16 / self.bpp as usize;
+        let mut idx = Vec::with_capacity(row_len * h as usize);
+        for row in px.chunks_exact(stride).skip(y as usize).take(h as usize) {
+            let start = idx.len();
+            idx.extend_from_slice(&row[x as usize..(x + w) as usize]);
+            idx.resize(start + row_len, 0);
+        }
+
+        match self.bpp {
+            4 => idx.chunks_exact(2).map(|p| p[0] | p[1] << 4).collect(),
+            _ => idx,
+        }
+    }
+
+    /// Bits per pixel as stored in the file
+    pub fn bpp(&self) -> u32 {
+        self.bpp
+    }
+
+    /// Decoded RGBA8888 pixel data, row by row
+    pub fn rgba_bytes(&self) -> &[u8] {
+        &self.data[..]
+    }
+
+    /// Takes the decoded RGBA8888 pixel data
+    pub fn into_rgba(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Number of palettes in the CLUT, or 0 if the image isn't indexed
+    pub fn num_palettes(&self) -> usize {
+        match self.header {
+            Header::Indexed(header, _) => header.ncluts as usize,
+            Header::NonIndexed(_) => 0,
+        }
+    }
+
+    /// The palette the pixel data was expanded with
+    pub fn palette_index(&self) -> usize {
+        self.palette
+    }
+
+    /// The colour lookup table, if the image is indexed
+    pub fn clut(&self) -> Option<&Clut> {
+        self.clut.as_ref()
+    }
+
+    /// The original RGBA5551 colour lookup table, if the image is indexed with 16-bit entries
+    pub fn palette(&self) -> Option<&[u16]> {
+        match self.clut.as_ref() {
+            Some(Clut::Rgba5551(clut)) => Some(&clut[..]),
+            _ => None,
+        }
+    }
+
+    /// Overwrites CLUT slots with new RGBA5551 colours and re-expands the RGBA data. The index
+    /// map is left untouched, so the same swap can be re-applied. 24-bit CLUTs take the colour
+    /// widened to RGB888. Slots outside the palette, or any mapping on a non-indexed image, are
+    /// ignored.
+    pub fn remap_palette(&mut self, mapping: &[(usize, u16)]) {
+        let Some(clut) = self.clut.as_mut() else {
+            return;
+        };
+
+        for (slot, colour) in mapping.iter() {
+            match clut {
+                Clut::Rgba5551(clut) => {
+                    if let Some(c) = clut.get_mut(*slot) {
+                        *c = *colour;
+                    }
+                }
+                Clut::Rgb888(clut) => {
+                    if let Some(c) = clut.get_mut(*slot) {
+                        let (r, g, b, _) = rgba5551_to_rgba8888(*colour as u32);
+                        *c = [r, g, b];
+                    }
+                }
+            }
+        }
+
+        let base = match self.header {
+            Header::Indexed(header, _) => self.palette * header.ncolors as usize,
+            Header::NonIndexed(_) => 0,
+        };
+        if let Ok(data) = expand_indexed(clut, base, &self.idx[..], self.bpp) {
+            self.data = crop_rows(&data[..], self.stored_width(), self.w);
+        }
+    }
+
+    /// Re-encodes the RGBA data as a TIM file at the image's bit depth. Indexed images get a
+    /// fresh RGBA5551 CLUT built from the colours in use, which must fit in 16 or 256 entries.
+    pub fn save_tim(&self, path: &str) -> Result<(), TIMErr> {
+        let mut buf = Vec::new();
+        let (w, h) = (self.w as usize, self.h as usize);
+
+        match self.bpp {
+            4 | 8 => {
+                let ncolors = 1 << self.bpp;
+                let mut clut = Vec::with_capacity(ncolors);
+                let mut slots = HashMap::new();
+                let mut idx = Vec::with_capacity(w * h);
+
+                for px in self.data.chunks_exact(4) {
+                    let c = rgba8888_to_rgba5551(px[0], px[1], px[2], px[3]);
+                    let slot = *slots.entry(c).or_insert_with(|| {
+                        clut.push(c);
+                        clut.len() - 1
+                    });
+                    idx.push(slot as u8);
+                }
+
+                if clut.len() > ncolors {
+                    return Err(TIMErr::ColorCount(clut.len()));
+                }
+                clut.resize(ncolors, 0);
+
+                // rows are padded out to whole 16-bit units
+                let ppw = 16 / self.bpp as usize;
+                let words = w.div_ceil(ppw);
+                let mut packed = Vec::with_capacity(words * 2 * h);
+                for row in idx.chunks_exact(w) {
+                    let mut row = row.to_vec();
+                    row.resize(words * ppw, 0);
+                    match self.bpp {
+                        4 => packed.extend(row.chunks_exact(2).map(|p| p[0] | p[1] << 4)),
+                        _ => packed.extend(row),
+                    }
+                }
+
+                let (header, imgh) = match self.header {
+                    Header::Indexed(header, imgh) => (header, imgh),
+                    Header::NonIndexed(_) => {
+                        (IndexedHeader::zeroed(), IndexedImageHeader::zeroed())
+                    }
+                };
+                let header = IndexedHeader {
+                    magic: 16,
+                    flags: Flags::INDEXED
+                        | if self.bpp == 4 {
+                            Flags::BPP_4
+                        } else {
+                            Flags::BPP_8
+                        },
+                    clut_size: 12 + ncolors as u32 * 2,
+                    ncolors: ncolors as u16,
+                    ncluts: 1,
+                    ..header
+                };
+                let imgh = IndexedImageHeader {
+                    size: 12 + packed.len() as u32,
+                    w: words as u16,
+                    h: self.h,
+                    ..imgh
+                };
+
+                buf.extend_from_slice(bytes_of(&header));
+                clut.iter()
+                    .for_each(|c| buf.extend_from_slice(&c.to_le_bytes()));
+                buf.extend_from_slice(bytes_of(&imgh));
+                buf.extend(packed);
+            }
+            16 | 24 => {
+                let mut pixels = Vec::new();
+                let words = match self.bpp {
+                    16 => {
+                        for px in self.data.chunks_exact(4) {
+                            let c = rgba8888_to_rgba5551(px[0], px[1], px[2], px[3]);
+                            pixels.extend_from_slice(&c.to_le_bytes());
+                        }
+                        w
+                    }
+                    _ => {
+                        // 2 pixels to every 3 units, with rows padded out to whole units
+                        let words = (w * 3).div_ceil(2);
+                        for row in self.data.chunks_exact(w * 4) {
+                            let start = pixels.len();
+                            row.chunks_exact(4)
+                                .for_each(|px| pixels.extend_from_slice(&px[..3]));
+                            pixels.resize(start + words * 2, 0);
+                        }
+                        words
+                    }
+                };
+
+                let header = match self.header {
+                    Header::NonIndexed(header) => header,
+                    Header::Indexed(..) => NonIndexedHeader::zeroed(),
+                };
+                let header = NonIndexedHeader {
+                    magic: 16,
+                    flags: if self.bpp == 16 {
+                        Flags::BPP_16
+                    } else {
+                        Flags::BPP_24
+                    },
+                    size: 12 + pixels.len() as u32,
+                    w: words as u16,
+                    h: self.h,
+                    ..header
+                };
+
+                buf.extend_from_slice(bytes_of(&header));
+                buf.extend(pixels);
+            }
+            _ => return Err(TIMErr::BitsPerPixel(self.bpp)),
+        }
+
+        fs::write(path, &buf[..]).map_err(|e| TIMErr::TIMWrite(e))
+    }
+}
+
+impl Image for TIMImage {
+    type ImageError = TIMErr;
+
+    fn load(path: &str) -> Result<TIMImage, TIMErr> {
+        TIMImage::load_with_palette(path, 0)
+    }
+
+    fn save_png(&self, path: &str) -> Result<(), TIMErr> {
+        let file = File::create(path).map_err(|_| TIMErr::PathWrite)?;
+        let ref mut w = BufWriter::new(file);
+        let mut enc = Encoder::new(w, self.w as u32, self.h as u32);
+
+        enc.set_color(ColorType::Rgba);
+        enc.set_depth(BitDepth::Eight);
+
+        enc.write_header()
+            .map_err(|e| TIMErr::FileWrite(e))?
+            .write_image_data(&self.data[..])
+            .map_err(|e| TIMErr::FileWrite(e))?;
+
+        Ok(())
+    }
+}
+
+/// Converts every .tim file in `in_dir` to a PNG with the same stem in `out_dir`. Files that fail
+/// to convert are listed in the report rather than stopping the batch.
+pub fn convert_dir(in_dir: &str, out_dir: &str) -> Result<ConvertReport, TIMErr> {
+    fs::create_dir_all(out_dir).map_err(|e| TIMErr::DirCreate(e))?;
+
+    let mut paths = fs::read_dir(in_dir)
+        .map_err(|e| TIMErr::DirRead(e))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, io::Error>>()
+        .map_err(|e| TIMErr::DirRead(e))?;
+    paths.sort();
+
+    let mut report = ConvertReport::default();
+    for path in paths {
+        let is_tim = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("tim"));
+        if !is_tim || !path.is_file() {
+            continue;
+        }
+
+        let out_path = Path::new(out_dir)
+            .join(path.file_stem().unwrap_or_default())
+            .with_extension("png");
+        match convert_file(&path, &out_path) {
+            Ok(()) => report.converted += 1,
+            Err(e) => report.failed.push((path, e)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Converts a single TIM file to PNG
+fn convert_file(path: &Path, out_path: &Path) -> Result<(), TIMErr> {
+    let (Some(path), Some(out_path)) = (path.to_str(), out_path.to_str()) else {
+        return Err(TIMErr::PathWrite);
+    };
+
+    TIMImage::load(path)?.save_png(out_path)
+}
+
+/// Expands 4 or 8-bit palette indices through the palette starting at CLUT entry `base` into
+/// RGBA8888 data
+fn expand_indexed(clut: &Clut, base: usize, idx: &[u8], bpp: u32) -> Result<Vec<u8>, TIMErr> {
+    match bpp {
+        4 => {
+            let mut data = vec![0; idx.len() * 8];
+            for (i, px) in idx.iter().zip(data.chunks_exact_mut(8)) {
+                // the low nibble holds the leftmost pixel
+                px[..4].copy_from_slice(&clut.rgba(base + (*i & 15) as usize)?);
+                px[4..].copy_from_slice(&clut.rgba(base + (*i >> 4) as usize)?);
+            }
+            Ok(data)
+        }
+        8 => {
+            let mut data = vec![0; idx.len() * 4];
+            for (i, px) in idx.iter().zip(data.chunks_exact_mut(4)) {
+                px.copy_from_slice(&clut.rgba(base + *i as usize)?);
+            }
+            Ok(data)
+        }
+        _ => Err(TIMErr::BitsPerPixel(bpp)),
+    }
+}
+
+/// Keeps the first `w` pixels of every `stride` pixel row of RGBA8888 data
+fn crop_rows(data: &[u8], stride: u16, w: u16) -> Vec<u8> {
+    data.chunks_exact(stride as usize * 4)
+        .flat_map(|row| &row[..w as usize * 4])
+        .copied()
+        .collect()
+}
+
+/// Writes an RGBA5551 colour into a 4-byte RGBA8888 pixel slot
+fn put_pixel(px: &mut [u8], i: u32) {
+    let (r, g, b, a) = rgba5551_to_rgba8888(i);
+    px.copy_from_slice(&[r, g, b, a]);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Image;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    /// Needs a TIM from the game, given by `KAJAR_TIM`
+    #[test]
+    #[ignore]
+    fn test_tim_import() {
+        let img = super::TIMImage::load(&std::env::var("KAJAR_TIM").unwrap());
+        println!("{:?}", img);
+    }
+
+    /// Needs a TIM from the game, given by `KAJAR_TIM`
+    #[test]
+    #[ignore]
+    fn test_tim_export() {
+        let img = super::TIMImage::load(&std::env::var("KAJAR_TIM").unwrap()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let png = dir.path().join("tim.png");
+        img.save_png(png.to_str().unwrap()).unwrap();
+        assert!(png.is_file());
+    }
+
+    /// Writes out a 4bpp TIM with 16 colour palettes of `entry_size` byte entries
+    fn indexed_tim(clut: &[u8], entry_size: u32, w: u16, h: u16, idx: &[u8]) -> NamedTempFile {
+        let mut tim = Vec::new();
+        tim.extend_from_slice(&16u32.to_le_bytes());
+        // 24-bit CLUTs are flagged next to the indexed flag
+        let flags: u32 = if entry_size == 3 { 24 } else { 8 };
+        tim.extend_from_slice(&flags.to_le_bytes());
+        // 16 colours per palette, with as many palettes as the CLUT holds
+        let ncluts = (clut.len() as u32 / (16 * entry_size)) as u16;
+        tim.extend_from_slice(&(12 + clut.len() as u32).to_le_bytes());
+        tim.extend_from_slice(&[0, 0, 0, 0, 16, 0]);
+        tim.extend_from_slice(&ncluts.to_le_bytes());
+        tim.extend_from_slice(clut);
+        tim.extend_from_slice(&(12 + idx.len() as u32).to_le_bytes());
+        tim.extend_from_slice(&[0, 0, 0, 0]);
+        tim.extend_from_slice(&w.to_le_bytes());
+        tim.extend_from_slice(&h.to_le_bytes());
+        tim.extend_from_slice(idx);
+
+        let mut f = NamedTempFile::new().unwrap();
+        f.write_all(&tim[..]).unwrap();
+        f
+    }
+
+    #[test]
+    fn test_tim_4bpp() {
+        // opaque greys, then pure red, green and blue in slots 1-3; slot 15 is 15/31 grey
+        let mut clut = (0..16u16).map(|i| i << 10 | i << 5 | i).collect::<Vec<_>>();
+        clut[1] = 0x001F;
+        clut[2] = 0x03E0;
+        clut[3] = 0x7C00;
+        let clut = clut
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let f = indexed_tim(&clut[..], 2, 1, 1, &[0x21, 0xF3]);
+        let img = super::TIMImage::load(f.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(img.palette().map(|p| p.len()), Some(16));
+        assert_eq!(
+            &img.data[..16],
+            &[255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 123, 123, 123, 255]
+        );
+    }
+
+    #[test]
+    fn test_tim_short_clut() {
+        use super::{TIMErr, TIMImage};
+
+        // a 2-colour palette, with the third pixel using index 15
+        let mut tim = Vec::new();
+        for word in [16u32, 8, 12 + 4] {
+            tim.extend_from_slice(&word.to_le_bytes());
+        }
+        tim.extend_from_slice(&[0, 0, 0, 0, 2, 0, 1, 0, 0x1F, 0, 0xE0, 0x03]);
+        tim.extend_from_slice(&(12u32 + 2).to_le_bytes());
+        tim.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0, 0x10, 0x0F]);
+
+        assert!(matches!(
+            TIMImage::from_bytes(&tim[..]),
+            Err(TIMErr::PaletteIndex(15))
+        ));
+
+        *tim.last_mut().unwrap() = 0x01;
+        let img = TIMImage::from_bytes(&tim[..]).unwrap();
+        assert_eq!(&img.data[..8], &[255, 0, 0, 255, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_tim_truncated() {
+        use super::{TIMErr, TIMImage};
+
+        assert!(matches!(
+            TIMImage::from_bytes(&[16, 0, 0, 0, 2]),
+            Err(TIMErr::HeaderRead(_))
+        ));
+
+        // a 16bpp 256x256 TIM with only one pixel of data
+        let mut tim = Vec::new();
+        for word in [16u32, 2, 12 + 2] {
+            tim.extend_from_slice(&word.to_le_bytes());
+        }
+        tim.extend_from_slice(&[0, 0, 0, 0, 0, 1, 0, 1, 0x1F, 0]);
+        assert!(matches!(
+            TIMImage::from_bytes(&tim[..]),
+            Err(TIMErr::PixelRead(_))
+        ));
+    }
+
+    #[test]
+    fn test_tim_palettes() {
+        use super::{TIMErr, TIMImage};
+
+        // palette 0 is all red and palette 1 all blue
+        let clut = [0x001Fu16; 16]
+            .iter()
+            .chain([0x7C00u16; 16].iter())
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+        let f = indexed_tim(&clut[..], 2, 1, 1, &[0x10, 0x32]);
+        let path = f.path().to_str().unwrap();
+
+        let tim = TIMImage::load(path).unwrap();
+        assert_eq!(tim.num_palettes(), 2);
+        assert_eq!(tim.palette_index(), 0);
+        assert_eq!(&tim.rgba_bytes()[..4], &[255, 0, 0, 255]);
+
+        let tim = TIMImage::load_with_palette(path, 1).unwrap();
+        assert_eq!(tim.palette_index(), 1);
+        assert!(tim
+            .rgba_bytes()
+            .chunks_exact(4)
+            .all(|px| px == [0, 0, 255, 255]));
+
+        assert!(matches!(
+            TIMImage::load_with_palette(path, 2),
+            Err(TIMErr::PaletteIndex(2))
+        ));
+    }
+
+    #[test]
+    fn test_split_sprites() {
+        use super::{Rect, TIMImage};
+
+        // an 8x4 page holding four 4x2 sprites, each drawn in the colour of its number
+        let clut = (0..16u16)
+            .map(|i| i << 10 | i << 5 | i)
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+        let idx = [0x00, 0x00, 0x11, 0x11]
+            .repeat(2)
+            .into_iter()
+            .chain([0x22, 0x22, 0x33, 0x33].repeat(2))
+            .collect::<Vec<u8>>();
+        let f = indexed_tim(&clut[..], 2, 2, 4, &idx[..]);
+        let page = TIMImage::load(f.path().to_str().unwrap()).unwrap();
+
+        let rects = [(0, 0), (4, 0), (0, 2), (4, 2)].map(|(x, y)| Rect { x, y, w: 4, h: 2 });
+        let sprites = page.split_sprites(&rects);
+        assert_eq!(sprites.len(), 4);
+
+        let out = tempfile::tempdir().unwrap();
+        for (i, sprite) in sprites.iter().enumerate() {
+            assert_eq!(
+                (sprite.width(), sprite.height(), sprite.vram_width()),
+                (4, 2, 1)
+            );
+            assert_eq!(sprite.idx, [(i as u8) * 0x11; 4]);
+            assert!(sprite
+                .rgba_bytes()
+                .chunks_exact(4)
+                .all(|px| px == &page.clut.as_ref().unwrap().rgba(i).unwrap()[..]));
+
+            let png = out.path().join(format!("{}.png", i));
+            sprite.save_png(png.to_str().unwrap()).unwrap();
+        }
+
+        let super::Header::Indexed(_, imgh) = sprites[3].header else {
+            panic!("sprite lost its CLUT");
+        };
+        assert_eq!((imgh.addr_x, imgh.addr_y), (1, 2));
+
+        // sprites re-expand through the shared CLUT, and rectangles are clipped to the page
+        let mut sprite = page
+            .split_sprites(&[Rect {
+                x: 6,
+                y: 3,
+                w: 4,
+                h: 4,
+            }])
+            .remove(0);
+        assert_eq!((sprite.width(), sprite.height()), (2, 1));
+        sprite.remap_palette(&[(3, 0x7FFF)]);
+        assert_eq!(sprite.rgba_bytes(), [255; 8]);
+    }
+
+    #[test]
+    fn test_tim_crop_width() {
+        use super::{TIMErr, TIMImage};
+
+        let clut = (0..16u16)
+            .map(|i| i << 10 | i << 5 | i)
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+        // 2 VRAM units hold 8 pixels a row, but the sprite is only 5 wide
+        let f = indexed_tim(
+            &clut[..],
+            2,
+            2,
+            2,
+            &[0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE],
+        );
+        let mut img = TIMImage::load(f.path().to_str().unwrap()).unwrap();
+        assert_eq!((img.vram_width(), img.width()), (2, 8));
+
+        img.crop_width(5).unwrap();
+        assert_eq!((img.vram_width(), img.width(), img.height()), (2, 5, 2));
+        let greys = img
+            .rgba_bytes()
+            .chunks_exact(4)
+            .map(|px| px[0])
+            .collect::<Vec<_>>();
+        let grey = |i: u32| super::rgba5551_to_rgba8888(i << 10 | i << 5 | i).0;
+        assert_eq!(greys, [0, 1, 2, 3, 4, 8, 9, 10, 11, 12].map(grey).to_vec());
+
+        // re-expanding the palette keeps the crop
+        img.remap_palette(&[(0, 0x7FFF)]);
+        assert_eq!(img.rgba_bytes().len(), 5 * 2 * 4);
+        assert_eq!(&img.rgba_bytes()[..4], [255, 255, 255, 255]);
+
+        assert!(matches!(img.crop_width(6), Err(TIMErr::Width(6))));
+    }
+
+    #[test]
+    fn test_tim_24bpp() {
+        let mut tim = Vec::new();
+        tim.extend_from_slice(&16u32.to_le_bytes());
+        tim.extend_from_slice(&3u32.to_le_bytes());
+        tim.extend_from_slice(&(12u32 + 12).to_le_bytes());
+        tim.extend_from_slice(&[0, 0, 0, 0, 3, 0, 2, 0]);
+        tim.extend_from_slice(&[255, 0, 0, 0, 255, 0]);
+        tim.extend_from_slice(&[0, 0, 255, 10, 20, 30]);
+
+        let mut f = NamedTempFile::new().unwrap();
+        f.write_all(&tim[..]).unwrap();
+
+        let img = super::TIMImage::load(f.path().to_str().unwrap()).unwrap();
+        assert_eq!((img.width(), img.height(), img.bpp()), (2, 2, 24));
+        assert_eq!(img.rgba_bytes().len(), 16);
+        assert_eq!(
+            img.into_rgba(),
+            [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 10, 20, 30, 255]
+        );
+    }
+
+    #[test]
+    fn test_tim_save_round_trip() {
+        use super::TIMImage;
+
+        let clut = (0..16u16)
+            .map(|i| i << 10 | (15 - i) << 5 | (i * 2))
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>();
+        let idx = (0..16u8).map(|i| (i * 17) ^ 0x5A).collect::<Vec<_>>();
+        let f = indexed_tim(&clut[..], 2, 2, 4, &idx[..]);
+
+        let mut pixels = Vec::new();
+        for i in 0..24u16 {
+            let c = i << 10 | i << 5 | (31 - i) | if i % 5 == 0 { 0x8000 } else { 0 };
+            pixels.extend_from_slice(&c.to_le_bytes());
+        }
+        let mut tim16 = Vec::new();
+        tim16.extend_from_slice(&16u32.to_le_bytes());
+        tim16.extend_from_slice(&2u32.to_le_bytes());
+        tim16.extend_from_slice(&(12 + pixels.len() as u32).to_le_bytes());
+        tim16.extend_from_slice(&[0, 0, 0, 0, 6, 0, 4, 0]);
+        tim16.extend_from_slice(&pixels[..]);
+        let mut f16 = NamedTempFile::new().unwrap();
+        f16.write_all(&tim16[..]).unwrap();
+
+        let mut tim24 = Vec::new();
+        tim24.extend_from_slice(&16u32.to_le_bytes());
+        tim24.extend_from_slice(&3u32.to_le_bytes());
+        tim24.extend_from_slice(&(12u32 + 12).to_le_bytes());
+        tim24.extend_from_slice(&[0, 0, 0, 0, 2, 0, 3, 0]);
+        tim24.extend_from_slice(&[1, 2, 3, 0, 4, 5, 6, 0, 7, 8, 9, 0]);
+        let mut f24 = NamedTempFile::new().unwrap();
+        f24.write_all(&tim24[..]).unwrap();
+
+        let out = tempfile::tempdir().unwrap();
+        for (i, f) in [f.path(), f16.path(), f24.path()].iter().enumerate() {
+            let img = TIMImage::load(f.to_str().unwrap()).unwrap();
+            let saved = out.path().join(format!("{}.tim", i));
+            img.save_tim(saved.to_str().unwrap()).unwrap();
+
+            let reloaded = TIMImage::load(saved.to_str().unwrap()).unwrap();
+            assert_eq!(
+                (reloaded.w, reloaded.h, reloaded.bpp),
+                (img.w, img.h, img.bpp)
+            );
+            assert_eq!(reloaded.data, img.data);
+        }
+    }
+
+    #[test]
+    fn test_convert_dir() {
+        let f = indexed_tim(&[0; 32], 2, 1, 1, &[0x10, 0x32]);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::copy(f.path(), dir.path().join("good.tim")).unwrap();
+        std::fs::write(dir.path().join("bad.TIM"), b"not a tim").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"skip me").unwrap();
+
+        let out = dir.path().join("png");
+        let report =
+            super::convert_dir(dir.path().to_str().unwrap(), out.to_str().unwrap()).unwrap();
+
+        assert_eq!(report.converted, 1);
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].0.ends_with("bad.TIM"));
+        assert!(out.join("good.png").is_file());
+        assert_eq!(std::fs::read_dir(&out).unwrap().count(), 1);
+    }
+
+    #[cfg(feature = "image-interop")]
+    #[test]
+    fn test_rgba_image() {
+        let mut clut = [0; 32];
+        clut[2..4].copy_from_slice(&0x001Fu16.to_le_bytes());
+        let f = indexed_tim(&clut[..], 2, 1, 1, &[0x10, 0x01]);
+        let img = super::TIMImage::load(f.path().to_str().unwrap()).unwrap();
+
+        let rgba = image::RgbaImage::try_from(&img).unwrap();
+        assert_eq!(rgba.dimensions(), (4, 1));
+        assert_eq!(rgba.get_pixel(1, 0).0, [255, 0, 0, 255]);
+        assert_eq!(rgba.get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(rgba.as_raw(), &img.data);
+    }
+
+    #[test]
+    fn test_tim_clut24() {
+        let clut = (0..16u8)
+            .flat_map(|i| [i * 16, 255 - i, 7])
+            .collect::<Vec<_>>();
+        let f = indexed_tim(&clut[..], 3, 1, 1, &[0x01, 0x00]);
+
+        let img = super::TIMImage::load(f.path().to_str().unwrap()).unwrap();
+        assert!(matches!(img.clut(), Some(super::Clut::Rgb888(c)) if c.len() == 16));
+        assert!(img.palette().is_none());
+        assert_eq!(&img.data[..8], &[16, 254, 7, 255, 0, 255, 7, 255]);
+    }
+
+    #[test]
+    fn test_tim_clut_block() {
+        let mut clut = [0; 32];
+        clut[2..4].copy_from_slice(&0x001Fu16.to_le_bytes());
+        let f = indexed_tim(&clut[..], 2, 1, 1, &[0x10, 0x00]);
+        let mut tim = std::fs::read(f.path()).unwrap();
+
+        // padding at the end of the CLUT block is skipped
+        let mut padded = tim.clone();
+        padded[8..12].copy_from_slice(&(12 + 32 + 4u32).to_le_bytes());
+        padded.splice(52..52, [0xAA; 4]);
+        let img = super::TIMImage::from_bytes(&padded[..]).unwrap();
+        assert_eq!((img.w, img.h), (4, 1));
+        assert_eq!(&img.data[..8], &[0, 0, 0, 0, 255, 0, 0, 255]);
+
+        // a 24-bit flag on a block sized for 16-bit entries
+        tim[4] |= 16;
+        assert!(matches!(
+            super::TIMImage::from_bytes(&tim[..]),
+            Err(super::TIMErr::ClutFormat(3))
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+/// Alpha given to semi-transparent pixels
+const SEMI_ALPHA: u8 = 128;
+
+/// Expands a 5 bit value to a full byte
+pub const fn scale5to8(i: u8) -> u8 {
+    (i << 3) | (i >> 2)
+}
+
+/// Converts an RGBA8888 colour to RGBA5551, following the STP rules of `rgba5551_to_rgba8888`.
+/// Semi-transparent black can't be represented and comes out opaque.
+pub const fn rgba8888_to_rgba5551(r: u8, g: u8, b: u8, a: u8) -> u16 {
+    let c = (r >> 3) as u16 | ((g >> 3) as u16) << 5 | ((b >> 3) as u16) << 10;
+    match (a, c) {
+        (0, _) => 0,
+        (255, 0) => 0x8000,
+        (255, _) => c,
+        _ => c | 0x8000,
+    }
+}
+
+/// Converts a colour value from RGBA5551 to RGBA8888
+pub const fn rgba5551_to_rgba8888(i: u32) -> (u8, u8, u8, u8) {
+    let r = scale5to8((i & 31) as u8);
+    let g = scale5to8(((i >> 5) & 31) as u8);
+    let b = scale5to8(((i >> 10) & 31) as u8);
+
+    // Black without the STP bit is fully transparent and black with it is opaque, while any
+    // other colour is opaque without it and semi-transparent with it
+    let a = match (i & 0x8000 != 0, i & 0x7FFF != 0) {
+        (false, false) => 0,
+        (false, true) | (true, false) => 255,
+        (true, true) => SEMI_ALPHA,
+    };
+
+    (r, g, b, a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rgba5551_to_rgba8888, rgba8888_to_rgba5551};
+
+    #[test]
+    fn test_stp_alpha() {
+        // transparent black, opaque black, opaque red, semi-transparent red
+        assert_eq!(rgba5551_to_rgba8888(0x0000), (0, 0, 0, 0));
+        assert_eq!(rgba5551_to_rgba8888(0x8000), (0, 0, 0, 255));
+        assert_eq!(rgba5551_to_rgba8888(0x001F), (255, 0, 0, 255));
+        assert_eq!(rgba5551_to_rgba8888(0x801F), (255, 0, 0, 128));
+
+        for c in [0x0000u16, 0x8000, 0x001F, 0x801F] {
+            let (r, g, b, a) = rgba5551_to_rgba8888(c as u32);
+            assert_eq!(rgba8888_to_rgba5551(r, g, b, a), c);
+        }
+    }
+
+    #[test]
+    fn test_color_round_trip() {
+        // every 5551 colour survives a trip through 8888
+        for c in 0..=u16::MAX {
+            let (r, g, b, a) = rgba5551_to_rgba8888(c as u32);
+            assert_eq!(rgba8888_to_rgba5551(r, g, b, a), c);
+        }
+
+        // 8888 colours come back truncated to 5 bits per channel, then stay put
+        for (r, g, b, a) in [
+            (255, 255, 255, 255),
+            (12, 200, 99, 255),
+            (255, 0, 0, 128),
+            (8, 16, 24, 0),
+        ] {
+            let c = rgba8888_to_rgba5551(r, g, b, a);
+            let back = rgba5551_to_rgba8888(c as u32);
+            assert_eq!(rgba8888_to_rgba5551(back.0, back.1, back.2, back.3), c);
+            if a != 0 {
+                assert_eq!(
+                    (back.0 >> 3, back.1 >> 3, back.2 >> 3),
+                    (r >> 3, g >> 3, b >> 3)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+
+use std::{
+    error,
+    ffi::c_int,
+    fmt,
+    io::{self, Read},
+};
+
+/// zlib's error code for bad parameters, such as an unsupported window size
+pub(crate) const Z_STREAM_ERROR: c_int = -2;
+
+/// zlib's error code for corrupt input, also reported by the flate2 backend
+pub(crate) const Z_DATA_ERROR: c_int = -3;
+
+/// zlib's error code for input or output running out before the stream ended, also reported by
+/// the flate2 backend
+pub(crate) const Z_BUF_ERROR: c_int = -5;
+
+/// Most bytes a deflate stream can inflate to per input byte, so a corrupt size can't make the
+/// output buffer larger than the data could ever fill
+const MAX_RATIO: usize = 1032;
+
+/// Inflate error
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InflateError {
+    SizeMismatch {
+        expected: usize,
+        got: usize,
+    },
+
+    /// zlib error code, which the flate2 backend reports the same way
+    Stream(c_int),
+    WindowBits(i32),
+}
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InflateError::SizeMismatch { expected, got } => {
+                write!(f, "decompressed to {} bytes, expected {}", got, expected)
+            }
+            InflateError::Stream(e) => write!(f, "zlib decompression failed with code {}", e),
+            InflateError::WindowBits(n) => write!(f, "unsupported window bits {}", n),
+        }
+    }
+}
+
+impl error::Error for InflateError {}
+
+/// Stream framing, as selected by zlib's `windowBits`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Framing {
+    Zlib,
+    Raw,
+    Gzip,
+
+    /// zlib or gzip, whichever header the stream has
+    Auto,
+}
+
+impl Framing {
+    /// The framing zlib picks for `window_bits`: 8-15 for zlib, -8 to -15 for raw deflate, 16
+    /// more for gzip, or 32 more to detect zlib or gzip from the header
+    fn new(window_bits: i32) -> Result<Framing, InflateError> {
+        match window_bits {
+            8..=15 => Ok(Framing::Zlib),
+            -15..=-8 => Ok(Framing::Raw),
+            24..=31 => Ok(Framing::Gzip),
+            40..=47 => Ok(Framing::Auto),
+            n => Err(InflateError::WindowBits(n)),
+        }
+    }
+}
+
+/// Inflates a deflate stream that should come to exactly `expected` bytes. `window_bits` follows
+/// zlib's `inflateInit2`, so 31 reads the gzip streams in resources.bin.
+pub fn inflate(data: &[u8], expected: usize, window_bits: i32) -> Result<Vec<u8>, InflateError> {
+    let framing = Framing::new(window_bits)?;
+
+    #[cfg(feature = "libz")]
+    let _ = framing;
+
+    #[cfg(feature = "libz")]
+    return libz::decompress(data, expected, window_bits);
+
+    #[cfg(not(feature = "libz"))]
+    return inflate_flate2(data, expected, framing);
+}
+
+/// Inflates data with flate2, failing the same way zlib does if it doesn't come to `expected`
+#[cfg_attr(feature = "libz", allow(dead_code))]
+fn inflate_flate2(data: &[u8], expected: usize, framing: Framing) -> Result<Vec<u8>, InflateError> {
+    let mut dcmp = Vec::with_capacity(expected.min(data.len().saturating_mul(MAX_RATIO)));
+
+    let framing = match framing {
+        Framing::Auto if data.starts_with(&[0x1F, 0x8B]) => Framing::Gzip,
+        Framing::Auto => Framing::Zlib,
+        f => f,
+    };
+
+    // read one byte past the expected size to tell if there was more
+    let limit = expected as u64 + 1;
+    match framing {
+        Framing::Zlib | Framing::Auto => ZlibDecoder::new(data).take(limit).read_to_end(&mut dcmp),
+        Framing::Raw => DeflateDecoder::new(data).take(limit).read_to_end(&mut dcmp),
+        Framing::Gzip => GzDecoder::new(data).take(limit).read_to_end(&mut dcmp),
+    }
+    .map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => InflateError::Stream(Z_BUF_ERROR),
+        _ => InflateError::Stream(Z_DATA_ERROR),
+    })?;
+
+    match dcmp.len() {
+        n if n > expected => Err(InflateError::Stream(Z_BUF_ERROR)),
+        n if n < expected => Err(InflateError::SizeMismatch { expected, got: n }),
+        _ => Ok(dcmp),
+    }
+}
+
+/// zlib itself, for exactly the C library's behavior
+#[cfg(feature = "libz")]
+mod libz {
+    use libz_sys::{
+        inflate, inflateEnd, inflateInit2_, uInt, z_stream, zlibVersion, Bytef, Z_FINISH, Z_OK,
+        Z_STREAM_END,
+    };
+
+    use std::{
+        ffi::c_int,
+        mem::{size_of, MaybeUninit},
+        ptr::addr_of_mut,
+    };
+
+    use super::{InflateError, MAX_RATIO};
+
+    pub(super) fn decompress(
+        data: &[u8],
+        expected: usize,
+        window_bits: i32,
+    ) -> Result<Vec<u8>, InflateError> {
+        let mut dcmp = vec![0; expected.min(data.len().saturating_mul(MAX_RATIO))];
+
+        unsafe {
+            let zs_ = MaybeUninit::<z_stream>::zeroed();
+            let mut zs = zs_.assume_init();
+            let ver = zlibVersion();
+
+            // zlib never writes through next_in
+            zs.next_in = data.as_ptr() as *mut Bytef;
+            zs.avail_in = data.len() as uInt;
+            zs.next_out = dcmp.as_mut_ptr() as *mut Bytef;
+            zs.avail_out = dcmp.len() as uInt;
+
+            let err = inflateInit2_(
+                addr_of_mut!(zs),
+                window_bits,
+                ver,
+                size_of::<z_stream>() as c_int,
+            );
+            if err != Z_OK {
+                return Err(InflateError::Stream(err));
+            }
+
+            let err = inflate(addr_of_mut!(zs), Z_FINISH);
+            if err != Z_STREAM_END {
+                inflateEnd(addr_of_mut!(zs));
+                return Err(InflateError::Stream(err));
+            }
+
+            inflateEnd(addr_of_mut!(zs));
+
+            // a stream that ends early would otherwise leave the rest of the buffer zeroed
+            if zs.total_out as usize != expected {
+                return Err(InflateError::SizeMismatch {
+                    expected,
+                    got: zs.total_out as usize,
+                });
+            }
+        }
+
+        Ok(dcmp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{inflate, InflateError};
+
+    #[cfg(feature = "libz")]
+    use std::io::Read;
+
+    /// zlib stream of "hello, hello, hello, hello" from `zlib.compress`
+    const ZLIB: &[u8] = b"\x78\x9c\xcb\x48\xcd\xc9\xc9\xd7\x51\xc8\xc0\xa4\x00\x7c\x16\x09\x35";
+
+    const HELLO: &[u8] = b"hello, hello, hello, hello";
+
+    #[test]
+    fn test_inflate_zlib() {
+        assert_eq!(inflate(ZLIB, HELLO.len(), 15).unwrap(), HELLO);
+        assert_eq!(inflate(ZLIB, HELLO.len(), 47).unwrap(), HELLO);
+
+        assert_eq!(
+            inflate(ZLIB, HELLO.len() + 1, 15),
+            Err(InflateError::SizeMismatch {
+                expected: HELLO.len() + 1,
+                got: HELLO.len()
+            })
+        );
+        assert!(matches!(
+            inflate(ZLIB, HELLO.len() - 1, 15),
+            Err(InflateError::Stream(_))
+        ));
+        assert!(matches!(
+            inflate(&ZLIB[..8], HELLO.len(), 15),
+            Err(InflateError::Stream(_))
+        ));
+        assert_eq!(
+            inflate(ZLIB, HELLO.len(), 7),
+            Err(InflateError::WindowBits(7))
+        );
+    }
+
+    #[test]
+    fn test_inflate_oversized() {
+        // a corrupt size is only trusted as far as the stream could expand
+        assert_eq!(
+            inflate(ZLIB, usize::MAX / 2, 15),
+            Err(InflateError::SizeMismatch {
+                expected: usize::MAX / 2,
+                got: HELLO.len()
+            })
+        );
+    }
+
+    #[test]
+    fn test_inflate_raw() {
+        // the same stream without its 2-byte header and 4-byte checksum
+        let raw = &ZLIB[2..ZLIB.len() - 4];
+        assert_eq!(inflate(raw, HELLO.len(), -15).unwrap(), HELLO);
+
+        // a raw stream has no zlib header to validate
+        assert!(inflate(raw, HELLO.len(), 15).is_err());
+    }
+
+    #[cfg(feature = "libz")]
+    #[test]
+    fn test_backends_match() {
+        use super::{inflate_flate2, libz, Framing};
+
+        let payload = b"Both backends must agree on every stream. ".repeat(256);
+        let mut zlib = Vec::new();
+        flate2::read::ZlibEncoder::new(&payload[..], flate2::Compression::default())
+            .read_to_end(&mut zlib)
+            .unwrap();
+
+        // both report a good stream, a short one, and output that doesn't fit, the same way
+        for expected in [payload.len(), payload.len() + 1, payload.len() - 1] {
+            assert_eq!(
+                libz::decompress(&zlib[..], expected, 15),
+                inflate_flate2(&zlib[..], expected, Framing::Zlib)
+            );
+        }
+    }
+
+    #[test]
+    fn test_inflate_gzip() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(HELLO).unwrap();
+        let gz = enc.finish().unwrap();
+
+        assert_eq!(inflate(&gz[..], HELLO.len(), 31).unwrap(), HELLO);
+        assert_eq!(inflate(&gz[..], HELLO.len(), 47).unwrap(), HELLO);
+    }
+}
@@ -1,5 +1,2 @@
 #[cfg(feature = "ct_win")]
 pub mod resbin;
-
-#[cfg(feature = "ct_win")]
-use resbin;
@@ -0,0 +1,2328 @@
+// Credit to https://github.com/jimzrt/ChronoMod
+
+use bytemuck::{bytes_of, bytes_of_mut, Zeroable};
+use bytemuck_derive::{Pod, Zeroable};
+use encoding_rs::SHIFT_JIS;
+use glob::{Pattern, PatternError};
+
+use flate2::{
+    write::{DeflateEncoder, GzEncoder, ZlibEncoder},
+    Compression, Crc,
+};
+
+#[cfg(not(feature = "libz"))]
+use flate2::read::GzDecoder;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::{
+    ascii,
+    borrow::Cow,
+    cmp::Reverse,
+    collections::HashMap,
+    error,
+    ffi::c_int,
+    fmt,
+    fs::{self, File},
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    compress::{self, InflateError, Z_DATA_ERROR, Z_STREAM_ERROR},
+    encoding::encode_sjis,
+    fourcc, read_cstr_capped, Archive, ArchiveEntry,
+};
+
+mod blowfish;
+
+use blowfish::{Cipher, CryptErr};
+
+/// Offset of the decryption key in the Steam release of the EXE
+pub const KEY_OFFSET: u64 = 0x398EE8;
+
+/// Size of the decryption key in bytes
+pub const KEY_SIZE: usize = 64;
+
+/// Longest entry path read from the path table, after Windows' `MAX_PATH`
+const PATH_MAX: usize = 260;
+
+/// Best compression ratio deflate can reach, bounding the size a compressed stream can claim
+const MAX_DEFLATE_RATIO: usize = 1032;
+
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+pub struct Header {
+    sig: u32,
+    size: u32,
+    offs: u32,
+    cmp_size: u32,
+}
+
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+pub struct ResEntry {
+    path_offs: u32,
+    data_offs: u32,
+    size: u32,
+}
+
+#[cfg(feature = "serde")]
+impl Header {
+    /// Serializes the header to JSON for external tools
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("headers always serialize")
+    }
+}
+
+pub struct ResBin {
+    header: Header,
+    key: [u8; KEY_SIZE],
+    entries: HashMap<PathBuf, (ResEntry, Vec<u8>)>,
+
+    /// Entries renamed because their path was taken, as (archive path, path stored under)
+    renamed: Vec<(PathBuf, PathBuf)>,
+
+    /// Deflated data of entries changed by `replace` or `insert`, reused by `save`
+    deflated: HashMap<PathBuf, Vec<u8>>,
+
+    /// Source of entry data not yet read, for archives opened with `open`
+    file: Option<File>,
+}
+
+/// Number of entries reported in `ArchiveStats::largest`
+const STATS_LARGEST: usize = 10;
+
+/// Entry totals for a single file extension
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExtStats {
+    pub count: usize,
+    pub size: usize,
+    pub cmp_size: usize,
+}
+
+/// Archive composition summary
+#[derive(Debug, Default)]
+pub struct ArchiveStats {
+    /// Totals grouped by file extension, with extensionless entries under ""
+    pub by_ext: HashMap<String, ExtStats>,
+
+    /// The largest entries by decompressed size, biggest first
+    pub largest: Vec<(PathBuf, usize)>,
+
+    /// Total compressed bytes over total decompressed bytes
+    pub ratio: f64,
+
+    /// Entries whose path was already taken, with the path each was stored under instead
+    pub duplicates: Vec<(PathBuf, PathBuf)>,
+}
+
+#[derive(Debug)]
+pub enum ResBinErr {
+    Cmp(c_int),
+    CmpRead(io::Error),
+    Crypt(PathBuf, CryptErr),
+    Decmp(c_int),
+    DirRead(PathBuf, io::Error),
+    Dump(io::Error),
+    DuplicatePath(PathBuf),
+    EntryDataRead(PathBuf, io::Error),
+    EntryPath(PathBuf),
+    EntryRead(io::Error),
+    EntryTruncated(PathBuf),
+    ExeRead(io::Error),
+    ExeVersion,
+    FileRead(io::Error),
+    FileWrite(io::Error),
+    HeaderMismatch(u32),
+    HeaderRead(io::Error),
+    KeyMismatch,
+    KeyRead(io::Error),
+    KeyWrite(io::Error),
+    ManifestWrite(io::Error),
+    OffsetOutOfRange { entry: usize, offset: u32 },
+    PathName(ResEntry, io::Error),
+    Pattern(PatternError),
+    SizeMismatch { expected: usize, got: usize },
+}
+
+impl fmt::Display for ResBinErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResBinErr::Cmp(e) => write!(f, "zlib compression failed with code {}", e),
+            ResBinErr::CmpRead(_) => write!(f, "unable to read compressed entry table"),
+            ResBinErr::Crypt(p, e) => write!(f, "unable to decrypt {}: {}", p.display(), e),
+            ResBinErr::Decmp(e) => write!(f, "zlib decompression failed with code {}", e),
+            ResBinErr::DirRead(p, _) => write!(f, "unable to read {}", p.display()),
+            ResBinErr::Dump(_) => write!(f, "unable to write entry"),
+            ResBinErr::DuplicatePath(p) => write!(f, "duplicate entry {}", p.display()),
+            ResBinErr::EntryDataRead(p, _) => write!(f, "unable to read data for {}", p.display()),
+            ResBinErr::EntryPath(p) => write!(f, "no entry named {}", p.display()),
+            ResBinErr::EntryRead(_) => write!(f, "unable to read entry table"),
+            ResBinErr::EntryTruncated(p) => {
+                write!(f, "{} is too short for its size prefix", p.display())
+            }
+            ResBinErr::ExeRead(_) => write!(f, "unable to read executable"),
+            ResBinErr::ExeVersion => {
+                write!(f, "unrecognised executable, the key offset must be given")
+            }
+            ResBinErr::FileRead(_) => write!(f, "unable to read archive"),
+            ResBinErr::FileWrite(_) => write!(f, "unable to write archive"),
+            ResBinErr::HeaderMismatch(sig) => write!(
+                f,
+                "bad archive signature: expected {}, got {}",
+                sig_str(fourcc(*b"ARC1")),
+                sig_str(*sig)
+            ),
+            ResBinErr::HeaderRead(_) => write!(f, "unable to read header"),
+            ResBinErr::KeyMismatch => write!(f, "decryption key does not match the archive"),
+            ResBinErr::KeyRead(_) => write!(f, "unable to read decryption key"),
+            ResBinErr::KeyWrite(_) => write!(f, "unable to write decryption key"),
+            ResBinErr::ManifestWrite(_) => write!(f, "unable to write manifest"),
+            ResBinErr::OffsetOutOfRange { entry, offset } => {
+                write!(f, "entry {} has out of range offset {:#X}", entry, offset)
+            }
+            ResBinErr::PathName(ent, _) => {
+                write!(f, "unable to read entry path at {:#X}", ent.path_offs)
+            }
+            ResBinErr::Pattern(e) => write!(f, "invalid pattern: {}", e),
+            ResBinErr::SizeMismatch { expected, got } => {
+                write!(f, "decompressed to {} bytes, expected {}", got, expected)
+            }
+        }
+    }
+}
+
+impl error::Error for ResBinErr {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ResBinErr::CmpRead(e)
+            | ResBinErr::DirRead(_, e)
+            | ResBinErr::Dump(e)
+            | ResBinErr::EntryDataRead(_, e)
+            | ResBinErr::EntryRead(e)
+            | ResBinErr::ExeRead(e)
+            | ResBinErr::FileRead(e)
+            | ResBinErr::FileWrite(e)
+            | ResBinErr::HeaderRead(e)
+            | ResBinErr::KeyRead(e)
+            | ResBinErr::KeyWrite(e)
+            | ResBinErr::ManifestWrite(e)
+            | ResBinErr::PathName(_, e) => Some(e),
+            ResBinErr::Crypt(_, e) => Some(e),
+            ResBinErr::Pattern(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// How `ResBin::load_with` treats entries that share a path
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicatePaths {
+    /// Fail with `ResBinErr::DuplicatePath`
+    Reject,
+
+    /// Keep every entry, giving later copies a `~1`, `~2`, ... suffix on the file stem. The
+    /// renamed entries are listed in `ArchiveStats::duplicates`.
+    #[default]
+    Rename,
+}
+
+impl ResBin {
+    /// Loads all data from resources.bin, renaming any entries with duplicate paths. The
+    /// decryption key is read from `ctexe` if given, as found by `find_key`, and left zeroed
+    /// otherwise.
+    pub fn load(filepath: &str, ctexe: Option<&str>) -> Result<Self, ResBinErr> {
+        Self::load_with(filepath, ctexe, DuplicatePaths::default())
+    }
+
+    /// Loads all data from resources.bin, handling duplicate entry paths as specified
+    pub fn load_with(
+        filepath: &str,
+        ctexe: Option<&str>,
+        dups: DuplicatePaths,
+    ) -> Result<Self, ResBinErr> {
+        let mut resb = Self::load_from(filepath, [0; KEY_SIZE], dups)?;
+        if let Some(ctexe) = ctexe {
+            resb.key = resb.find_key(ctexe)?;
+        }
+
+        Ok(resb)
+    }
+
+    /// Loads all data from resources.bin with a key previously pulled out by `extract_key`,
+    /// so the EXE isn't needed
+    pub fn load_with_key(filepath: &str, key: [u8; KEY_SIZE]) -> Result<Self, ResBinErr> {
+        Self::load_from(filepath, key, DuplicatePaths::default())
+    }
+
+    /// Parses an unencrypted resources.bin already held in memory
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ResBinErr> {
+        Self::from_buf(data, [0; KEY_SIZE], DuplicatePaths::default())
+    }
+
+    /// Parses a resources.bin already held in memory, decrypting entries with a key pulled out
+    /// by `extract_key`
+    pub fn from_bytes_with_key(data: &[u8], key: [u8; KEY_SIZE]) -> Result<Self, ResBinErr> {
+        Self::from_buf(data, key, DuplicatePaths::default())
+    }
+
+    /// Reads only the header and entry table of resources.bin, leaving entry data on disk to
+    /// be pulled out one at a time with `extract_one`
+    pub fn open(filepath: &str) -> Result<Self, ResBinErr> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("ResBin::open", path = filepath).entered();
+
+        let mut file = File::open(filepath).map_err(|e| ResBinErr::FileRead(e))?;
+        let mut resb = Self::read(&mut file, [0; KEY_SIZE], DuplicatePaths::default())?;
+        resb.file = Some(file);
+
+        Ok(resb)
+    }
+
+    fn load_from(
+        filepath: &str,
+        key: [u8; KEY_SIZE],
+        dups: DuplicatePaths,
+    ) -> Result<Self, ResBinErr> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("ResBin::load", path = filepath).entered();
+
+        // buffer file
+        let buf = fs::read(filepath).map_err(|e| ResBinErr::FileRead(e))?;
+        Self::from_buf(&buf[..], key, dups)
+    }
+
+    /// Loads all data from a memory-mapped resources.bin rather than reading it into memory
+    /// first. Entry data is still copied out of the map to be decoded.
+    #[cfg(feature = "mmap")]
+    pub fn load_mmap(filepath: &str, ctexe: Option<&str>) -> Result<Self, ResBinErr> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("ResBin::load_mmap", path = filepath).entered();
+
+        let file = File::open(filepath).map_err(|e| ResBinErr::FileRead(e))?;
+        // Safety: the map is dropped before returning, and the archive isn't expected to be
+        // modified while it's being loaded
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| ResBinErr::FileRead(e))?;
+        let mut resb = Self::from_buf(&map[..], [0; KEY_SIZE], DuplicatePaths::default())?;
+        if let Some(ctexe) = ctexe {
+            resb.key = resb.find_key(ctexe)?;
+        }
+
+        Ok(resb)
+    }
+
+    /// Parses a whole archive held in memory
+    fn from_buf(buf: &[u8], key: [u8; KEY_SIZE], dups: DuplicatePaths) -> Result<Self, ResBinErr> {
+        let mut resb = Self::read(&mut Cursor::new(buf), key, dups)?;
+        resb.read_entries(buf)?;
+
+        Ok(resb)
+    }
+
+    /// Parses the header and entry table, leaving entry data empty
+    fn read<R: Read + Seek>(
+        fc: &mut R,
+        key: [u8; KEY_SIZE],
+        dups: DuplicatePaths,
+    ) -> Result<Self, ResBinErr> {
+        let mut header = Header::zeroed();
+
+        // header
+        fc.read_exact(bytes_of_mut(&mut header))
+            .map_err(|e| ResBinErr::HeaderRead(e))?;
+
+        decode(0, bytes_of_mut(&mut header));
+
+        if header.sig != fourcc(*b"ARC1") {
+            return Err(ResBinErr::HeaderMismatch(header.sig));
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            size = header.size,
+            offs = header.offs,
+            cmp_size = header.cmp_size,
+            "read header"
+        );
+
+        // compressed data, which must lie within the file before it's worth allocating for
+        let file_len = fc
+            .seek(SeekFrom::End(0))
+            .map_err(|e| ResBinErr::FileRead(e))?;
+        if header.offs as u64 + header.cmp_size as u64 > file_len {
+            return Err(ResBinErr::CmpRead(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        }
+
+        let mut cmp = vec![0; header.cmp_size as usize];
+        fc.seek(SeekFrom::Start(header.offs as u64))
+            .and_then(|_| fc.read_exact(&mut cmp[..]))
+            .map_err(|e| ResBinErr::CmpRead(e))?;
+
+        decode(header.offs, &mut cmp[..]);
+        if get_u32_le(&cmp[..]).is_none() {
+            return Err(ResBinErr::CmpRead(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        }
+        let dcmp = decompress(&cmp[4..], header.size as usize)?;
+
+        // decompressed data
+        let mut dc = Cursor::new(&dcmp[..]);
+        let mut n = [0; 4];
+        dc.read_exact(&mut n).map_err(|e| ResBinErr::EntryRead(e))?;
+        let n = u32::from_le_bytes(n);
+        let table_len = (n as usize).checked_mul(size_of::<ResEntry>());
+        if !table_len.is_some_and(|len| len <= dcmp.len() - size_of::<u32>()) {
+            return Err(ResBinErr::EntryRead(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        }
+        let mut entdata = vec![ResEntry::zeroed(); n as usize];
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entries = n, "read entry table");
+
+        for ent in entdata.iter_mut() {
+            dc.read_exact(bytes_of_mut(ent))
+                .map_err(|e| ResBinErr::EntryRead(e))?;
+        }
+
+        check_offsets(&entdata[..], dcmp.len(), file_len)?;
+
+        // entries
+        let mut entries = HashMap::with_capacity(n as usize);
+        let mut renamed_paths = Vec::new();
+        for ent in entdata.iter() {
+            dc.set_position(ent.path_offs as u64);
+
+            let s = read_cstr_capped(&mut dc, PATH_MAX, SHIFT_JIS)
+                .map_err(|e| ResBinErr::PathName(*ent, e))?;
+            let mut path = PathBuf::from(s);
+
+            if entries.contains_key(&path) {
+                if dups == DuplicatePaths::Reject {
+                    return Err(ResBinErr::DuplicatePath(path));
+                }
+
+                let renamed = dedup_path(&entries, &path);
+
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    path = %path.display(),
+                    renamed = %renamed.display(),
+                    "duplicate entry"
+                );
+
+                renamed_paths.push((path.clone(), renamed.clone()));
+                path = renamed;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                path = %path.display(),
+                offs = ent.data_offs,
+                size = ent.size,
+                "read entry"
+            );
+
+            entries.insert(path, (*ent, Vec::new()));
+        }
+
+        Ok(ResBin {
+            header,
+            key,
+            entries,
+            renamed: renamed_paths,
+            deflated: HashMap::new(),
+            file: None,
+        })
+    }
+
+    /// Decodes and inflates the data of every entry from the archive buffer
+    #[cfg(not(feature = "parallel"))]
+    fn read_entries(&mut self, buf: &[u8]) -> Result<(), ResBinErr> {
+        self.read_entries_serial(buf)
+    }
+
+    /// Decodes and inflates the data of every entry from the archive buffer, spread across
+    /// threads
+    #[cfg(feature = "parallel")]
+    fn read_entries(&mut self, buf: &[u8]) -> Result<(), ResBinErr> {
+        self.entries
+            .par_iter_mut()
+            .try_for_each(|(path, (ent, data))| {
+                *data = read_entry(&mut Cursor::new(buf), ent, path)?;
+                Ok(())
+            })
+    }
+
+    #[cfg(any(test, not(feature = "parallel")))]
+    fn read_entries_serial(&mut self, buf: &[u8]) -> Result<(), ResBinErr> {
+        for (path, (ent, data)) in self.entries.iter_mut() {
+            *data = read_entry(&mut Cursor::new(buf), ent, path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the archive decodes correctly with `key`. An encrypted entry decrypts to a
+    /// size prefix and gzip stream, like an entry block, so the key is right if some entry
+    /// decrypts to a stream that inflates to its recorded size. Entries are tried in path order.
+    pub fn verify_key(&self, key: &[u8; KEY_SIZE]) -> Result<(), ResBinErr> {
+        let cipher = Cipher::new(&key[..]);
+        let mut paths = self.entries.keys().collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let data = self.data(path)?;
+
+            // the IV and first two blocks hold the size prefix and gzip magic, which rules
+            // out most entries without decrypting all of them
+            let Ok(head) = cipher.decrypt(&data[..data.len().min(24)]) else {
+                continue;
+            };
+            if head.get(4..6) != Some(&[0x1F, 0x8B][..]) {
+                continue;
+            }
+
+            let Ok(ddata) = cipher.decrypt(&data[..]) else {
+                continue;
+            };
+            let size = get_u32_le(&ddata[..]).unwrap_or_default() as usize;
+            if size <= ddata.len().saturating_mul(MAX_DEFLATE_RATIO)
+                && decompress(&ddata[4..], size).is_ok()
+            {
+                return Ok(());
+            }
+        }
+
+        Err(ResBinErr::KeyMismatch)
+    }
+
+    /// Reads the decryption key for this archive from the EXE, at the key offset of the release
+    /// it's identified as by `CtExeVersion::detect`. Unknown releases fail with `ExeVersion`,
+    /// and their key must be read from an explicit offset with `read_key`.
+    pub fn find_key(&self, ctexe: &str) -> Result<[u8; KEY_SIZE], ResBinErr> {
+        let exe = fs::read(ctexe).map_err(|e| ResBinErr::ExeRead(e))?;
+        CtExeVersion::detect(&exe[..], self)
+            .and_then(|v| v.key_in(&exe[..]))
+            .ok_or(ResBinErr::ExeVersion)
+    }
+
+    /// Decrypts a single file entry in place, using the key the archive was loaded with
+    pub fn decrypt(&mut self, path: &str) -> Result<(), ResBinErr> {
+        let ddata = Cipher::new(&self.key[..])
+            .decrypt(&self.data(Path::new(path))?[..])
+            .map_err(|e| ResBinErr::Crypt(PathBuf::from(path), e))?;
+        self.replace(path, ddata)
+    }
+
+    /// The archive header
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Reports entry counts and sizes grouped by extension, the largest entries and the overall
+    /// compression ratio
+    pub fn stats(&self) -> ArchiveStats {
+        let mut stats = ArchiveStats::default();
+        let mut size = 0;
+        let mut cmp_size = 0;
+
+        for (path, (ent, data)) in self.entries.iter() {
+            let ext = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            let es = stats.by_ext.entry(ext).or_default();
+            es.count += 1;
+            es.size += data.len();
+            es.cmp_size += ent.size as usize;
+
+            size += data.len();
+            cmp_size += ent.size as usize;
+            stats.largest.push((path.clone(), data.len()));
+        }
+
+        stats.largest.sort_by_key(|(_, size)| Reverse(*size));
+        stats.largest.truncate(STATS_LARGEST);
+
+        if size != 0 {
+            stats.ratio = cmp_size as f64 / size as f64;
+        }
+        stats.duplicates = self.renamed.clone();
+
+        stats
+    }
+
+    /// Lists every entry's path with the CRC32 and size of its decompressed data, sorted by
+    /// path, for comparing extractions and releases without diffing the data itself
+    pub fn manifest(&self) -> Result<Vec<(PathBuf, u32, usize)>, ResBinErr> {
+        let mut paths = self.entries.keys().collect::<Vec<_>>();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .map(|p| {
+                let data = self.data(p)?;
+                let mut crc = Crc::new();
+                crc.update(&data[..]);
+                Ok((p.clone(), crc.sum(), data.len()))
+            })
+            .collect()
+    }
+
+    /// Writes the manifest out as text, one `crc32 size path` line per entry with the CRC in
+    /// hex
+    pub fn write_manifest(&self, path: &str) -> Result<(), ResBinErr> {
+        let mut out = String::new();
+        for (p, crc, size) in self.manifest()? {
+            out.push_str(&format!("{:08x} {} {}\n", crc, size, p.display()));
+        }
+
+        fs::write(path, out).map_err(|e| ResBinErr::ManifestWrite(e))
+    }
+
+    /// Decompressed data for a single entry. Entries of an archive from `open` are only
+    /// available here once replaced or inserted; use `extract_one` for the rest.
+    pub fn get(&self, path: &str) -> Option<&[u8]> {
+        match self.entries.get(Path::new(path)) {
+            Some((ent, data)) if !self.on_disk(ent, data) => Some(&data[..]),
+            _ => None,
+        }
+    }
+
+    /// Paths of all entries, in no particular order
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.entries.keys().map(|p| p.as_path())
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the archive has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Reads and inflates a single entry, from disk if the archive came from `open`
+    pub fn extract_one(&self, inner_path: &str) -> Result<Vec<u8>, ResBinErr> {
+        self.data(Path::new(inner_path))
+            .map(|data| data.into_owned())
+    }
+
+    /// Whether an entry's data is still in the archive file rather than in memory
+    fn on_disk(&self, ent: &ResEntry, data: &[u8]) -> bool {
+        self.file.is_some() && data.is_empty() && ent.size != 0
+    }
+
+    /// An entry's decompressed data, reading it in if it's still on disk
+    fn data(&self, path: &Path) -> Result<Cow<'_, [u8]>, ResBinErr> {
+        let (ent, data) = self
+            .entries
+            .get(path)
+            .ok_or_else(|| ResBinErr::EntryPath(path.to_path_buf()))?;
+
+        match self.file.as_ref() {
+            Some(mut f) if self.on_disk(ent, data) => {
+                Ok(Cow::Owned(read_entry(&mut f, ent, path)?))
+            }
+            _ => Ok(Cow::Borrowed(&data[..])),
+        }
+    }
+
+    /// Swaps the decompressed data of an existing entry. Offsets are reassigned by `save`.
+    pub fn replace(&mut self, path: &str, data: Vec<u8>) -> Result<(), ResBinErr> {
+        let (ent, old) = self
+            .entries
+            .get_mut(Path::new(path))
+            .ok_or_else(|| ResBinErr::EntryPath(PathBuf::from(path)))?;
+
+        let cdata = deflate_entry(&data[..])?;
+        ent.size = stored_size(&cdata[..]);
+        *old = data;
+        self.deflated.insert(PathBuf::from(path), cdata);
+
+        Ok(())
+    }
+
+    /// Adds a new entry. Fails with `DuplicatePath` if the path is already taken.
+    pub fn insert(&mut self, path: &str, data: Vec<u8>) -> Result<(), ResBinErr> {
+        let path = archive_path(Path::new(path));
+        if self.entries.contains_key(&path) {
+            return Err(ResBinErr::DuplicatePath(path));
+        }
+
+        let cdata = deflate_entry(&data[..])?;
+        let mut ent = ResEntry::zeroed();
+        ent.size = stored_size(&cdata[..]);
+        self.entries.insert(path.clone(), (ent, data));
+        self.deflated.insert(path, cdata);
+
+        Ok(())
+    }
+
+    /// Dumps the contents of a single entry to file.
+    pub fn dump(&self, in_path: &str, out_path: &str) -> Result<(), ResBinErr> {
+        let data = self.data(Path::new(in_path))?;
+        let mut path = PathBuf::from(out_path);
+        path.push(in_path);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| ResBinErr::Dump(e))?;
+        }
+        fs::write(path.as_path(), &data[..]).map_err(|e| ResBinErr::Dump(e))?;
+
+        Ok(())
+    }
+
+    /// Dumps all files in resources.bin
+    pub fn dump_all(&self, out_path: &str) -> Result<(), ResBinErr> {
+        self.dump_all_with_progress(out_path, |_, _| {})
+    }
+
+    /// Dumps all files in resources.bin, calling `progress` with the number of entries done and
+    /// the total after each one
+    pub fn dump_all_with_progress(
+        &self,
+        out_path: &str,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(), ResBinErr> {
+        let total = self.entries.len();
+        for (i, p) in self.entries.keys().enumerate() {
+            if let Some(path) = p.to_str() {
+                self.dump(path, out_path)?;
+            }
+            progress(i + 1, total);
+        }
+
+        Ok(())
+    }
+
+    /// Dumps the files whose paths match a glob `pattern`, returning how many were written.
+    /// Patterns without a `/` are matched against file names alone, so `string_*.bin` picks out
+    /// those entries from every directory.
+    pub fn dump_matching(&self, pattern: &str, out_dir: &str) -> Result<usize, ResBinErr> {
+        let pat = Pattern::new(pattern).map_err(|e| ResBinErr::Pattern(e))?;
+        let by_name = !pattern.contains('/');
+
+        let mut n = 0;
+        for p in self.entries.keys() {
+            let target = match by_name {
+                true => p.file_name().map(Path::new),
+                false => Some(p.as_path()),
+            };
+            if !target.is_some_and(|t| pat.matches_path(t)) {
+                continue;
+            }
+
+            if let Some(path) = p.to_str() {
+                self.dump(path, out_dir)?;
+                n += 1;
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Rebuilds the archive and writes it to file. Entries are stored in path order, each
+    /// re-deflated and encoded at its new offset, followed by the entry table.
+    pub fn save(&self, path: &str) -> Result<(), ResBinErr> {
+        let mut paths = self.entries.keys().collect::<Vec<_>>();
+        paths.sort();
+
+        let mut buf = vec![0; size_of::<Header>()];
+        let mut entdata = Vec::with_capacity(paths.len());
+        let mut names = Vec::new();
+        let names_offs = size_of::<u32>() + paths.len() * size_of::<ResEntry>();
+
+        // entry data
+        for p in paths.iter() {
+            let data = self.data(p)?;
+            let data_offs = buf.len() as u32;
+            let cdata = match (data.is_empty(), self.deflated.get(*p)) {
+                (true, _) => Vec::new(),
+                (false, Some(cmp)) => encode_deflated(data.len(), cmp, data_offs),
+                (false, None) => encode_block(&data[..], data_offs)?,
+            };
+
+            entdata.push(ResEntry {
+                path_offs: (names_offs + names.len()) as u32,
+                data_offs,
+                size: cdata.len() as u32,
+            });
+
+            names.extend(encode_sjis(&p.to_string_lossy()));
+            names.push(0);
+            buf.extend(cdata);
+        }
+
+        // entry table
+        let mut table = (entdata.len() as u32).to_le_bytes().to_vec();
+        for ent in entdata.iter() {
+            table.extend(bytes_of(ent));
+        }
+        table.extend(names);
+
+        let offs = buf.len() as u32;
+        let cmp = encode_block(&table[..], offs)?;
+
+        // header
+        let mut header = Header {
+            sig: fourcc(*b"ARC1"),
+            size: table.len() as u32,
+            offs,
+            cmp_size: cmp.len() as u32,
+        };
+        decode(0, bytes_of_mut(&mut header));
+        buf[..size_of::<Header>()].copy_from_slice(bytes_of(&header));
+        buf.extend(cmp);
+
+        fs::write(path, &buf[..]).map_err(|e| ResBinErr::FileWrite(e))
+    }
+}
+
+impl Archive for ResBin {
+    /// Lists every entry, with its file extension as its kind. Entries of an archive from
+    /// `open` that haven't been read in report their stored size.
+    fn entries(&self) -> Vec<ArchiveEntry> {
+        self.entries
+            .iter()
+            .map(|(path, (ent, data))| ArchiveEntry {
+                name: path.to_string_lossy().into_owned(),
+                size: match self.on_disk(ent, data) {
+                    true => ent.size as usize,
+                    false => data.len(),
+                },
+                kind: path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    fn extract(&self, name: &str) -> Option<Vec<u8>> {
+        self.extract_one(name).ok()
+    }
+}
+
+/// Builds an archive from loose files
+#[derive(Debug, Default)]
+pub struct ResBinBuilder {
+    entries: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl ResBinBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects every file under a directory, keyed by its path relative to that directory
+    pub fn from_dir(dir: &str) -> Result<Self, ResBinErr> {
+        let mut builder = Self::new();
+        builder.add_dir(Path::new(dir), Path::new(dir))?;
+        Ok(builder)
+    }
+
+    /// Adds or replaces a file. Path separators are stored as forward slashes.
+    pub fn add(&mut self, path: &str, data: Vec<u8>) -> &mut Self {
+        self.entries.insert(archive_path(Path::new(path)), data);
+        self
+    }
+
+    /// Finishes the archive. Entry offsets and sizes are laid out when it is written.
+    pub fn build(self) -> ResBin {
+        let mut header = Header::zeroed();
+        header.sig = fourcc(*b"ARC1");
+
+        let entries = self
+            .entries
+            .into_iter()
+            .map(|(path, data)| (path, (ResEntry::zeroed(), data)))
+            .collect();
+
+        ResBin {
+            header,
+            key: [0; KEY_SIZE],
+            entries,
+            renamed: Vec::new(),
+            deflated: HashMap::new(),
+            file: None,
+        }
+    }
+
+    /// Recursively adds the files of `dir`, relative to `root`
+    fn add_dir(&mut self, root: &Path, dir: &Path) -> Result<(), ResBinErr> {
+        let rd = fs::read_dir(dir).map_err(|e| ResBinErr::DirRead(dir.to_path_buf(), e))?;
+
+        for de in rd {
+            let path = de
+                .map_err(|e| ResBinErr::DirRead(dir.to_path_buf(), e))?
+                .path();
+
+            if path.is_dir() {
+                self.add_dir(root, &path)?;
+            } else {
+                let data = fs::read(&path).map_err(|e| ResBinErr::FileRead(e))?;
+                let rel = path.strip_prefix(root).unwrap_or(&path);
+                self.entries.insert(archive_path(rel), data);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Known releases of the Chrono Trigger EXE
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CtExeVersion {
+    /// The Steam release the key offset was found in
+    Steam,
+}
+
+impl CtExeVersion {
+    /// Every release with a known key offset
+    pub const ALL: [Self; 1] = [CtExeVersion::Steam];
+
+    /// Identifies the release of an EXE by the key it holds for `resb`. A release matches when
+    /// the bytes at its key offset decrypt an entry of the archive, so an unknown or patched
+    /// EXE gives `None` rather than a key that would decrypt to garbage.
+    pub fn detect(exe: &[u8], resb: &ResBin) -> Option<Self> {
+        // Windows executables start with the DOS stub signature
+        if !exe.starts_with(b"MZ") {
+            return None;
+        }
+
+        Self::ALL
+            .into_iter()
+            .find(|v| v.key_in(exe).is_some_and(|k| resb.verify_key(&k).is_ok()))
+    }
+
+    /// The bytes at this release's key offset, if the EXE is long enough to hold them
+    fn key_in(self, exe: &[u8]) -> Option<[u8; KEY_SIZE]> {
+        let offs = usize::try_from(self.key_offset()).ok()?;
+        exe.get(offs..offs.checked_add(KEY_SIZE)?)?.try_into().ok()
+    }
+
+    /// Offset of the archive decryption key in this release
+    pub const fn key_offset(self) -> u64 {
+        match self {
+            CtExeVersion::Steam => KEY_OFFSET,
+        }
+    }
+}
+
+/// Reads the archive decryption key from the EXE at `offs`, for releases `ResBin::find_key`
+/// doesn't recognise
+pub fn read_key(ctexe: &str, offs: u64) -> Result<[u8; KEY_SIZE], ResBinErr> {
+    let buf = fs::read(ctexe).map_err(|e| ResBinErr::ExeRead(e))?;
+    let mut exe = Cursor::new(buf);
+    let mut key = [0; KEY_SIZE];
+
+    exe.set_position(offs);
+    exe.read_exact(&mut key)
+        .map_err(|e| ResBinErr::KeyRead(e))?;
+
+    Ok(key)
+}
+
+/// Writes the archive decryption key from the EXE out to a file, for use with
+/// `ResBin::load_with_key`. The key is read from `offs` as in `read_key`.
+pub fn extract_key(ctexe: &str, out: &str, offs: u64) -> Result<(), ResBinErr> {
+    let key = read_key(ctexe, offs)?;
+    fs::write(out, &key[..]).map_err(|e| ResBinErr::KeyWrite(e))
+}
+
+/// Finds the first free `stem~N.ext` variant of a path already in the entry table
+fn dedup_path(entries: &HashMap<PathBuf, (ResEntry, Vec<u8>)>, path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|i| path.with_file_name(format!("{}~{}{}", stem, i, ext)))
+        .find(|p| !entries.contains_key(p))
+        .unwrap()
+}
+
+/// Normalises a relative path to the archive's forward-slash form
+fn archive_path(path: &Path) -> PathBuf {
+    let parts = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>();
+
+    PathBuf::from(parts.join("/"))
+}
+
+/// Checks that every entry's path lies within the entry table and its data within the archive
+fn check_offsets(entdata: &[ResEntry], table_len: usize, file_len: u64) -> Result<(), ResBinErr> {
+    for (i, ent) in entdata.iter().enumerate() {
+        if ent.path_offs as usize >= table_len {
+            return Err(ResBinErr::OffsetOutOfRange {
+                entry: i,
+                offset: ent.path_offs,
+            });
+        }
+
+        if ent.data_offs as u64 + ent.size as u64 > file_len {
+            return Err(ResBinErr::OffsetOutOfRange {
+                entry: i,
+                offset: ent.data_offs,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads, decodes and inflates the data of a single entry
+fn read_entry<R: Read + Seek>(
+    fc: &mut R,
+    ent: &ResEntry,
+    path: &Path,
+) -> Result<Vec<u8>, ResBinErr> {
+    // empty files are stored without a size prefix or compressed stream
+    if ent.size == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut cdata = vec![0; ent.size as usize];
+
+    fc.seek(SeekFrom::Start(ent.data_offs as u64))
+        .and_then(|_| fc.read_exact(&mut cdata[..]))
+        .map_err(|e| ResBinErr::EntryDataRead(path.to_path_buf(), e))?;
+
+    decode(ent.data_offs, &mut cdata);
+    let size =
+        get_u32_le(&cdata[..]).ok_or_else(|| ResBinErr::EntryTruncated(path.to_path_buf()))?;
+    decompress(&cdata[4..], size as usize)
+}
+
+/// Whether a buffer starts with the encoded signature of a resources.bin header
+pub(crate) fn has_signature(data: &[u8]) -> bool {
+    let mut sig = match data.get(..4) {
+        Some(sig) => [sig[0], sig[1], sig[2], sig[3]],
+        None => return false,
+    };
+
+    decode(0, &mut sig[..]);
+    u32::from_le_bytes(sig) == fourcc(*b"ARC1")
+}
+
+/// Decodes a block of data
+fn decode(offs: u32, data: &mut [u8]) {
+    Keystream::new(offs).apply(data);
+}
+
+/// Decoding keystream, seeded by the offset of the data in the archive
+struct Keystream(u32);
+
+impl Keystream {
+    fn new(offs: u32) -> Self {
+        Keystream(0x19000000 + offs)
+    }
+
+    /// Decodes the next block of data, continuing from where the last block left off
+    fn apply(&mut self, data: &mut [u8]) {
+        // Decoding uses a common PRNG algorithm
+        data.iter_mut().for_each(|b| {
+            self.0 = self.0.wrapping_mul(0x41C64E6D).wrapping_add(12345);
+            *b = ((*b as u32) ^ self.0 >> 24) as u8;
+        });
+    }
+}
+
+/// Reader that decodes archive data with the keystream as it is read
+struct KeyReader<R: Read> {
+    inner: R,
+    keys: Keystream,
+}
+
+impl<R: Read> Read for KeyReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(out)?;
+        self.keys.apply(&mut out[..n]);
+        Ok(n)
+    }
+}
+
+/// Reader that decodes and inflates a single archive entry on the fly, without buffering the
+/// whole decompressed entry
+pub struct ArcEntryReader<R: Read> {
+    #[cfg(not(feature = "libz"))]
+    dec: GzDecoder<KeyReader<R>>,
+
+    #[cfg(feature = "libz")]
+    dec: libz::Inflater<KeyReader<R>>,
+
+    size: usize,
+}
+
+impl<R: Read> ArcEntryReader<R> {
+    /// Wraps a reader over the raw entry data, which was stored at `offs` in the archive
+    pub fn new(inner: R, offs: u32) -> Result<Self, ResBinErr> {
+        let mut rdr = KeyReader {
+            inner,
+            keys: Keystream::new(offs),
+        };
+
+        let mut prefix = [0; 4];
+        rdr.read_exact(&mut prefix[..])
+            .map_err(|e| ResBinErr::CmpRead(e))?;
+
+        Ok(ArcEntryReader {
+            #[cfg(not(feature = "libz"))]
+            dec: GzDecoder::new(rdr),
+
+            #[cfg(feature = "libz")]
+            dec: libz::Inflater::new(rdr, DeflateFormat::Gzip)?,
+
+            // the prefix was read in full, so this can't come up short
+            size: get_u32_le(&prefix[..]).unwrap_or_default() as usize,
+        })
+    }
+
+    /// Decompressed size of the entry, as recorded in its prefix
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl<R: Read> Read for ArcEntryReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.dec.read(out)
+    }
+}
+
+/// Framing of a deflate stream
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DeflateFormat {
+    /// zlib header and trailer
+    Zlib,
+
+    /// Bare deflate data with no header
+    Raw,
+
+    /// gzip header and trailer, as used by the archive
+    #[default]
+    Gzip,
+}
+
+impl DeflateFormat {
+    /// The zlib `windowBits` selecting this format, as taken by `compress::inflate`
+    pub fn window_bits(self) -> i32 {
+        match self {
+            DeflateFormat::Zlib => 15,
+            DeflateFormat::Raw => -15,
+            DeflateFormat::Gzip => 31,
+        }
+    }
+}
+
+/// Inflates zlib-compressed data
+fn decompress(data: &[u8], dcmp_size: usize) -> Result<Vec<u8>, ResBinErr> {
+    // decompression uses a custom window of 31 bits
+    decompress_with(data, dcmp_size, DeflateFormat::Gzip)
+}
+
+/// Inflates deflate-compressed data with the given stream framing
+pub fn decompress_with(
+    data: &[u8],
+    dcmp_size: usize,
+    format: DeflateFormat,
+) -> Result<Vec<u8>, ResBinErr> {
+    let dcmp = compress::inflate(data, dcmp_size, format.window_bits()).map_err(|e| match e {
+        InflateError::SizeMismatch { expected, got } => ResBinErr::SizeMismatch { expected, got },
+        InflateError::Stream(e) => ResBinErr::Decmp(e),
+        InflateError::WindowBits(_) => ResBinErr::Decmp(Z_STREAM_ERROR),
+    });
+
+    #[cfg(feature = "tracing")]
+    if let Err(ResBinErr::SizeMismatch { expected, got }) = dcmp {
+        tracing::warn!(expected, got, "decompressed size mismatch");
+    }
+
+    dcmp
+}
+
+/// Deflates data into a stream the archive can read back
+fn compress(data: &[u8]) -> Result<Vec<u8>, ResBinErr> {
+    compress_with(data, DeflateFormat::Gzip)
+}
+
+/// Deflates data with the given stream framing
+pub fn compress_with(data: &[u8], format: DeflateFormat) -> Result<Vec<u8>, ResBinErr> {
+    #[cfg(feature = "libz")]
+    return libz::compress(data, format);
+
+    #[cfg(not(feature = "libz"))]
+    return deflate(data, format);
+}
+
+/// Deflates data with flate2
+#[cfg_attr(feature = "libz", allow(dead_code))]
+fn deflate(data: &[u8], format: DeflateFormat) -> Result<Vec<u8>, ResBinErr> {
+    let level = Compression::default();
+    match format {
+        DeflateFormat::Zlib => {
+            let mut enc = ZlibEncoder::new(Vec::new(), level);
+            enc.write_all(data).and_then(|_| enc.finish())
+        }
+        DeflateFormat::Raw => {
+            let mut enc = DeflateEncoder::new(Vec::new(), level);
+            enc.write_all(data).and_then(|_| enc.finish())
+        }
+        DeflateFormat::Gzip => {
+            let mut enc = GzEncoder::new(Vec::new(), level);
+            enc.write_all(data).and_then(|_| enc.finish())
+        }
+    }
+    .map_err(|_| ResBinErr::Cmp(Z_DATA_ERROR))
+}
+
+/// zlib itself, for exactly the C library's behavior
+#[cfg(feature = "libz")]
+mod libz {
+    use libz_sys::{
+        deflate, deflateBound, deflateEnd, deflateInit2_, inflate, inflateEnd, inflateInit2_, uInt,
+        uLong, z_stream, zlibVersion, Bytef, Z_DEFAULT_COMPRESSION, Z_DEFAULT_STRATEGY, Z_DEFLATED,
+        Z_FINISH, Z_NO_FLUSH, Z_OK, Z_STREAM_END,
+    };
+
+    use std::{
+        ffi::c_int,
+        io::{self, Read},
+        mem::{size_of, MaybeUninit},
+        ptr::addr_of_mut,
+    };
+
+    use super::{DeflateFormat, ResBinErr};
+    use crate::compress::Z_BUF_ERROR;
+
+    /// Size of the buffer `Inflater` reads compressed data into
+    const STREAM_CHUNK: usize = 0x10000;
+
+    /// Reader that inflates a stream on the fly
+    pub(super) struct Inflater<R: Read> {
+        inner: R,
+        zs: Box<MaybeUninit<z_stream>>,
+        buf: Vec<u8>,
+        eof: bool,
+        done: bool,
+    }
+
+    impl<R: Read> Inflater<R> {
+        pub(super) fn new(inner: R, format: DeflateFormat) -> Result<Self, ResBinErr> {
+            // z_stream must not move once initialised, hence the box
+            let mut zs = Box::new(MaybeUninit::<z_stream>::zeroed());
+            let err = unsafe {
+                inflateInit2_(
+                    zs.as_mut_ptr(),
+                    format.window_bits(),
+                    zlibVersion(),
+                    size_of::<z_stream>() as c_int,
+                )
+            };
+            if err != Z_OK {
+                return Err(ResBinErr::Decmp(err));
+            }
+
+            Ok(Inflater {
+                inner,
+                zs,
+                buf: vec![0; STREAM_CHUNK],
+                eof: false,
+                done: false,
+            })
+        }
+    }
+
+    impl<R: Read> Read for Inflater<R> {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            if self.done || out.is_empty() {
+                return Ok(0);
+            }
+
+            let zs = self.zs.as_mut_ptr();
+
+            loop {
+                unsafe {
+                    if (*zs).avail_in == 0 && !self.eof {
+                        let n = self.inner.read(&mut self.buf[..])?;
+                        (*zs).next_in = self.buf.as_mut_ptr() as *mut Bytef;
+                        (*zs).avail_in = n as uInt;
+                        self.eof = n == 0;
+                    }
+
+                    (*zs).next_out = out.as_mut_ptr() as *mut Bytef;
+                    (*zs).avail_out = out.len() as uInt;
+                }
+
+                let err = unsafe { inflate(zs, Z_NO_FLUSH) };
+                let n = out.len() - unsafe { (*zs).avail_out } as usize;
+
+                match err {
+                    Z_STREAM_END => {
+                        self.done = true;
+                        return Ok(n);
+                    }
+                    Z_OK | Z_BUF_ERROR if n > 0 => return Ok(n),
+                    Z_OK | Z_BUF_ERROR if self.eof => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "compressed entry ended early",
+                        ))
+                    }
+                    Z_OK | Z_BUF_ERROR => continue,
+                    e => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("zlib decompression failed with code {}", e),
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    impl<R: Read> Drop for Inflater<R> {
+        fn drop(&mut self) {
+            unsafe {
+                inflateEnd(self.zs.as_mut_ptr());
+            }
+        }
+    }
+
+    pub(super) fn compress(data: &[u8], format: DeflateFormat) -> Result<Vec<u8>, ResBinErr> {
+        unsafe {
+            let zs_ = MaybeUninit::<z_stream>::zeroed();
+            let mut zs = zs_.assume_init();
+            let ver = zlibVersion();
+
+            let err = deflateInit2_(
+                addr_of_mut!(zs),
+                Z_DEFAULT_COMPRESSION,
+                Z_DEFLATED,
+                format.window_bits(),
+                8,
+                Z_DEFAULT_STRATEGY,
+                ver,
+                size_of::<z_stream>() as c_int,
+            );
+            if err != Z_OK {
+                return Err(ResBinErr::Cmp(err));
+            }
+
+            let mut cmp = vec![0; deflateBound(addr_of_mut!(zs), data.len() as uLong) as usize];
+
+            zs.next_in = data.as_ptr() as *mut Bytef;
+            zs.avail_in = data.len() as uInt;
+            zs.next_out = cmp.as_mut_ptr() as *mut Bytef;
+            zs.avail_out = cmp.len() as uInt;
+
+            let err = deflate(addr_of_mut!(zs), Z_FINISH);
+            if err != Z_STREAM_END {
+                deflateEnd(addr_of_mut!(zs));
+                return Err(ResBinErr::Cmp(err));
+            }
+
+            cmp.truncate(zs.total_out as usize);
+            deflateEnd(addr_of_mut!(zs));
+
+            Ok(cmp)
+        }
+    }
+}
+
+/// Deflates an entry's data, leaving empty entries empty
+fn deflate_entry(data: &[u8]) -> Result<Vec<u8>, ResBinErr> {
+    match data.is_empty() {
+        true => Ok(Vec::new()),
+        false => compress(data),
+    }
+}
+
+/// Size of an entry's deflated data once prefixed, as recorded in its `ResEntry`
+fn stored_size(cdata: &[u8]) -> u32 {
+    match cdata.is_empty() {
+        true => 0,
+        false => (size_of::<u32>() + cdata.len()) as u32,
+    }
+}
+
+/// Compresses and encodes a block of data to be stored at `offs`, prefixed with its
+/// decompressed size
+fn encode_block(data: &[u8], offs: u32) -> Result<Vec<u8>, ResBinErr> {
+    Ok(encode_deflated(data.len(), &compress(data)?[..], offs))
+}
+
+/// Encodes already deflated data to be stored at `offs`, prefixed with its decompressed `size`
+fn encode_deflated(size: usize, cdata: &[u8], offs: u32) -> Vec<u8> {
+    let mut block = (size as u32).to_be_bytes().to_vec();
+    block.extend(cdata);
+    decode(offs, &mut block[..]);
+
+    block
+}
+
+/// Renders a 4-byte signature as (lossy) ASCII, escaping any unprintable bytes
+fn sig_str(sig: u32) -> String {
+    sig.to_le_bytes()
+        .iter()
+        .flat_map(|b| ascii::escape_default(*b))
+        .map(char::from)
+        .collect()
+}
+
+/// Helper function to get an unsigned 32-bit value from the start of a buffer, if it holds one
+fn get_u32_le(buf: &[u8]) -> Option<u32> {
+    match buf {
+        [b0, b1, b2, b3, ..] => Some(u32::from_le_bytes([*b3, *b2, *b1, *b0])),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    /// Needs the game files, given by `KAJAR_RESBIN` and `KAJAR_CT_EXE`
+    #[test]
+    #[ignore]
+    fn test_resbin_extract() {
+        let resbin = std::env::var("KAJAR_RESBIN").unwrap();
+        let exe = std::env::var("KAJAR_CT_EXE").unwrap();
+        let mut resb = super::ResBin::load(&resbin, Some(&exe)).unwrap();
+        resb.decrypt("string_1.bin").unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        resb.dump("string_1.bin", dir.path().to_str().unwrap())
+            .unwrap();
+        assert!(dir.path().join("string_1.bin").is_file());
+    }
+
+    /// Gzips and encodes a block with its 4-byte size prefix, as stored at `offs`
+    fn encode_block(data: &[u8], offs: u32) -> Vec<u8> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(data).unwrap();
+        let mut block = (data.len() as u32).to_be_bytes().to_vec();
+        block.extend(enc.finish().unwrap());
+        super::decode(offs, &mut block[..]);
+        block
+    }
+
+    /// Assembles an encoded archive from a list of entries, along with a stand-in EXE
+    fn write_archive(
+        dir: &std::path::Path,
+        files: &[(&str, &[u8])],
+    ) -> (std::path::PathBuf, std::path::PathBuf) {
+        write_archive_with(dir, files, |_| ())
+    }
+
+    /// Assembles an archive like `write_archive`, letting `patch` change the entry table before
+    /// it is encoded
+    fn write_archive_with(
+        dir: &std::path::Path,
+        files: &[(&str, &[u8])],
+        patch: impl FnOnce(&mut Vec<u8>),
+    ) -> (std::path::PathBuf, std::path::PathBuf) {
+        use bytemuck::bytes_of_mut;
+        use std::{fs, io::Write};
+
+        let mut arc = vec![0; 16];
+        let mut table = (files.len() as u32).to_le_bytes().to_vec();
+        let mut paths = Vec::new();
+        let path_base = 4 + files.len() * 12;
+
+        for (path, data) in files.iter() {
+            let data_offs = arc.len() as u32;
+            let block = encode_block(data, data_offs);
+            let path_offs = (path_base + paths.len()) as u32;
+
+            table.extend(path_offs.to_le_bytes());
+            table.extend(data_offs.to_le_bytes());
+            table.extend((block.len() as u32).to_le_bytes());
+            paths.extend(crate::encoding::encode_sjis(path));
+            paths.push(0);
+            arc.extend(block);
+        }
+        table.extend(paths);
+        patch(&mut table);
+
+        let offs = arc.len() as u32;
+        let block = encode_block(&table[..], offs);
+        let mut header = super::Header {
+            sig: crate::fourcc(*b"ARC1"),
+            size: table.len() as u32,
+            offs,
+            cmp_size: block.len() as u32,
+        };
+        super::decode(0, bytes_of_mut(&mut header));
+        arc[..16].copy_from_slice(bytemuck::bytes_of(&header));
+        arc.extend(block);
+
+        let arc_path = dir.join("resources.bin");
+        fs::write(&arc_path, &arc[..]).unwrap();
+
+        // only the signature and key need to be present in the EXE
+        let exe_path = dir.join("Chrono Trigger.exe");
+        let mut exe = fs::File::create(&exe_path).unwrap();
+        exe.write_all(b"MZ").unwrap();
+        exe.set_len(super::KEY_OFFSET + 64).unwrap();
+
+        (arc_path, exe_path)
+    }
+
+    #[test]
+    fn test_duplicate_paths() {
+        use super::{DuplicatePaths, ResBin, ResBinErr};
+        use std::path::Path;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(
+            dir.path(),
+            &[
+                ("string_1.bin", b"first"),
+                ("string_2.bin", b"other"),
+                ("string_1.bin", b"second"),
+            ],
+        );
+        let arc = arc.to_str().unwrap();
+
+        let resb = ResBin::load(arc, None).unwrap();
+        assert_eq!(resb.entries.len(), 3);
+        assert_eq!(resb.entries[Path::new("string_2.bin")].1, b"other");
+
+        let mut copies = vec![
+            resb.get("string_1.bin").unwrap(),
+            resb.get("string_1~1.bin").unwrap(),
+        ];
+        copies.sort();
+        assert_eq!(copies, [&b"first"[..], &b"second"[..]]);
+        assert_eq!(
+            resb.stats().duplicates,
+            [(
+                Path::new("string_1.bin").to_path_buf(),
+                Path::new("string_1~1.bin").to_path_buf()
+            )]
+        );
+
+        match ResBin::load_with(arc, None, DuplicatePaths::Reject) {
+            Err(ResBinErr::DuplicatePath(p)) => assert_eq!(p, Path::new("string_1.bin")),
+            _ => panic!("duplicate path was not rejected"),
+        }
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        use super::ResBin;
+        use std::{collections::HashMap, path::PathBuf};
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(
+            dir.path(),
+            &[
+                ("string_1.bin", b"strings"),
+                ("field/maps/m000.bin", &[7; 300][..]),
+                ("empty.bin", b""),
+            ],
+        );
+
+        let contents = |resb: &ResBin| {
+            resb.entries
+                .iter()
+                .map(|(p, (_, data))| (p.clone(), data.clone()))
+                .collect::<HashMap<PathBuf, Vec<u8>>>()
+        };
+
+        let resb = ResBin::load(arc.to_str().unwrap(), None).unwrap();
+        let saved = dir.path().join("saved.bin");
+        resb.save(saved.to_str().unwrap()).unwrap();
+
+        let reloaded = ResBin::load(saved.to_str().unwrap(), None).unwrap();
+        assert_eq!(reloaded.entries.len(), 3);
+        assert_eq!(contents(&reloaded), contents(&resb));
+
+        // a second save of the same entries is byte-identical
+        let resaved = dir.path().join("resaved.bin");
+        reloaded.save(resaved.to_str().unwrap()).unwrap();
+        assert_eq!(
+            std::fs::read(saved).unwrap(),
+            std::fs::read(resaved).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        use super::ResBin;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(
+            dir.path(),
+            &[
+                ("string_1.bin", b"strings"),
+                ("string_2.BIN", b"more"),
+                ("field/maps/m000.map", &[7; 300][..]),
+                ("readme", b"hi"),
+            ],
+        );
+
+        let resb = ResBin::load(arc.to_str().unwrap(), None).unwrap();
+        let stats = resb.stats();
+        let stored = |path: &str| resb.entries[std::path::Path::new(path)].0.size as usize;
+
+        assert_eq!(stats.by_ext.len(), 3);
+        let bin = stats.by_ext["bin"];
+        assert_eq!((bin.count, bin.size), (2, 11));
+        assert_eq!(
+            bin.cmp_size,
+            stored("string_1.bin") + stored("string_2.BIN")
+        );
+        let map = stats.by_ext["map"];
+        assert_eq!((map.count, map.size), (1, 300));
+        assert_eq!(map.cmp_size, stored("field/maps/m000.map"));
+        assert_eq!((stats.by_ext[""].count, stats.by_ext[""].size), (1, 2));
+
+        assert_eq!(stats.largest.len(), 4);
+        assert_eq!(
+            stats.largest[0],
+            (std::path::PathBuf::from("field/maps/m000.map"), 300)
+        );
+
+        let cmp_size = stats.by_ext.values().map(|e| e.cmp_size).sum::<usize>();
+        assert_eq!(stats.ratio, cmp_size as f64 / 313.0);
+        assert!(stats.duplicates.is_empty());
+    }
+
+    #[test]
+    fn test_save_sjis_path() {
+        use super::ResBin;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(dir.path(), &[("クロノ/トリガー.bin", b"ct")]);
+        let mut resb = ResBin::load(arc.to_str().unwrap(), None).unwrap();
+        assert_eq!(resb.get("クロノ/トリガー.bin").unwrap(), b"ct");
+
+        resb.insert("クロノ.bin", b"crono".to_vec()).unwrap();
+        let saved = dir.path().join("saved.bin");
+        resb.save(saved.to_str().unwrap()).unwrap();
+
+        let reloaded = ResBin::load(saved.to_str().unwrap(), None).unwrap();
+        assert_eq!(reloaded.get("クロノ.bin").unwrap(), b"crono");
+        assert_eq!(reloaded.get("クロノ/トリガー.bin").unwrap(), b"ct");
+    }
+
+    #[test]
+    fn test_replace_insert() {
+        use super::{ResBin, ResBinErr};
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(dir.path(), &[("string_1.bin", b"strings")]);
+        let mut resb = ResBin::load(arc.to_str().unwrap(), None).unwrap();
+
+        resb.replace("string_1.bin", b"translated strings".to_vec())
+            .unwrap();
+        resb.insert("field/maps/m001.bin", b"new map".to_vec())
+            .unwrap();
+
+        assert!(matches!(
+            resb.replace("missing.bin", Vec::new()),
+            Err(ResBinErr::EntryPath(_))
+        ));
+        assert!(matches!(
+            resb.insert("string_1.bin", Vec::new()),
+            Err(ResBinErr::DuplicatePath(_))
+        ));
+        assert_eq!(resb.deflated.len(), 2);
+
+        let saved = dir.path().join("patched.bin");
+        resb.save(saved.to_str().unwrap()).unwrap();
+
+        let patched = ResBin::load(saved.to_str().unwrap(), None).unwrap();
+        assert_eq!(patched.get("string_1.bin").unwrap(), b"translated strings");
+        assert_eq!(patched.get("field/maps/m001.bin").unwrap(), b"new map");
+        assert_eq!(
+            patched.entries[std::path::Path::new("string_1.bin")].0.size,
+            resb.entries[std::path::Path::new("string_1.bin")].0.size
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        use super::ResBin;
+        use std::path::PathBuf;
+
+        let mut builder = super::ResBinBuilder::new();
+        builder.add("text/string_en.bin", b"en".to_vec());
+        builder.add("names.bin", b"names".to_vec());
+        builder.add("empty.bin", Vec::new());
+
+        let dir = tempfile::tempdir().unwrap();
+        let saved = dir.path().join("resources.bin");
+        builder.build().save(saved.to_str().unwrap()).unwrap();
+
+        let first = ResBin::load(saved.to_str().unwrap(), None).unwrap();
+        let second = ResBin::load(saved.to_str().unwrap(), None).unwrap();
+        let manifest = first.manifest().unwrap();
+        assert_eq!(manifest, second.manifest().unwrap());
+        assert_eq!(
+            manifest,
+            [
+                (PathBuf::from("empty.bin"), 0, 0),
+                (PathBuf::from("names.bin"), 0xF2334940, 5),
+                (PathBuf::from("text/string_en.bin"), 0xF359C142, 2),
+            ]
+        );
+
+        let out = dir.path().join("manifest.txt");
+        first.write_manifest(out.to_str().unwrap()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "00000000 0 empty.bin\nf2334940 5 names.bin\nf359c142 2 text/string_en.bin\n"
+        );
+    }
+
+    #[test]
+    fn test_open_extract_one() {
+        use super::{ResBin, ResBinErr};
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(
+            dir.path(),
+            &[("string_1.bin", b"strings"), ("string_2.bin", b"more")],
+        );
+
+        let mut resb = ResBin::open(arc.to_str().unwrap()).unwrap();
+        assert_eq!(resb.len(), 2);
+
+        let mut paths = resb.paths().collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["string_1.bin", "string_2.bin"]);
+
+        assert!(resb.get("string_1.bin").is_none());
+        assert_eq!(resb.extract_one("string_1.bin").unwrap(), b"strings");
+        assert_eq!(resb.extract_one("string_2.bin").unwrap(), b"more");
+        assert!(matches!(
+            resb.extract_one("string_3.bin"),
+            Err(ResBinErr::EntryPath(_))
+        ));
+
+        // replaced entries come from memory, the rest are still read from disk on save
+        resb.replace("string_2.bin", b"patched".to_vec()).unwrap();
+        let saved = dir.path().join("saved.bin");
+        resb.save(saved.to_str().unwrap()).unwrap();
+
+        let reloaded = ResBin::load(saved.to_str().unwrap(), None).unwrap();
+        assert_eq!(reloaded.get("string_1.bin").unwrap(), b"strings");
+        assert_eq!(reloaded.get("string_2.bin").unwrap(), b"patched");
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_load() {
+        use super::{DuplicatePaths, ResBin, KEY_SIZE};
+        use std::{fs, io::Cursor};
+
+        let files = (0..64)
+            .map(|i| {
+                (
+                    format!("string_{}.bin", i),
+                    format!("entry {} ", i).repeat(i * 50),
+                )
+            })
+            .collect::<Vec<_>>();
+        let files = files
+            .iter()
+            .map(|(p, d)| (p.as_str(), d.as_bytes()))
+            .collect::<Vec<_>>();
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(dir.path(), &files[..]);
+        let buf = fs::read(arc).unwrap();
+
+        let table = || {
+            ResBin::read(
+                &mut Cursor::new(&buf[..]),
+                [0; KEY_SIZE],
+                DuplicatePaths::default(),
+            )
+            .unwrap()
+        };
+
+        let mut serial = table();
+        serial.read_entries_serial(&buf[..]).unwrap();
+        let mut parallel = table();
+        parallel.read_entries(&buf[..]).unwrap();
+
+        assert_eq!(parallel.len(), 64);
+        for (path, (_, data)) in serial.entries.iter() {
+            assert_eq!(&parallel.entries[path].1, data);
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_load_mmap() {
+        use super::ResBin;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(
+            dir.path(),
+            &[
+                ("string_1.bin", b"strings"),
+                ("field/maps/m000.bin", b"map"),
+            ],
+        );
+        let arc = arc.to_str().unwrap();
+
+        let loaded = ResBin::load(arc, None).unwrap();
+        let mapped = ResBin::load_mmap(arc, None).unwrap();
+        assert_eq!(mapped.len(), loaded.len());
+        for path in loaded.paths() {
+            let path = path.to_str().unwrap();
+            assert_eq!(mapped.get(path), loaded.get(path));
+        }
+    }
+
+    /// Encrypts a size prefix and gzip stream of `data` with `key`, as an encrypted entry holds
+    fn encrypted_entry(key: &[u8; super::KEY_SIZE], data: &[u8], size: u32) -> Vec<u8> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(data).unwrap();
+        let mut block = size.to_be_bytes().to_vec();
+        block.extend(enc.finish().unwrap());
+        super::Cipher::new(&key[..]).encrypt(&block[..], [3; 8])
+    }
+
+    #[test]
+    fn test_exe_version() {
+        use super::{read_key, CtExeVersion, ResBin, ResBinErr, KEY_OFFSET, KEY_SIZE};
+        use std::fs;
+
+        let mut key = [0; KEY_SIZE];
+        key.iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8 ^ 0x5A);
+
+        let strings = b"Crono, Marle and Lucca".repeat(8);
+        let encrypted = encrypted_entry(&key, &strings[..], strings.len() as u32);
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, exe_path) = write_archive(dir.path(), &[("string_1.bin", &encrypted[..])]);
+        let (arc, exe_path) = (arc.to_str().unwrap(), exe_path.to_str().unwrap());
+        let resb = ResBin::open(arc).unwrap();
+
+        let mut exe = vec![0; KEY_OFFSET as usize + KEY_SIZE];
+        exe[..2].copy_from_slice(b"MZ");
+        exe[KEY_OFFSET as usize..].copy_from_slice(&key[..]);
+        assert_eq!(
+            CtExeVersion::detect(&exe[..], &resb),
+            Some(CtExeVersion::Steam)
+        );
+
+        // a patched key, a missing signature or a short EXE all make the release unknown
+        let mut patched = exe.clone();
+        patched[KEY_OFFSET as usize] ^= 1;
+        assert_eq!(CtExeVersion::detect(&patched[..], &resb), None);
+        assert_eq!(CtExeVersion::detect(&exe[1..], &resb), None);
+        assert_eq!(CtExeVersion::detect(&exe[..0x1000], &resb), None);
+
+        fs::write(exe_path, &exe[..]).unwrap();
+        assert_eq!(ResBin::load(arc, Some(exe_path)).unwrap().key, key);
+
+        // an unrecognised EXE needs its key offset given explicitly
+        fs::write(exe_path, &patched[..]).unwrap();
+        assert!(matches!(
+            ResBin::load(arc, Some(exe_path)),
+            Err(ResBinErr::ExeVersion)
+        ));
+        assert_eq!(read_key(exe_path, KEY_OFFSET).unwrap()[0], key[0] ^ 1);
+    }
+
+    #[test]
+    fn test_dump_nested() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(dir.path(), &[("field/maps/m000.bin", b"map")]);
+
+        let resb = super::ResBin::load(arc.to_str().unwrap(), None).unwrap();
+        resb.dump_all(out.path().to_str().unwrap()).unwrap();
+
+        let dumped = out.path().join("field").join("maps").join("m000.bin");
+        assert_eq!(std::fs::read(dumped).unwrap(), b"map");
+    }
+
+    #[test]
+    fn test_extract_key() {
+        use super::{ResBin, ResBinErr, KEY_OFFSET, KEY_SIZE};
+        use std::{
+            fs,
+            io::{Seek, SeekFrom, Write},
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, exe) = write_archive(dir.path(), &[("string_1.bin", b"strings")]);
+
+        let mut key = [0; KEY_SIZE];
+        key.iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8 ^ 0x5A);
+        let mut f = fs::OpenOptions::new().write(true).open(&exe).unwrap();
+        f.seek(SeekFrom::Start(KEY_OFFSET)).unwrap();
+        f.write_all(&key[..]).unwrap();
+
+        let key_path = dir.path().join("key.bin");
+        super::extract_key(
+            exe.to_str().unwrap(),
+            key_path.to_str().unwrap(),
+            KEY_OFFSET,
+        )
+        .unwrap();
+        let extracted: [u8; KEY_SIZE] = fs::read(&key_path).unwrap().try_into().unwrap();
+        assert_eq!(extracted, key);
+
+        let resb = ResBin::load_with_key(arc.to_str().unwrap(), extracted).unwrap();
+        assert_eq!(resb.get("string_1.bin").unwrap(), b"strings");
+
+        // no entry is encrypted, so no key can be verified
+        assert!(matches!(resb.verify_key(&key), Err(ResBinErr::KeyMismatch)));
+    }
+
+    #[test]
+    fn test_verify_key() {
+        use super::{ResBin, ResBinErr, KEY_SIZE};
+
+        let mut key = [0; KEY_SIZE];
+        key.iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b = i as u8 ^ 0x5A);
+
+        let strings = b"Crono, Marle and Lucca".repeat(8);
+        let encrypted = encrypted_entry(&key, &strings[..], strings.len() as u32);
+
+        let dir = tempfile::tempdir().unwrap();
+        let (arc, _) = write_archive(
+            dir.path(),
+            &[("a.txt", b"plain"), ("string_1.bin", &encrypted[..])],
+        );
+
+        for resb in [
+            ResBin::load_with_key(arc.to_str().unwrap(), [0; KEY_SIZE]).unwrap(),
+            ResBin::open(arc.to_str().unwrap()).unwrap(),
+        ] {
+            assert!(resb.verify_key(&key).is_ok());
+
+            let mut wrong = key;
+            wrong[0] ^= 1;
+            assert!(matches!(
+                resb.verify_key(&wrong),
+                Err(ResBinErr::KeyMismatch)
+            ));
+        }
+
+        // a stream that doesn't inflate to its prefix isn't accepted
+        let encrypted = encrypted_entry(&key, &strings[..], strings.len() as u32 + 1);
+        let (arc, _) = write_archive(dir.path(), &[("string_1.bin", &encrypted[..])]);
+        let resb = ResBin::load_with_key(arc.to_str().unwrap(), [0; KEY_SIZE]).unwrap();
+        assert!(matches!(resb.verify_key(&key), Err(ResBinErr::KeyMismatch)));
+    }
+
+    #[test]
+    fn test_arc_entry_reader() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::{Cursor, Write};
+
+        let payload = b"The Kajar Engine streams this entry. ".repeat(4096);
+        let offs = 0x1234;
+
+        // an encoded entry is a 4-byte size prefix followed by a gzip stream
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(&payload[..]).unwrap();
+        let mut ent = (payload.len() as u32).to_be_bytes().to_vec();
+        ent.extend(enc.finish().unwrap());
+        super::decode(offs, &mut ent[..]);
+
+        let mut buffered = ent.clone();
+        super::decode(offs, &mut buffered[..]);
+        let buffered = super::decompress(&buffered[4..], payload.len()).unwrap();
+
+        let mut rdr = super::ArcEntryReader::new(Cursor::new(&ent[..]), offs).unwrap();
+        let mut streamed = Vec::new();
+        std::io::copy(&mut rdr, &mut streamed).unwrap();
+
+        assert_eq!(rdr.size(), payload.len());
+        assert_eq!(streamed, buffered);
+        assert_eq!(streamed, payload);
+    }
+
+    #[test]
+    fn test_decompress_size_mismatch() {
+        use super::ResBinErr;
+
+        let payload = b"The size prefix says this entry is bigger than it is.".repeat(16);
+        let mut cmp = super::compress(&payload[..]).unwrap();
+
+        let out = super::decompress(&cmp.clone()[..], payload.len() + 10);
+        assert!(matches!(
+            out,
+            Err(ResBinErr::SizeMismatch { expected, got })
+                if expected == payload.len() + 10 && got == payload.len()
+        ));
+
+        // a cut-off stream never reaches its end
+        let half = cmp.len() / 2;
+        let out = super::decompress(&cmp[..half], payload.len());
+        assert!(matches!(out, Err(ResBinErr::Decmp(_))));
+    }
+
+    #[cfg(feature = "libz")]
+    #[test]
+    fn test_backends_match() {
+        use super::{libz, DeflateFormat};
+
+        let payload = b"Both backends must agree on every entry. ".repeat(256);
+
+        for format in [DeflateFormat::Zlib, DeflateFormat::Raw, DeflateFormat::Gzip] {
+            let streams = [
+                libz::compress(&payload[..], format).unwrap(),
+                super::deflate(&payload[..], format).unwrap(),
+            ];
+
+            for cmp in streams {
+                let out = super::decompress_with(&cmp[..], payload.len(), format).unwrap();
+                assert_eq!(out, payload);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompress_formats() {
+        use super::DeflateFormat;
+        use flate2::{
+            write::{DeflateEncoder, ZlibEncoder},
+            Compression,
+        };
+        use std::io::Write;
+
+        let payload = b"Raw and zlib streams inflate through the same routine.".repeat(64);
+
+        let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(&payload[..]).unwrap();
+        let mut zlib = enc.finish().unwrap();
+
+        let mut enc = DeflateEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(&payload[..]).unwrap();
+        let mut raw = enc.finish().unwrap();
+
+        let out = super::decompress_with(&zlib[..], payload.len(), DeflateFormat::Zlib);
+        assert_eq!(out.unwrap(), payload);
+
+        let out = super::decompress_with(&raw[..], payload.len(), DeflateFormat::Raw);
+        assert_eq!(out.unwrap(), payload);
+
+        // a raw stream has no zlib header to validate
+        let out = super::decompress_with(&raw[..], payload.len(), DeflateFormat::Zlib);
+        assert!(out.is_err());
+    }
+
+    #[test]
+    fn test_builder_from_dir() {
+        use std::{fs, path::Path};
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("field").join("maps")).unwrap();
+        fs::write(dir.path().join("string_1.bin"), b"strings").unwrap();
+        fs::write(
+            dir.path().join("field").join("maps").join("m000.bin"),
+            b"map",
+        )
+        .unwrap();
+
+        let resb = super::ResBinBuilder::from_dir(dir.path().to_str().unwrap())
+            .unwrap()
+            .build();
+
+        assert_eq!(resb.entries.len(), 2);
+        assert_eq!(resb.entries[Path::new("string_1.bin")].1, b"strings");
+        assert_eq!(resb.entries[Path::new("field/maps/m000.bin")].1, b"map");
+    }
+
+    #[test]
+    fn test_archive_entries() {
+        use crate::Archive;
+
+        let mut builder = super::ResBinBuilder::new();
+        builder
+            .add("string_1.bin", b"strings".to_vec())
+            .add("field/maps/m000.map", b"map".to_vec());
+        let resb = builder.build();
+
+        let mut entries = resb.entries();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "field/maps/m000.map");
+        assert_eq!((entries[0].size, &entries[0].kind[..]), (3, "map"));
+
+        assert_eq!(resb.extract("string_1.bin").unwrap(), b"strings");
+        assert!(resb.extract("missing.bin").is_none());
+    }
+
+    #[test]
+    fn test_dump_progress() {
+        let mut builder = super::ResBinBuilder::new();
+        for i in 0..5 {
+            builder.add(&format!("field/m{:03}.bin", i), vec![i; 16]);
+        }
+        let resb = builder.build();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut calls = Vec::new();
+        resb.dump_all_with_progress(dir.path().to_str().unwrap(), |done, total| {
+            calls.push((done, total))
+        })
+        .unwrap();
+
+        assert_eq!(calls, (1..=5).map(|i| (i, 5)).collect::<Vec<_>>());
+        assert_eq!(
+            std::fs::read(dir.path().join("field/m003.bin")).unwrap(),
+            [3; 16]
+        );
+    }
+
+    #[test]
+    fn test_dump_matching() {
+        let mut builder = super::ResBinBuilder::new();
+        builder.add("text/string_en.bin", b"en".to_vec());
+        builder.add("text/string_jp.bin", b"jp".to_vec());
+        builder.add("text/names.bin", b"names".to_vec());
+        builder.add("field/string_map.dat", b"map".to_vec());
+        let resb = builder.build();
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().to_str().unwrap();
+        assert_eq!(resb.dump_matching("string_*.bin", out).unwrap(), 2);
+        assert_eq!(
+            std::fs::read(dir.path().join("text/string_jp.bin")).unwrap(),
+            b"jp"
+        );
+        assert!(!dir.path().join("text/names.bin").exists());
+        assert!(!dir.path().join("field").exists());
+
+        assert_eq!(resb.dump_matching("field/*", out).unwrap(), 1);
+        assert_eq!(resb.dump_matching("*.png", out).unwrap(), 0);
+        assert!(matches!(
+            resb.dump_matching("[", out),
+            Err(super::ResBinErr::Pattern(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_entry() {
+        use bytemuck::Zeroable;
+        use std::{io::Cursor, path::Path};
+
+        let mut ent = super::ResEntry::zeroed();
+        ent.data_offs = 4;
+
+        let mut fc = Cursor::new(vec![0; 4]);
+        let data = super::read_entry(&mut fc, &ent, Path::new("empty.bin")).unwrap();
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_entry() {
+        use super::ResBinErr;
+        use bytemuck::Zeroable;
+        use std::{io::Cursor, path::Path};
+
+        assert_eq!(super::get_u32_le(&[1, 2]), None);
+        assert_eq!(super::get_u32_le(&[0, 0, 1, 0, 9]), Some(0x100));
+
+        // a 2-byte entry can't hold the 4-byte size prefix
+        let mut ent = super::ResEntry::zeroed();
+        ent.data_offs = 4;
+        ent.size = 2;
+
+        let mut fc = Cursor::new(vec![0; 6]);
+        let err = super::read_entry(&mut fc, &ent, Path::new("short.bin")).unwrap_err();
+        assert!(matches!(&err, ResBinErr::EntryTruncated(p) if p == Path::new("short.bin")));
+        assert_eq!(
+            err.to_string(),
+            "short.bin is too short for its size prefix"
+        );
+    }
+
+    #[test]
+    fn test_truncated_table() {
+        use super::{ResBin, ResBinErr};
+        use bytemuck::{bytes_of_mut, Zeroable};
+        use std::fs;
+
+        let dir = tempfile::tempdir().unwrap();
+        let files: [(&str, &[u8]); 1] = [("a.txt", b"aaaa")];
+
+        // a 2-byte outer block can't hold the 4-byte size prefix
+        let (arc, _) = write_archive(dir.path(), &files);
+        let mut buf = fs::read(&arc).unwrap();
+        let mut header = super::Header::zeroed();
+        bytes_of_mut(&mut header).copy_from_slice(&buf[..16]);
+        super::decode(0, bytes_of_mut(&mut header));
+        header.cmp_size = 2;
+        super::decode(0, bytes_of_mut(&mut header));
+        buf[..16].copy_from_slice(bytemuck::bytes_of(&header));
+        fs::write(&arc, &buf[..]).unwrap();
+        assert!(matches!(
+            ResBin::load_with_key(arc.to_str().unwrap(), [0; 64]),
+            Err(ResBinErr::CmpRead(_))
+        ));
+
+        // a table too short for its entry count
+        let (arc, _) = write_archive_with(dir.path(), &files, |t| t.truncate(2));
+        assert!(matches!(
+            ResBin::load_with_key(arc.to_str().unwrap(), [0; 64]),
+            Err(ResBinErr::EntryRead(_))
+        ));
+    }
+
+    #[test]
+    fn test_oversized_counts() {
+        use super::{ResBin, ResBinErr};
+        use bytemuck::{bytes_of_mut, Zeroable};
+        use std::fs;
+
+        let dir = tempfile::tempdir().unwrap();
+        let files: [(&str, &[u8]); 1] = [("a.txt", b"aaaa")];
+
+        // a compressed table running past the end of the file is rejected before allocating
+        let (arc, _) = write_archive(dir.path(), &files);
+        let mut buf = fs::read(&arc).unwrap();
+        let mut header = super::Header::zeroed();
+        bytes_of_mut(&mut header).copy_from_slice(&buf[..16]);
+        super::decode(0, bytes_of_mut(&mut header));
+        header.cmp_size = u32::MAX;
+        super::decode(0, bytes_of_mut(&mut header));
+        buf[..16].copy_from_slice(bytemuck::bytes_of(&header));
+        fs::write(&arc, &buf[..]).unwrap();
+        assert!(matches!(
+            ResBin::load_with_key(arc.to_str().unwrap(), [0; 64]),
+            Err(ResBinErr::CmpRead(_))
+        ));
+
+        // as is an entry count the table can't hold
+        let (arc, _) = write_archive_with(dir.path(), &files, |t| {
+            t[..4].copy_from_slice(&u32::MAX.to_le_bytes())
+        });
+        assert!(matches!(
+            ResBin::load_with_key(arc.to_str().unwrap(), [0; 64]),
+            Err(ResBinErr::EntryRead(_))
+        ));
+    }
+
+    #[test]
+    fn test_offset_out_of_range() {
+        use super::{ResBin, ResBinErr};
+
+        let dir = tempfile::tempdir().unwrap();
+        let files: [(&str, &[u8]); 2] = [("a.txt", b"aaaa"), ("b.txt", b"bbbb")];
+
+        // the second entry's data offset, 4 bytes into its 12-byte record
+        let (arc, _) = write_archive_with(dir.path(), &files, |t| {
+            t[20..24].copy_from_slice(&0x10_0000u32.to_le_bytes())
+        });
+        let Err(err) = ResBin::load_with_key(arc.to_str().unwrap(), [0; 64]) else {
+            panic!("loaded an archive with an out of range offset");
+        };
+        assert!(matches!(
+            err,
+            ResBinErr::OffsetOutOfRange {
+                entry: 1,
+                offset: 0x10_0000
+            }
+        ));
+        assert_eq!(err.to_string(), "entry 1 has out of range offset 0x100000");
+
+        // the first entry's path offset
+        let (arc, _) = write_archive_with(dir.path(), &files, |t| {
+            t[4..8].copy_from_slice(&0xFFFFu32.to_le_bytes())
+        });
+        assert!(matches!(
+            ResBin::open(arc.to_str().unwrap()),
+            Err(ResBinErr::OffsetOutOfRange {
+                entry: 0,
+                offset: 0xFFFF
+            })
+        ));
+
+        let (arc, _) = write_archive_with(dir.path(), &files, |_| ());
+        assert!(ResBin::load_with_key(arc.to_str().unwrap(), [0; 64]).is_ok());
+    }
+
+    #[test]
+    fn test_err_display() {
+        use super::ResBinErr;
+        use std::{error::Error, io, path::PathBuf};
+
+        let err = ResBinErr::HeaderMismatch(crate::fourcc(*b"ARC0"));
+        assert_eq!(
+            err.to_string(),
+            "bad archive signature: expected ARC1, got ARC0"
+        );
+        assert!(err.source().is_none());
+
+        let err = ResBinErr::EntryDataRead(
+            PathBuf::from("field/maps/m000.bin"),
+            io::Error::from(io::ErrorKind::UnexpectedEof),
+        );
+        assert_eq!(
+            err.to_string(),
+            "unable to read data for field/maps/m000.bin"
+        );
+        assert!(err.source().unwrap().is::<io::Error>());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_header_json() {
+        use super::{Header, ResEntry};
+        use bytemuck::{bytes_of, Zeroable};
+
+        let hdr = Header::zeroed();
+        let json = hdr.to_json();
+        assert!(json.contains("\"cmp_size\": 0"));
+        let back = serde_json::from_str::<Header>(&json).unwrap();
+        assert_eq!(bytes_of(&back), bytes_of(&hdr));
+
+        let mut ent = ResEntry::zeroed();
+        ent.data_offs = 0x40;
+        let back = serde_json::from_str::<ResEntry>(&serde_json::to_string(&ent).unwrap());
+        assert_eq!(back.unwrap().data_offs, 0x40);
+    }
+
+    #[test]
+    fn test_sig_str() {
+        assert_eq!(super::sig_str(crate::fourcc(*b"ARC1")), "ARC1");
+        assert_eq!(super::sig_str(0x00FF4241), "AB\\xff\\x00");
+    }
+}
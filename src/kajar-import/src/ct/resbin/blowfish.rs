@@ -1,11 +1,10 @@
-use bytemuck::bytes_of_mut;
+use std::{error, fmt};
 
-use std::{
-    fs,
-    io::{self, Cursor, Read},
-};
+/// Number of key bytes fed into the key schedule
+const KEY_LEN: usize = 8;
 
-const KEY_OFFSET: u64 = 0x398EE8;
+/// Size of a cipher block in bytes
+const BLOCK_SIZE: usize = 8;
 
 /// Blowfish P init table
 const P: [u32; 18] = [
@@ -149,10 +148,19 @@ const S: [u32; 1024] = [
 /// Decryption error
 #[derive(Debug)]
 pub enum CryptErr {
-    FileRead(io::Error),
-    KeyRead(io::Error),
+    Length(usize),
 }
 
+impl fmt::Display for CryptErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptErr::Length(n) => write!(f, "{} bytes is too short for an encrypted entry", n),
+        }
+    }
+}
+
+impl error::Error for CryptErr {}
+
 /// Blowfish cipher
 #[derive(Debug)]
 pub struct Cipher {
@@ -160,88 +168,122 @@ pub struct Cipher {
 }
 
 impl Cipher {
-    /// Constructs a new Blowfish cipher, given an input file containing the key
-    pub fn new(path: &str) -> Result<Self, CryptErr> {
-        // key retrieval
-        let mut exe = Cursor::new(fs::read(path).map_err(|e| CryptErr::FileRead(e))?);
-        let mut key = [0; 64];
-        exe.set_position(KEY_OFFSET);
-        exe.read_exact(bytes_of_mut(&mut key))
-            .map_err(|e| CryptErr::KeyRead(e))?;
-
+    /// Constructs a new Blowfish cipher from the key read out of the EXE
+    pub fn new(key: &[u8]) -> Self {
         // This variant of Blowfish copies a precalculated S table into the buffer starting at offset 18 (0x12)
         let mut buf = [0u32; 1042];
+        buf[..18].copy_from_slice(&P);
         buf[18..].copy_from_slice(&S);
 
         let mut l = 0;
-        for i in 0..18 {
-            let xor = u32::from_le_bytes([
-                key[((l as usize) + 3) % 8],
-                key[((l as usize) + 2) % 8],
-                key[((l as usize) + 1) % 8],
-                key[l as usize],
+        for b in buf[..18].iter_mut() {
+            let xor = u32::from_be_bytes([
+                key[l % KEY_LEN],
+                key[(l + 1) % KEY_LEN],
+                key[(l + 2) % KEY_LEN],
+                key[(l + 3) % KEY_LEN],
             ]);
-            buf[i] = xor ^ P[i];
-            l = (l + 4) % 8;
+            *b ^= xor;
+            l = (l + 4) % KEY_LEN;
         }
 
-        l = 0;
-        let mut r = 0;
-        for i in (0..18).step_by(2) {
-            encrypt(&mut buf, l, r);
+        let (mut l, mut r) = (0, 0);
+        for i in (0..1042).step_by(2) {
+            (l, r) = encrypt(&buf, l, r);
             buf[i] = l;
             buf[i + 1] = r;
         }
 
-        for i in (18..1042).step_by(2) {
-            encrypt(&mut buf, l, r);
-            buf[i] = l;
-            buf[i + 1] = r;
+        Cipher { buf }
+    }
+
+    /// Decrypts an entry in CBC mode. The first block is the XOR-masked IV and is stripped
+    /// from the output; any trailing partial block is passed through as-is.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptErr> {
+        if data.len() < BLOCK_SIZE {
+            return Err(CryptErr::Length(data.len()));
         }
 
-        Ok(Cipher { buf })
-    }
+        let mut iv = [0; BLOCK_SIZE];
+        iv.copy_from_slice(&data[..BLOCK_SIZE]);
+        header_magic(&mut iv);
+
+        let mut out = data[BLOCK_SIZE..].to_vec();
+        for block in out.chunks_exact_mut(BLOCK_SIZE) {
+            let l = u32::from_be_bytes([block[0], block[1], block[2], block[3]]);
+            let r = u32::from_be_bytes([block[4], block[5], block[6], block[7]]);
+            let next_iv = [
+                block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7],
+            ];
 
-    /*/// Decrypt given data
-    pub fn decrypt(&self, data: &[u8]) -> Vec<u8> {
-        let mut header = &data[0..7].to_vec();
-        let mut data = &data[8..].to_vec();
+            let (l, r) = decrypt(&self.buf, l, r);
+            block[..4].copy_from_slice(&l.to_be_bytes());
+            block[4..].copy_from_slice(&r.to_be_bytes());
+            block.iter_mut().zip(iv.iter()).for_each(|(b, v)| *b ^= v);
 
-        header_magic(&mut header);
+            iv = next_iv;
+        }
+
+        Ok(out)
     }
 
-    /// Decryption stage 1
-    fn stage1<'h, 'd>(&self, header: &'h mut [u8], data: &'d mut [u8]) {
-        let mut out_buf = [0; 8];
+    /// Encrypts data in CBC mode under `iv`, in the layout `decrypt` reads
+    #[cfg(test)]
+    pub(crate) fn encrypt(&self, data: &[u8], iv: [u8; BLOCK_SIZE]) -> Vec<u8> {
+        let mut out = iv.to_vec();
+        header_magic(&mut out[..]);
+
+        let mut prev = iv;
+        let mut blocks = data.chunks_exact(BLOCK_SIZE);
+        for block in blocks.by_ref() {
+            let mut x = [0; BLOCK_SIZE];
+            x.iter_mut()
+                .zip(block.iter().zip(prev.iter()))
+                .for_each(|(x, (b, p))| *x = b ^ p);
 
-        for (i, _) in (0..(data.len() >> 2)).step_by(8).enumerate() {
-            //let chunk =
+            let (l, r) = encrypt(
+                &self.buf,
+                u32::from_be_bytes([x[0], x[1], x[2], x[3]]),
+                u32::from_be_bytes([x[4], x[5], x[6], x[7]]),
+            );
+            prev[..4].copy_from_slice(&l.to_be_bytes());
+            prev[4..].copy_from_slice(&r.to_be_bytes());
+            out.extend(prev);
         }
-    }*/
+        out.extend_from_slice(blocks.remainder());
+
+        out
+    }
 }
 
+/// Blowfish round function, looking up the four S boxes that follow P in the buffer
 fn round(buf: &[u32], x: u32) -> u32 {
-    ((buf[0][(x >> 24) as usize].wrapping_add(buf[1][((x >> 16) & 0xff) as usize]))
-        ^ buf[2][((x >> 8) & 0xff) as usize])
-        .wrapping_add(buf[3][(x & 0xff) as usize])
+    ((buf[18 + (x >> 24) as usize].wrapping_add(buf[274 + ((x >> 16) & 0xff) as usize]))
+        ^ buf[530 + ((x >> 8) & 0xff) as usize])
+        .wrapping_add(buf[786 + (x & 0xff) as usize])
 }
 
-/// Initialises the encryption tables based on the key given at construction
-fn encrypt(buf: &mut [u32], mut l: u32, mut r: u32) {
-    let mut tmp = 0;
-    for b in buf[..16].iter() {
-        tmp = (l ^ *b) as usize;
-        l = buf[(tmp >> 24).wrapping_add(18)].wrapping_add(
-            buf[(tmp >> 16 & 255).wrapping_add(274)].wrapping_add(
-                buf[(tmp >> 8 & 255).wrapping_add(530)]
-                    .wrapping_add(buf[(tmp & 255).wrapping_add(786)]),
-            ),
-        ) ^ r;
-        r = tmp as u32;
+/// Encrypts a single block, also used to initialise the tables based on the key given at
+/// construction
+fn encrypt(buf: &[u32], mut l: u32, mut r: u32) -> (u32, u32) {
+    for p in buf[..16].iter() {
+        l ^= p;
+        r ^= round(buf, l);
+        (l, r) = (r, l);
     }
 
-    l = buf[17] ^ (tmp as u32);
-    r = buf[16] ^ l;
+    (r ^ buf[17], l ^ buf[16])
+}
+
+/// Decrypts a single block
+fn decrypt(buf: &[u32], mut l: u32, mut r: u32) -> (u32, u32) {
+    for p in buf[2..18].iter().rev() {
+        l ^= p;
+        r ^= round(buf, l);
+        (l, r) = (r, l);
+    }
+
+    (r ^ buf[0], l ^ buf[1])
 }
 
 /// XORs the encrypted header
@@ -259,9 +301,37 @@ fn header_magic(header: &mut [u8]) {
 #[cfg(test)]
 mod tests {
     #[test]
-    fn test_cipher_init() {
-        let c =
-            super::Cipher::new("/home/admin/Documents/GitHub/KajarEngine/utils/Chrono Trigger.exe");
-        println!("{:?}", c);
+    fn test_cipher_vectors() {
+        // Schneier's reference vectors
+        let vectors: [(u64, u64, u64); 3] = [
+            (0x0000000000000000, 0x0000000000000000, 0x4EF997456198DD78),
+            (0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0x51866FD5B85ECB8A),
+            (0x0123456789ABCDEF, 0x1111111111111111, 0x61F9C3802281B096),
+        ];
+
+        for (key, pt, ct) in vectors {
+            let c = super::Cipher::new(&key.to_be_bytes());
+            let (l, r) = super::encrypt(&c.buf, (pt >> 32) as u32, pt as u32);
+            assert_eq!(((l as u64) << 32) | r as u64, ct);
+
+            let (l, r) = super::decrypt(&c.buf, l, r);
+            assert_eq!(((l as u64) << 32) | r as u64, pt);
+        }
+    }
+
+    #[test]
+    fn test_cbc_decrypt() {
+        let c = super::Cipher::new(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let pt = b"string_1.bin stays encrypted!!!!";
+
+        let data = c.encrypt(pt, [9, 8, 7, 6, 5, 4, 3, 2]);
+        assert_eq!(data.len(), 8 + pt.len());
+        assert_eq!(c.decrypt(&data[..]).unwrap(), pt);
+        assert!(c.decrypt(&data[..7]).is_err());
+
+        // a trailing partial block passes through
+        let data = c.encrypt(b"0123456789", [0; 8]);
+        assert_eq!(&data[16..], b"89");
+        assert_eq!(c.decrypt(&data[..]).unwrap(), b"0123456789");
     }
 }
@@ -0,0 +1,59 @@
+use encoding_rs::{Encoding, SHIFT_JIS};
+
+/// Decodes a Shift-JIS string, as used for resource paths and names. Strings that aren't valid
+/// Shift-JIS are decoded as Latin-1 instead.
+pub fn decode_sjis(bytes: &[u8]) -> String {
+    decode(bytes, SHIFT_JIS)
+}
+
+/// Encodes a string as Shift-JIS, the reverse of `decode_sjis`. Strings Shift-JIS can't
+/// represent are encoded as Latin-1 where possible, and as UTF-8 otherwise.
+pub fn encode_sjis(s: &str) -> Vec<u8> {
+    let (bytes, _, unmappable) = SHIFT_JIS.encode(s);
+    if !unmappable {
+        return bytes.into_owned();
+    }
+
+    match s.chars().all(|c| (c as u32) < 0x100) {
+        true => s.chars().map(|c| c as u8).collect(),
+        false => s.as_bytes().to_vec(),
+    }
+}
+
+/// Decodes a string with `encoding`, falling back to Latin-1
+pub(crate) fn decode(bytes: &[u8], encoding: &'static Encoding) -> String {
+    // ASCII reads the same in every encoding we deal with
+    if bytes.is_ascii() {
+        return bytes.iter().map(|c| *c as char).collect();
+    }
+
+    match encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+        Some(s) => s.into_owned(),
+        None => bytes.iter().map(|c| *c as char).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_sjis, encode_sjis};
+
+    #[test]
+    fn test_decode_sjis() {
+        assert_eq!(decode_sjis(b"se/bgm01.sab"), "se/bgm01.sab");
+        assert_eq!(
+            decode_sjis(b"\x83\x4e\x83\x8d\x83\x6d\x81\x45\x83\x67\x83\x8a\x83\x4b\x81\x5b"),
+            "クロノ・トリガー"
+        );
+        assert_eq!(decode_sjis(b"\x8e\x9e\x8a\xd4/\xb1"), "時間/ｱ");
+        assert_eq!(decode_sjis(b"\xe9t\xe9"), "été");
+    }
+
+    #[test]
+    fn test_encode_sjis() {
+        for s in ["se/bgm01.sab", "クロノ・トリガー", "時間/ｱ", "été"] {
+            assert_eq!(decode_sjis(&encode_sjis(s)), s);
+        }
+        assert_eq!(encode_sjis("クロノ"), b"\x83\x4e\x83\x8d\x83\x6d");
+        assert_eq!(encode_sjis("été"), b"\xe9t\xe9");
+    }
+}
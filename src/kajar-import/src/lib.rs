@@ -1,4 +1,4 @@
-use bytes::Buf;
+use encoding_rs::Encoding;
 
 use nom::{
     character::complete::multispace0, combinator::value, error::ParseError, sequence::delimited,
@@ -10,44 +10,99 @@ use std::{
     io::{self, Read},
 };
 
+pub mod ai;
+pub mod audio;
+pub mod binary_dialogue;
 pub mod cc;
+pub mod color;
+pub mod compress;
 pub mod ct;
+pub mod encoding;
+pub mod hca;
+pub mod markup;
+pub mod sniff;
 
 #[cfg(feature = "ct_win")]
-pub mod
+pub mod sead;
+
 /// Converts a 4-byte string into a 32-bit big endian integer.
-/// Byte strings longer than 4 bytes are truncated.
+/// Byte strings longer than 4 bytes are truncated, and shorter ones fail to compile.
 #[macro_export]
 macro_rules! tag {
-    ($b4: literal) => {
+    ($b4: literal) => {{
+        const _: () = assert!($b4.len() >= 4, "tag! needs at least 4 bytes");
         u32::from_be_bytes([$b4[3], $b4[2], $b4[1], $b4[0]])
-    };
+    }};
+}
+
+/// Converts a 4-byte string into a 32-bit integer, the same way as `tag!`
+pub const fn fourcc(tag: [u8; 4]) -> u32 {
+    u32::from_le_bytes(tag)
 }
 
 /// Image import/export functionality
-pub trait Image {
+pub trait Image: Sized {
     type ImageError;
 
     /// Loads in an image file
-    fn load(path: &str) -> Result<Self, ImageError>;
+    fn load(path: &str) -> Result<Self, Self::ImageError>;
 
     /// Saves the imported image to a PNG file
-    fn save_png(&self, path: &str) -> Result<(), ImageError> {
+    fn save_png(&self, path: &str) -> Result<(), Self::ImageError>;
+}
+
+/// A file stored inside an archive
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArchiveEntry {
+    pub name: String,
+
+    /// Size of the entry's data in bytes
+    pub size: usize,
+
+    /// Format-specific type of the entry, or "" if the format has none
+    pub kind: String,
 }
 
-/// Reads a null-terminated string from a buffer
-pub fn read_cstr(mut buf: impl Read) -> io::Result<String> {
-    let mut s = String::new();
+/// Listing and extraction common to every archive format
+pub trait Archive {
+    /// Lists the files in the archive
+    fn entries(&self) -> Vec<ArchiveEntry>;
+
+    /// Reads out a file's data by name
+    fn extract(&self, name: &str) -> Option<Vec<u8>>;
+}
+
+/// Reads a null-terminated string from a buffer, decoding it with `encoding`. Strings that
+/// aren't valid in `encoding` are decoded as Latin-1 instead.
+pub fn read_cstr(buf: impl Read, encoding: &'static Encoding) -> io::Result<String> {
+    read_cstr_capped(buf, usize::MAX, encoding)
+}
+
+/// Reads a null-terminated string of at most `max` bytes, not counting the terminator, as
+/// `read_cstr` does. Fails with `InvalidData` once `max` bytes pass without a terminator, so a bad
+/// offset into binary data can't run on through the rest of the buffer.
+pub fn read_cstr_capped(
+    mut buf: impl Read,
+    max: usize,
+    encoding: &'static Encoding,
+) -> io::Result<String> {
+    let mut s = Vec::new();
+    let mut c = [0];
     loop {
-        let c = buf.get_u8();
-        if c != 0 {
-            s.push(c as char);
-        } else {
+        buf.read_exact(&mut c)?;
+        if c[0] == 0 {
             break;
         }
+        if s.len() == max {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no string terminator within {} bytes", max),
+            ));
+        }
+        s.push(c[0]);
     }
 
-    Ok(s)
+    Ok(encoding::decode(&s[..], encoding))
 }
 
 /// A combinator that takes a parser `inner` and produces a parser that also consumes both leading and
@@ -60,3 +115,44 @@ where
 {
     delimited(multispace0, inner, multispace0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{fourcc, read_cstr, read_cstr_capped};
+
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+    use std::io::{self, Cursor};
+
+    #[test]
+    fn test_read_cstr() {
+        let mut buf = Cursor::new(b"\x83\x4e\x83\x8d\x83\x6d\0menu\0\xe9t\xe9\0\x83".to_vec());
+        assert_eq!(read_cstr(&mut buf, SHIFT_JIS).unwrap(), "クロノ");
+        assert_eq!(read_cstr(&mut buf, SHIFT_JIS).unwrap(), "menu");
+
+        // not valid Shift-JIS, so read as Latin-1
+        assert_eq!(read_cstr(&mut buf, SHIFT_JIS).unwrap(), "été");
+
+        let err = read_cstr(&mut buf, WINDOWS_1252).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_read_cstr_capped() {
+        let mut buf = Cursor::new(b"field\0maps\0".to_vec());
+        assert_eq!(read_cstr_capped(&mut buf, 5, SHIFT_JIS).unwrap(), "field");
+        assert_eq!(read_cstr_capped(&mut buf, 8, SHIFT_JIS).unwrap(), "maps");
+
+        // a runaway string stops at the cap rather than reading on to the end
+        let mut buf = Cursor::new([b'A'; 4096].to_vec());
+        let err = read_cstr_capped(&mut buf, 16, SHIFT_JIS).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(buf.position(), 17);
+    }
+
+    #[test]
+    fn test_fourcc() {
+        assert_eq!(fourcc(*b"ARC1"), crate::tag!(b"ARC1"));
+        assert_eq!(fourcc(*b"drp\0"), crate::tag!(b"drp\0"));
+        assert_eq!(fourcc(*b"ARC1").to_le_bytes(), *b"ARC1");
+    }
+}
@@ -1,8 +1,9 @@
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_till, take_until},
-    character::complete::{char, hex_digit1, u16, u8},
-    combinator::value,
+    bytes::complete::{tag, take_till, take_till1, take_until, take_while},
+    character::complete::{char, hex_digit1, one_of, u16, u8},
+    combinator::{all_consuming, map_res, value},
+    error::ErrorKind,
     multi::many0,
     sequence::{delimited, pair, preceded},
     IResult,
@@ -10,9 +11,20 @@ use nom::{
 
 use indexmap::IndexMap;
 
-/// Token types that correspond to various variables
-#[derive(Clone, Debug)]
-pub(crate) enum Token {
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::{collections::HashMap, slice, str::Chars};
+
+/// Token types that correspond to various variables. With the `serde` feature, tokens serialize
+/// as `{"type": "Variant", "value": ...}`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(tag = "type", content = "value")
+)]
+pub enum Token {
     AccessoryIcon,
     ArmorIcon,
     AutoEnd,
@@ -23,6 +35,7 @@ pub(crate) enum Token {
     Choice2(String),
     Choice3(String),
     Choice4(String),
+    ChoiceElse(String),
     Config,
     CronoIcon,
     CronoName,
@@ -65,127 +78,624 @@ pub(crate) enum Token {
     WaterIcon,
 }
 
-/// <C#> ... </C#>
+/// Icons that can be inlined in dialogue
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IconId {
+    Accessory,
+    Armor,
+    Ayla,
+    Crono,
+    Fire,
+    Frog,
+    Helm,
+    Item,
+    Light,
+    Lucca,
+    Magus,
+    Marle,
+    Non,
+    Robo,
+    Shadow,
+    Water,
+}
+
+impl IconId {
+    /// Every icon, in declaration order
+    pub const ALL: [IconId; 16] = [
+        IconId::Accessory,
+        IconId::Armor,
+        IconId::Ayla,
+        IconId::Crono,
+        IconId::Fire,
+        IconId::Frog,
+        IconId::Helm,
+        IconId::Item,
+        IconId::Light,
+        IconId::Lucca,
+        IconId::Magus,
+        IconId::Marle,
+        IconId::Non,
+        IconId::Robo,
+        IconId::Shadow,
+        IconId::Water,
+    ];
+
+    /// The icon's name, as used in the `[icon:NAME]` fallback
+    pub fn name(self) -> &'static str {
+        match self {
+            IconId::Accessory => "Accessory",
+            IconId::Armor => "Armor",
+            IconId::Ayla => "Ayla",
+            IconId::Crono => "Crono",
+            IconId::Fire => "Fire",
+            IconId::Frog => "Frog",
+            IconId::Helm => "Helm",
+            IconId::Item => "Item",
+            IconId::Light => "Light",
+            IconId::Lucca => "Lucca",
+            IconId::Magus => "Magus",
+            IconId::Marle => "Marle",
+            IconId::Non => "Non",
+            IconId::Robo => "Robo",
+            IconId::Shadow => "Shadow",
+            IconId::Water => "Water",
+        }
+    }
+
+    /// The token that inlines this icon
+    pub(crate) fn to_token(self) -> Token {
+        match self {
+            IconId::Accessory => Token::AccessoryIcon,
+            IconId::Armor => Token::ArmorIcon,
+            IconId::Ayla => Token::AylaIcon,
+            IconId::Crono => Token::CronoIcon,
+            IconId::Fire => Token::FireIcon,
+            IconId::Frog => Token::FrogIcon,
+            IconId::Helm => Token::HelmIcon,
+            IconId::Item => Token::ItemIcon,
+            IconId::Light => Token::LightIcon,
+            IconId::Lucca => Token::LuccaIcon,
+            IconId::Magus => Token::MagusIcon,
+            IconId::Marle => Token::MarleIcon,
+            IconId::Non => Token::NonIcon,
+            IconId::Robo => Token::RoboIcon,
+            IconId::Shadow => Token::ShadowIcon,
+            IconId::Water => Token::WaterIcon,
+        }
+    }
+
+    /// The icon a token inlines, if any
+    pub(crate) fn from_token(tok: &Token) -> Option<IconId> {
+        match tok {
+            Token::AccessoryIcon => Some(IconId::Accessory),
+            Token::ArmorIcon => Some(IconId::Armor),
+            Token::AylaIcon => Some(IconId::Ayla),
+            Token::CronoIcon => Some(IconId::Crono),
+            Token::FireIcon => Some(IconId::Fire),
+            Token::FrogIcon => Some(IconId::Frog),
+            Token::HelmIcon => Some(IconId::Helm),
+            Token::ItemIcon => Some(IconId::Item),
+            Token::LightIcon => Some(IconId::Light),
+            Token::LuccaIcon => Some(IconId::Lucca),
+            Token::MagusIcon => Some(IconId::Magus),
+            Token::MarleIcon => Some(IconId::Marle),
+            Token::NonIcon => Some(IconId::Non),
+            Token::RoboIcon => Some(IconId::Robo),
+            Token::ShadowIcon => Some(IconId::Shadow),
+            Token::WaterIcon => Some(IconId::Water),
+            _ => None,
+        }
+    }
+}
+
+/// Default plain-text stand-ins for icons
+pub fn default_icon_text() -> HashMap<IconId, &'static str> {
+    HashMap::from([
+        (IconId::Accessory, "[accessory]"),
+        (IconId::Armor, "[armor]"),
+        (IconId::Ayla, "[ayla]"),
+        (IconId::Crono, "[crono]"),
+        (IconId::Fire, "[fire]"),
+        (IconId::Frog, "[frog]"),
+        (IconId::Helm, "[helm]"),
+        (IconId::Item, "[item]"),
+        (IconId::Light, "[light]"),
+        (IconId::Lucca, "[lucca]"),
+        (IconId::Magus, "[magus]"),
+        (IconId::Marle, "[marle]"),
+        (IconId::Non, "[none]"),
+        (IconId::Robo, "[robo]"),
+        (IconId::Shadow, "[shadow]"),
+        (IconId::Water, "[water]"),
+    ])
+}
+
+/// Plain text for an icon. Icons missing from `icons`, or mapped to nothing, fall back to
+/// `[icon:NAME]` so they are never dropped.
+pub fn icon_text(icon: IconId, icons: &HashMap<IconId, &str>) -> String {
+    match icons.get(&icon) {
+        Some(txt) if !txt.is_empty() => txt.to_string(),
+        _ => format!("[icon:{}]", icon.name()),
+    }
+}
+
+/// Reverses `icon_text`, mapping plain-text icon stand-ins back to their icons
+pub fn icon_reverse_table(icons: &HashMap<IconId, &str>) -> HashMap<String, IconId> {
+    IconId::ALL
+        .iter()
+        .flat_map(|i| {
+            [
+                (icon_text(*i, icons), *i),
+                (format!("[icon:{}]", i.name()), *i),
+            ]
+        })
+        .collect()
+}
+
+/// Renders dialogue as plain text, keeping text, spacing, line breaks and icons
+pub fn tokens_to_plain(toks: &[Token], icons: &HashMap<IconId, &str>) -> String {
+    render_plain(toks, |tok| {
+        IconId::from_token(tok).map(|icon| icon_text(icon, icons))
+    })
+}
+
+/// Renders dialogue as plain text, keeping text, spacing and line breaks. Every other token is
+/// replaced with what `other` gives for it, or dropped if it gives `None`.
+pub fn render_plain(toks: &[Token], mut other: impl FnMut(&Token) -> Option<String>) -> String {
+    let mut out = String::new();
+
+    for tok in toks.iter() {
+        match tok {
+            Token::Text(txt)
+            | Token::Choice1(txt)
+            | Token::Choice2(txt)
+            | Token::Choice3(txt)
+            | Token::Choice4(txt)
+            | Token::ChoiceElse(txt) => out.push_str(txt),
+            Token::Space(n) => out.extend((0..*n).map(|_| ' ')),
+            Token::LineBreak => out.push('\n'),
+            tok => {
+                if let Some(s) = other(tok) {
+                    out.push_str(&s);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// A display event for a typewriter-style text box
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Glyph {
+    Char(char),
+    Icon(IconId),
+    Wait(u8),
+    Page,
+    NewLine,
+}
+
+/// Walks dialogue tokens, yielding the glyphs a text box displays in order.
+/// Runtime substitutions (names, numbers) and button prompts yield nothing.
+pub struct GlyphStream<'a> {
+    toks: slice::Iter<'a, Token>,
+    text: Chars<'a>,
+    spaces: u8,
+}
+
+impl<'a> GlyphStream<'a> {
+    pub fn new(toks: &'a [Token]) -> Self {
+        GlyphStream {
+            toks: toks.iter(),
+            text: "".chars(),
+            spaces: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for GlyphStream<'a> {
+    type Item = Glyph;
+
+    fn next(&mut self) -> Option<Glyph> {
+        loop {
+            if let Some(c) = self.text.next() {
+                return Some(Glyph::Char(c));
+            }
+
+            if self.spaces > 0 {
+                self.spaces -= 1;
+                return Some(Glyph::Char(' '));
+            }
+
+            let glyph = match self.toks.next()? {
+                Token::Text(s)
+                | Token::Choice1(s)
+                | Token::Choice2(s)
+                | Token::Choice3(s)
+                | Token::Choice4(s)
+                | Token::ChoiceElse(s) => {
+                    self.text = s.chars();
+                    continue;
+                }
+                Token::Space(n) => {
+                    self.spaces = *n;
+                    continue;
+                }
+                Token::Wait(n) => Glyph::Wait(*n),
+                Token::Page | Token::AutoPage => Glyph::Page,
+                Token::LineBreak => Glyph::NewLine,
+                tok => match IconId::from_token(tok) {
+                    Some(icon) => Glyph::Icon(icon),
+                    None => continue,
+                },
+            };
+
+            return Some(glyph);
+        }
+    }
+}
+
+/// Dialogue parse failure, located by line and column in the source text
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MarkupError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: String,
+}
+
+impl MarkupError {
+    /// Locates `rest`, a subslice of `input` where parsing failed
+    fn new(input: &str, rest: &str, kind: ErrorKind) -> MarkupError {
+        let offs = (rest.as_ptr() as usize)
+            .saturating_sub(input.as_ptr() as usize)
+            .min(input.len());
+        let before = &input[..offs];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        MarkupError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            kind: format!("{:?}", kind),
+        }
+    }
+}
+
+impl std::fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+/// <C#> ... </C#>, or <CE> ... </CE> for the else branch
 fn choice(input: &str) -> IResult<&str, Token> {
-    let (input, n) = delimited(tag("<C"), alt((u8, char('E'))), char('>'))(input)?;
+    let (input, n) = delimited(tag("<C"), one_of("1234E"), char('>'))(input)?;
     let (input, txt) = take_until("</C")(input)?;
-    let (input, _) = pair(alt((u8, char('E'))), char('>'))(input)?; // nyomp!
+    let (input, _) = delimited(tag("</C"), char(n), char('>'))(input)?; // nyomp!
 
     match n {
-        1 => Ok((input, Token::Choice1(txt.to_owned()))),
-        2 => Ok((input, Token::Choice2(txt.to_owned()))),
-        3 => Ok((input, Token::Choice3(txt.to_owned()))),
-        4 => Ok((input, Token::Choice4(txt.to_owned()))),
-        _ => unreachable!(),
+        '1' => Ok((input, Token::Choice1(txt.to_owned()))),
+        '2' => Ok((input, Token::Choice2(txt.to_owned()))),
+        '3' => Ok((input, Token::Choice3(txt.to_owned()))),
+        '4' => Ok((input, Token::Choice4(txt.to_owned()))),
+        _ => Ok((input, Token::ChoiceElse(txt.to_owned()))),
     }
 }
 
-/// Parses a dialogue entry
+/// Parses a dialogue entry, failing on any markup left unparsed
 fn entry(input: &str) -> IResult<&str, (u16, Vec<Token>)> {
     let (input, i) = ident(input)?;
     let (input, txt) = preceded(char(','), take_till(|c| c == '\n' || c == '\r'))(input)?;
-    let (txt, toks) = token_split(txt)?;
+    let (_, toks) = all_consuming(token_split)(txt)?;
 
     Ok((input, (i, toks)))
 }
 
 /// Parses a dialogue identifier (ie. XXX_001) and returns the array index
 fn ident(input: &str) -> IResult<&str, u16> {
-    let (input, _) = take_until("_")(input)?;
+    let (input, _) = pair(take_until("_"), char('_'))(input)?;
     let (input, i) = u16(input)?;
 
     Ok((input, i))
 }
 
-/// Parses an array of dialogue entries into an indexed map
-pub(crate) fn ident_array(input: &str) -> IResult<&str, IndexMap<u16, Vec<Token>>> {
+/// Parses an array of dialogue entries into an indexed map, failing if anything but line breaks
+/// is left after the last entry
+pub fn ident_array(input: &str) -> IResult<&str, IndexMap<u16, Vec<Token>>> {
     let (input, entries) = many0(entry)(input)?;
+    let (input, _) = all_consuming(take_while(|c| c == '\n' || c == '\r'))(input)?;
     let mut entmap = IndexMap::new();
 
-    // todo: drain_filter when stabilised
-    entries.iter_mut().enumerate().for_each(|(i, e)| {
-        if !e.1.is_empty() {
-            entmap.insert(e.0, e.1);
+    for (i, toks) in entries {
+        if !toks.is_empty() {
+            entmap.insert(i, toks);
         }
-    });
+    }
 
     Ok((input, entmap))
 }
 
+/// Parses an array of dialogue entries like `ident_array`, but stops at the first malformed
+/// entry and reports where it is
+pub fn parse_dialogue_verbose(input: &str) -> Result<IndexMap<u16, Vec<Token>>, MarkupError> {
+    let mut entmap = IndexMap::new();
+    let mut rest = input;
+
+    loop {
+        rest = rest.trim_start_matches(['\r', '\n']);
+        if rest.is_empty() {
+            break;
+        }
+
+        match entry(rest) {
+            Ok((r, (i, toks))) => {
+                if !toks.is_empty() {
+                    entmap.insert(i, toks);
+                }
+                rest = r;
+            }
+            Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+                return Err(MarkupError::new(input, e.input, e.code))
+            }
+            Err(nom::Err::Incomplete(_)) => {
+                return Err(MarkupError::new(input, rest, ErrorKind::Eof))
+            }
+        }
+    }
+
+    Ok(entmap)
+}
+
+/// Exports parsed dialogue entries to JSON for translation tools
+#[cfg(feature = "serde")]
+pub fn export_json(map: &IndexMap<u16, Vec<Token>>) -> String {
+    serde_json::to_string_pretty(map).expect("dialogue tokens always serialize")
+}
+
+/// Reads dialogue entries back from JSON made by `export_json`
+#[cfg(feature = "serde")]
+pub fn import_json(json: &str) -> serde_json::Result<IndexMap<u16, Vec<Token>>> {
+    serde_json::from_str(json)
+}
+
 /// <PT#>
 fn party_char(input: &str) -> IResult<&str, Token> {
-    let (input, n) = delimited(alt((tag("<PT"), tag("<NAME_PT"))), u8, char('>'))(input)?;
+    let (input, n) =
+        delimited(alt((tag("<PT"), tag("<NAME_PT"))), one_of("123"), char('>'))(input)?;
 
     match n {
-        1 => Ok((input, Token::PartyCharacter1)),
-        2 => Ok((input, Token::PartyCharacter2)),
-        3 => Ok((input, Token::PartyCharacter3)),
-        _ => unreachable!(),
+        '1' => Ok((input, Token::PartyCharacter1)),
+        '2' => Ok((input, Token::PartyCharacter2)),
+        _ => Ok((input, Token::PartyCharacter3)),
     }
 }
 
-/// <S##>
+/// <S##> or <SP##>
 fn space(input: &str) -> IResult<&str, Token> {
-    let (input, n) = delimited(tag("<S"), u8, char('>'))(input)?;
+    let (input, n) = delimited(alt((tag("<SP"), tag("<S"))), u8, char('>'))(input)?;
 
     Ok((input, Token::Space(n)))
 }
 
 /// Non-markup dialogue text
 fn text(input: &str) -> IResult<&str, Token> {
-    let (input, txt) = take_till(|c| c == '\\' || c == '\r' || c == '\n' || c == '<')(input)?;
+    let (input, txt) = take_till1(|c| c == '\\' || c == '\r' || c == '\n' || c == '<')(input)?;
 
     Ok((input, Token::Text(txt.to_owned())))
 }
 
-/// Any special token in text
+/// Runtime values for the placeholder tokens in dialogue. Unset values leave their token as markup.
+#[derive(Clone, Debug, Default)]
+pub struct Substitutions {
+    pub number: Option<u32>,
+    pub name: Option<String>,
+    pub item: Option<String>,
+    pub tech: Option<String>,
+    pub party: [Option<String>; 3],
+    pub crono: Option<String>,
+    pub marle: Option<String>,
+    pub lucca: Option<String>,
+    pub robo: Option<String>,
+    pub frog: Option<String>,
+    pub ayla: Option<String>,
+    pub magus: Option<String>,
+    pub epoch: Option<String>,
+}
+
+impl Substitutions {
+    /// The characters' default names, with every other value unset
+    pub fn default_names() -> Substitutions {
+        Substitutions {
+            crono: Some("Crono".to_owned()),
+            marle: Some("Marle".to_owned()),
+            lucca: Some("Lucca".to_owned()),
+            robo: Some("Robo".to_owned()),
+            frog: Some("Frog".to_owned()),
+            ayla: Some("Ayla".to_owned()),
+            magus: Some("Magus".to_owned()),
+            epoch: Some("Epoch".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    /// The runtime value for a placeholder token, if it is one and a value is set
+    fn get(&self, tok: &Token) -> Option<String> {
+        match tok {
+            Token::Number => self.number.map(|n| n.to_string()),
+            Token::Name => self.name.clone(),
+            Token::ItemName => self.item.clone(),
+            Token::TechName => self.tech.clone(),
+            Token::PartyCharacter1 => self.party[0].clone(),
+            Token::PartyCharacter2 => self.party[1].clone(),
+            Token::PartyCharacter3 => self.party[2].clone(),
+            Token::CronoName => self.crono.clone(),
+            Token::MarleName => self.marle.clone(),
+            Token::LuccaName => self.lucca.clone(),
+            Token::RoboName => self.robo.clone(),
+            Token::FrogName => self.frog.clone(),
+            Token::AylaName => self.ayla.clone(),
+            Token::MagusName => self.magus.clone(),
+            Token::EpochName => self.epoch.clone(),
+            _ => None,
+        }
+    }
+}
+
+/// Renders dialogue tokens back into markup, the inverse of `token_split`
+pub fn render_markup(toks: &[Token]) -> String {
+    let mut out = String::new();
+
+    for tok in toks.iter() {
+        write_markup(tok, &mut out);
+    }
+
+    out
+}
+
+/// Renders dialogue as readable plain text, using the characters' default names
+pub fn to_plaintext(toks: &[Token]) -> String {
+    to_plaintext_with(toks, &Substitutions::default_names())
+}
+
+/// Renders dialogue as readable plain text. Placeholders take their values from `subs`;
+/// unset placeholders, icons and control tokens are dropped.
+pub fn to_plaintext_with(toks: &[Token], subs: &Substitutions) -> String {
+    render_plain(toks, |tok| subs.get(tok))
+}
+
+/// Renders dialogue with runtime values substituted in, leaving control tokens as markup
+pub fn render_with_substitutions(toks: &[Token], subs: &Substitutions) -> String {
+    let mut out = String::new();
+
+    for tok in toks.iter() {
+        match subs.get(tok) {
+            Some(s) => out.push_str(&s),
+            None => write_markup(tok, &mut out),
+        }
+    }
+
+    out
+}
+
+/// Writes the markup for a single token
+fn write_markup(tok: &Token, out: &mut String) {
+    match tok {
+        Token::Text(txt) => out.push_str(txt),
+        Token::Choice1(txt) => out.push_str(&format!("<C1>{}</C1>", txt)),
+        Token::Choice2(txt) => out.push_str(&format!("<C2>{}</C2>", txt)),
+        Token::Choice3(txt) => out.push_str(&format!("<C3>{}</C3>", txt)),
+        Token::Choice4(txt) => out.push_str(&format!("<C4>{}</C4>", txt)),
+        Token::ChoiceElse(txt) => out.push_str(&format!("<CE>{}</CE>", txt)),
+        Token::Space(n) => out.push_str(&format!("<S{}>", n)),
+        Token::Wait(n) => out.push_str(&format!("<WAIT>{:02X}</WAIT>", n)),
+        Token::LineBreak => out.push('\\'),
+        Token::AccessoryIcon => out.push_str("<ICON_ACCE>"),
+        Token::ArmorIcon => out.push_str("<ICON_ARMO>"),
+        Token::AutoEnd => out.push_str("<AUTO_END>"),
+        Token::AutoPage => out.push_str("<AUTO_PAGE>"),
+        Token::AylaIcon => out.push_str("<ICON_AYL>"),
+        Token::AylaName => out.push_str("<NAME_AYL>"),
+        Token::Config => out.push_str("<BTN_CONF>"),
+        Token::CronoIcon => out.push_str("<ICON_CRO>"),
+        Token::CronoName => out.push_str("<NAME_CRO>"),
+        Token::Dash => out.push_str("<BTN_DASH>"),
+        Token::EpochName => out.push_str("<NAME_SIL>"),
+        Token::FireIcon => out.push_str("<ICON_FIRE>"),
+        Token::FrogIcon => out.push_str("<ICON_FRO>"),
+        Token::FrogName => out.push_str("<NAME_FRO>"),
+        Token::HelmIcon => out.push_str("<ICON_HELM>"),
+        Token::ItemIcon => out.push_str("<ICON_ITEM>"),
+        Token::ItemName => out.push_str("<NAME_ITM>"),
+        Token::L => out.push_str("<BTN_L>"),
+        Token::LightIcon => out.push_str("<ICON_LIGHT>"),
+        Token::LuccaIcon => out.push_str("<ICON_LUC>"),
+        Token::LuccaName => out.push_str("<NAME_LUC>"),
+        Token::MagusIcon => out.push_str("<ICON_MAG>"),
+        Token::MagusName => out.push_str("<NAME_MAG>"),
+        Token::MarleIcon => out.push_str("<ICON_MAR>"),
+        Token::MarleName => out.push_str("<NAME_MAR>"),
+        Token::Menu => out.push_str("<BTN_MENU>"),
+        Token::Name => out.push_str("<NAME>"),
+        Token::Narrate => out.push_str("<CT>"),
+        Token::NonIcon => out.push_str("<NON_ICON>"),
+        Token::Number => out.push_str("<NUMBER>"),
+        Token::Page => out.push_str("<PAGE>"),
+        Token::PartyCharacter1 => out.push_str("<PT1>"),
+        Token::PartyCharacter2 => out.push_str("<PT2>"),
+        Token::PartyCharacter3 => out.push_str("<PT3>"),
+        Token::R => out.push_str("<BTN_R>"),
+        Token::RoboIcon => out.push_str("<ICON_ROB>"),
+        Token::RoboName => out.push_str("<NAME_ROB>"),
+        Token::ShadowIcon => out.push_str("<ICON_SHADOW>"),
+        Token::Sharp => out.push_str("<SHARP>"),
+        Token::TechName => out.push_str("<NAME_TEC>"),
+        Token::Warp => out.push_str("<BTN_WARP>"),
+        Token::WaterIcon => out.push_str("<ICON_WATER>"),
+    }
+}
+
+/// Any special token in text. `alt` takes at most 21 parsers, so they are grouped.
 fn token(input: &str) -> IResult<&str, Token> {
+    alt((choice, party_char, space, wait, icon, name, control))(input)
+}
+
+/// Inline icons
+fn icon(input: &str) -> IResult<&str, Token> {
     alt((
-        choice,
-        party_char,
-        space,
-        wait,
         value(Token::AccessoryIcon, tag("<ICON_ACCE>")),
         value(Token::ArmorIcon, tag("<ICON_ARMO>")),
-        value(Token::AutoEnd, tag("<AUTO_END>")),
-        value(Token::AutoPage, tag("<AUTO_PAGE>")),
         value(Token::AylaIcon, tag("<ICON_AYL>")),
-        value(Token::AylaName, tag("<NAME_AYL>")),
-        value(Token::Config, tag("<BTN_CONF>")),
         value(Token::CronoIcon, tag("<ICON_CRO>")),
+        value(Token::FireIcon, tag("<ICON_FIRE>")),
+        value(Token::FrogIcon, tag("<ICON_FRO>")),
+        value(Token::HelmIcon, tag("<ICON_HELM>")),
+        value(Token::ItemIcon, tag("<ICON_ITEM>")),
+        value(Token::LightIcon, tag("<ICON_LIGHT>")),
+        value(Token::LuccaIcon, tag("<ICON_LUC>")),
+        value(Token::MagusIcon, tag("<ICON_MAG>")),
+        value(Token::MarleIcon, tag("<ICON_MAR>")),
+        value(Token::NonIcon, tag("<NON_ICON>")),
+        value(Token::RoboIcon, tag("<ICON_ROB>")),
+        value(Token::ShadowIcon, tag("<ICON_SHADOW>")),
+        value(Token::WaterIcon, tag("<ICON_WATER>")),
+    ))(input)
+}
+
+/// Name placeholders
+fn name(input: &str) -> IResult<&str, Token> {
+    alt((
+        value(Token::AylaName, tag("<NAME_AYL>")),
         value(
             Token::CronoName,
             alt((tag("<NAME_CRO>"), tag("<NICK_CRO>"), tag("<NAME_CNO>"))),
         ),
-        value(Token::Dash, tag("<BTN_DASH>")),
         value(Token::EpochName, tag("<NAME_SIL>")),
-        value(Token::FireIcon, tag("<ICON_FIRE>")),
         value(Token::FrogName, tag("<NAME_FRO>")),
-        value(Token::HelmIcon, tag("<ICON_HELM>")),
-        value(Token::ItemIcon, tag("<ICON_ITEM>")),
         value(Token::ItemName, tag("<NAME_ITM>")),
-        value(Token::L, tag("<BTN_L>")),
-        value(Token::LightIcon, tag("<ICON_LIGHT>")),
-        value(Token::LineBreak, char('\\')),
-        value(Token::LuccaIcon, tag("<ICON_LUC>")),
         value(Token::LuccaName, tag("<NAME_LUC>")),
-        value(Token::MagusIcon, tag("<ICON_MAG>")),
         value(Token::MagusName, tag("<NAME_MAG>")),
-        value(Token::MarleIcon, tag("<ICON_MAR>")),
         value(Token::MarleName, tag("<NAME_MAR>")),
-        value(Token::Menu, tag("<BTN_MENU>")),
         value(Token::Name, tag("<NAME>")),
+        value(Token::RoboName, tag("<NAME_ROB>")),
+        value(Token::TechName, tag("<NAME_TEC>")),
+    ))(input)
+}
+
+/// Buttons, paging and other control tokens
+fn control(input: &str) -> IResult<&str, Token> {
+    alt((
+        value(Token::AutoEnd, tag("<AUTO_END>")),
+        value(Token::AutoPage, tag("<AUTO_PAGE>")),
+        value(Token::Config, tag("<BTN_CONF>")),
+        value(Token::Dash, tag("<BTN_DASH>")),
+        value(Token::L, tag("<BTN_L>")),
+        value(Token::LineBreak, char('\\')),
+        value(Token::Menu, tag("<BTN_MENU>")),
         value(Token::Narrate, tag("<CT>")),
-        value(Token::NonIcon, tag("<NON_ICON>")),
         value(Token::Number, tag("<NUMBER>")),
         value(Token::Page, tag("<PAGE>")),
         value(Token::R, tag("<BTN_R>")),
-        value(Token::RoboName, tag("<NAME_ROB>")),
-        value(Token::ShadowIcon, tag("<ICON_SHADOW>")),
         value(Token::Sharp, tag("<SHARP>")),
-        value(Token::TechName, tag("<NAME_TEC>")),
         value(Token::Warp, tag("<BTN_WARP>")),
-        value(Token::WaterIcon, tag("<ICON_WATER>")),
     ))(input)
 }
 
@@ -196,8 +706,10 @@ fn token_split(input: &str) -> IResult<&str, Vec<Token>> {
 
 /// <WAIT>##</WAIT>
 fn wait(input: &str) -> IResult<&str, Token> {
-    let (input, hex) = delimited(tag("<WAIT>"), hex_digit1, tag("</WAIT>"))(input)?;
-    let n = hex.parse::<u8>()?;
+    let (input, n) = map_res(
+        delimited(tag("<WAIT>"), hex_digit1, tag("</WAIT>")),
+        |hex| u8::from_str_radix(hex, 16),
+    )(input)?;
 
     Ok((input, Token::Wait(n)))
 }
@@ -211,4 +723,203 @@ the <NAME_CNO>s to the<SP5>yard ";
         let out = super::ident_array(&demo).unwrap();
         println!("{:?}", out);
     }
+
+    #[test]
+    fn test_choice_else() {
+        use super::Token;
+
+        let (_, map) = super::ident_array("DEMO_01,<C1>yes</C1><CE>no</CE>").unwrap();
+        assert_eq!(
+            map[&1],
+            [
+                Token::Choice1("yes".to_owned()),
+                Token::ChoiceElse("no".to_owned())
+            ]
+        );
+        assert_eq!(
+            super::render_markup(&map[&1][..]),
+            "<C1>yes</C1><CE>no</CE>"
+        );
+        assert_eq!(super::to_plaintext(&map[&1][..]), "yesno");
+
+        // the closing tag has to match the opening one
+        assert!(super::choice("<CE>no</C1>").is_err());
+    }
+
+    #[test]
+    fn test_plaintext() {
+        use super::{Substitutions, Token};
+
+        let demo = "DEMO_01,<NAME_MAR>: My <NAME_ITM> brings all\
+the <NAME_CNO>s to the<SP5>yard ";
+        let (_, out) = super::ident_array(&demo).unwrap();
+        assert_eq!(
+            super::to_plaintext(&out[&1][..]),
+            "Marle: My  brings allthe Cronos to the     yard "
+        );
+
+        // markup that isn't understood fails the parse instead of cutting the entry short
+        assert!(super::ident_array("DEMO_01,to the<BOGUS>yard").is_err());
+
+        let toks = [
+            Token::CronoName,
+            Token::Text(":".to_owned()),
+            Token::Space(2),
+            Token::Wait(4),
+            Token::Dash,
+            Token::ItemName,
+            Token::LineBreak,
+            Token::FireIcon,
+            Token::Page,
+            Token::AutoEnd,
+        ];
+        let subs = Substitutions {
+            crono: Some("Chrono".to_owned()),
+            item: Some("Tonic".to_owned()),
+            ..Substitutions::default_names()
+        };
+        assert_eq!(super::to_plaintext(&toks[..]), "Crono:  \n");
+        assert_eq!(
+            super::to_plaintext_with(&toks[..], &subs),
+            "Chrono:  Tonic\n"
+        );
+        assert_eq!(
+            super::render_plain(&toks[..], |tok| Some(format!("[{:?}]", tok))),
+            "[CronoName]:  [Wait(4)][Dash][ItemName]\n[FireIcon][Page][AutoEnd]"
+        );
+    }
+
+    #[test]
+    fn test_glyph_stream() {
+        use super::{Glyph, IconId, Token};
+
+        let toks = [
+            Token::Text("Hi".to_owned()),
+            Token::Space(2),
+            Token::CronoIcon,
+            Token::CronoName,
+            Token::Wait(3),
+            Token::LineBreak,
+            Token::Page,
+        ];
+        let glyphs = super::GlyphStream::new(&toks[..]).collect::<Vec<Glyph>>();
+
+        assert_eq!(
+            glyphs,
+            [
+                Glyph::Char('H'),
+                Glyph::Char('i'),
+                Glyph::Char(' '),
+                Glyph::Char(' '),
+                Glyph::Icon(IconId::Crono),
+                Glyph::Wait(3),
+                Glyph::NewLine,
+                Glyph::Page,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_substitutions() {
+        use super::{Substitutions, Token};
+
+        let toks = [
+            Token::MarleName,
+            Token::Text(": ".to_owned()),
+            Token::Number,
+            Token::Text(" of ".to_owned()),
+            Token::ItemName,
+            Token::Page,
+            Token::CronoName,
+        ];
+        let subs = Substitutions {
+            number: Some(3),
+            item: Some("Tonic".to_owned()),
+            marle: Some("Nadia".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            super::render_with_substitutions(&toks[..], &subs),
+            "Nadia: 3 of Tonic<PAGE><NAME_CRO>"
+        );
+    }
+
+    #[test]
+    fn test_icon_text() {
+        use super::IconId;
+
+        let mut icons = super::default_icon_text();
+        icons.remove(&IconId::Fire);
+        icons.insert(IconId::Water, "");
+
+        let rev = super::icon_reverse_table(&icons);
+        for icon in IconId::ALL.iter() {
+            let txt = super::icon_text(*icon, &icons);
+            assert!(!txt.is_empty());
+            assert_eq!(rev[&txt], *icon);
+            assert_eq!(IconId::from_token(&icon.to_token()), Some(*icon));
+        }
+
+        assert_eq!(super::icon_text(IconId::Fire, &icons), "[icon:Fire]");
+        assert_eq!(super::icon_text(IconId::Water, &icons), "[icon:Water]");
+
+        let toks = [
+            super::Token::Text("Cast".to_owned()),
+            super::Token::Space(1),
+            super::Token::FireIcon,
+            super::Token::LightIcon,
+        ];
+        assert_eq!(
+            super::tokens_to_plain(&toks[..], &icons),
+            "Cast [icon:Fire][light]"
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_render_markup(
+            parts in proptest::collection::vec(
+                proptest::prop_oneof![
+                    "[a-zA-Z0-9 .,!?:']{1,8}",
+                    "<C[1-4E]>[a-z ]{0,8}</C[1-4E]>",
+                    "<(NAME|PT)[1-3]>",
+                    "<S[0-9]{1,3}>",
+                    "<WAIT>[0-9A-F]{2}</WAIT>",
+                    "<ICON_(ACCE|ARMO|AYL|CRO|FIRE|FRO|HELM|ITEM|LIGHT|LUC|MAG|MAR|ROB|SHADOW|WATER)>",
+                    "<NAME(_AYL|_CRO|_CNO|_SIL|_FRO|_ITM|_LUC|_MAG|_MAR|_ROB|_TEC)?>",
+                    "<(NICK_CRO|NON_ICON|AUTO_END|AUTO_PAGE|CT|NUMBER|PAGE|SHARP)>",
+                    "<BTN_(CONF|DASH|L|MENU|R|WARP)>",
+                    "\\\\",
+                ],
+                0..16,
+            )
+        ) {
+            let (_, toks) = super::token_split(&parts.concat()).unwrap();
+            let markup = super::render_markup(&toks[..]);
+            let (rest, again) = super::token_split(&markup).unwrap();
+
+            proptest::prop_assert_eq!(rest, "");
+            proptest::prop_assert_eq!(again, toks);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json() {
+        let demo = "DEMO_01,<NAME_MAR>: <C1>Yes</C1><S5><WAIT>0A</WAIT>\\<PAGE>\nDEMO_02,Hi";
+        let (_, map) = super::ident_array(&demo).unwrap();
+
+        let json = super::export_json(&map);
+        assert!(json.contains(r#""type": "MarleName""#));
+        assert!(json.contains(r#""type": "Wait","#));
+        assert_eq!(super::import_json(&json).unwrap(), map);
+    }
+
+    #[test]
+    fn test_dlg_error_position() {
+        let demo = "DEMO_01,Fine\nDEMO_02,Also fine\nDEMO_03,Not <BOGUS> fine";
+        let err = super::parse_dialogue_verbose(&demo).unwrap_err();
+        assert_eq!((err.line, err.column), (3, 13));
+    }
 }
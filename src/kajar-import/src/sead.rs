@@ -1,19 +1,28 @@
 // based on https://github.com/vgmstream/vgmstream/blob/master/src/meta/sqex_sead.c
 
-use anyhow::Result;
-use bytes::Buf;
+use bytemuck::{bytes_of_mut, Zeroable};
+use bytemuck_derive::{Pod, Zeroable};
 
-use bytemuck::{bytes_of_mut, Pod, Zeroable};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     io::{self, Cursor, Read},
+    mem::size_of,
+    ops::Range,
 };
 
-use crate::tag;
+use crate::{
+    audio::{write_audio, write_midi, AudioErr, AudioFormat, MidiEvent},
+    encoding::decode_sjis,
+    fourcc,
+    hca::{self, HCAErr},
+};
 
 /// SEAD file header
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct Header {
     id: u32,
@@ -27,36 +36,42 @@ struct Header {
 }
 
 /// SEAD chunk ID
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[repr(u32)]
 enum ChunkType {
-    Instruments = tag!(b"inst"),
-    Materials = tag!(b"mtrl"),
-    Music = tag!(b"musc"),
-    Sequences = tag!(b"seq "),
-    Sounds = tag!(b"snd "),
-    Tracks = tag!(b"trk "),
-
-    #[default]
-    Unknown,
-}
-
-impl From<u32> for ChunkType {
-    fn from(value: u32) -> Self {
-        match value {
-            tag!(b"inst") => ChunkType::Instruments,
-            tag!(b"mtrl") => ChunkType::Materials,
-            tag!(b"musc") => ChunkType::Music,
-            tag!(b"seq ") => ChunkType::Sequences,
-            tag!(b"snd ") => ChunkType::Sounds,
-            tag!(b"trk ") => ChunkType::Tracks,
-            _ => ChunkType::Unknown,
+    Instruments = fourcc(*b"inst"),
+    Materials = fourcc(*b"mtrl"),
+    Music = fourcc(*b"musc"),
+    Sequences = fourcc(*b"seq "),
+    Sounds = fourcc(*b"snd "),
+    Tracks = fourcc(*b"trk "),
+}
+
+impl ChunkType {
+    /// The chunk type with the given ID, or `None` for chunks we don't handle
+    fn from_id(id: u32) -> Option<ChunkType> {
+        const INST: u32 = ChunkType::Instruments as u32;
+        const MTRL: u32 = ChunkType::Materials as u32;
+        const MUSC: u32 = ChunkType::Music as u32;
+        const SEQ: u32 = ChunkType::Sequences as u32;
+        const SND: u32 = ChunkType::Sounds as u32;
+        const TRK: u32 = ChunkType::Tracks as u32;
+
+        match id {
+            INST => Some(ChunkType::Instruments),
+            MTRL => Some(ChunkType::Materials),
+            MUSC => Some(ChunkType::Music),
+            SEQ => Some(ChunkType::Sequences),
+            SND => Some(ChunkType::Sounds),
+            TRK => Some(ChunkType::Tracks),
+            _ => None,
         }
     }
 }
 
 /// SEAD chunk table metadata
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct ChkTblEntry {
     id: u32,
@@ -69,6 +84,7 @@ struct ChkTblEntry {
 
 /// SEAD sound header
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct SndHdr {
     ver: u8,
@@ -87,12 +103,25 @@ struct SndHdr {
     _output: u8,
     _curve: u8,
     _port: u8,
-    _name_size: u8,
+    name_size: u8,
     _play_len: u32,
 }
 
+/// SEAD sound chunk header
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(C)]
+struct SndChkHdr {
+    _ver: u8,
+    _01: u8,
+    _size: u16,
+    nentries: u16,
+    _align: [u8; 10],
+}
+
 /// SEAD sequence entry
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct SeqInfo {
     _ver: u8,
@@ -105,6 +134,7 @@ struct SeqInfo {
 
 /// SEAD old sequence
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct OldSeq {
     _cfg: [u8; 12],
@@ -116,39 +146,29 @@ struct OldSeq {
 
 /// SEAD new sequence
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct NewSeq {
     _id: u16,
     cmd_start: u16,
     _n: u8,
+    _05: [u8; 3],
     _08: u64,
 }
 
 /// SEAD sequence header
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct SeqHdr {
-    ver: u16,
+    ver: u8,
     _01: u8,
     _size: u16,
 }
 
-/// SEAD track
-#[derive(Clone, Copy, Debug, Pod, Zeroable)]
-#[repr(C)]
-struct Track {
-    _ver: u8,
-    kind: u8,
-    _size: u16,
-    idx: u16,
-    _bank: u16,
-    _id: u16,
-    _child_id: u16,
-    _0c: u8,
-}
-
 /// SEAD sequence command header
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct SeqCmdHdr {
     _ver: u8,
@@ -159,28 +179,31 @@ struct SeqCmdHdr {
 
 /// SEAD sequence command track
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct CmdTrack {
     trk_idx: u32,
-    _looped: bool,
+    _looped: u8,
     _05: u8,
     _trk_id: u16,
-    _play_len: f32,
+    play_len: f32,
 }
 
 /// SEAD material header
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct MatHdr {
     _ver: u8,
     _01: u8,
     _size: u16,
     nentries: u16,
-    _align: [u8; 12],
+    _align: [u8; 10],
 }
 
 /// SEAD stream header
 #[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(C)]
 struct StreamHdr {
     _ver: u8,
@@ -192,7 +215,7 @@ struct StreamHdr {
     sample_rate: u32,
     loop_start: u32,
     loop_end: u32,
-    total_size: u32,
+    extra_size: u32,
     stream_size: u32,
     _id: u16,
     _align: u16,
@@ -223,177 +246,572 @@ impl SeqVer {
     }
 }
 
+/// Sequence command that ends the stream
+const CMD_END: u8 = 0;
+
+/// Sequence command that starts a track playing
+const CMD_KEY_ON: u8 = 2;
+
+/// Sequence command that stops the playing track
+const CMD_KEY_OFF: u8 = 3;
+
+/// Sequence command that sets the tempo
+const CMD_TEMPO: u8 = 4;
+
+/// Sequence command that sets the volume
+const CMD_VOLUME: u8 = 5;
+
+/// MIDI ticks per quarter note in exported sequences
+const MIDI_DIVISION: u16 = 480;
+
+/// Tempo exported sequences start at, in microseconds per quarter note (120 BPM)
+const MIDI_TEMPO: u32 = 500_000;
+
+/// Note that each SEAD track plays in exported sequences
+const MIDI_NOTE: u8 = 60;
+
 /// SEAD sequence command
 #[derive(Debug)]
-struct SeqCmd {
-    hdr: SeqCmdHdr,
-    cmdtrk: Option<CmdTrack>,
-    trk: Option<Track>,
+enum SeqCmd {
+    KeyOn(CmdTrack),
+    KeyOff,
+    Tempo(f32),
+    Volume(f32),
+    Other(SeqCmdHdr),
 }
 
 impl SeqCmd {
-    fn new(buf: &mut impl Read) -> Result<SeqCmd, SEADErr> {
+    /// Decodes the body of a command following its header
+    fn new(hdr: SeqCmdHdr, buf: &mut impl Read) -> Result<SeqCmd, SEADErr> {
+        match hdr.kind {
+            CMD_KEY_ON => {
+                // the track entry that follows is skipped along with the rest of the command
+                let mut cmdtrk = CmdTrack::zeroed();
+                buf.read_exact(bytes_of_mut(&mut cmdtrk))
+                    .map_err(|e| SEADErr::CmdTrackRead(e))?;
+                Ok(SeqCmd::KeyOn(cmdtrk))
+            }
+            CMD_KEY_OFF => Ok(SeqCmd::KeyOff),
+            CMD_TEMPO => read_f32(buf).map(SeqCmd::Tempo),
+            CMD_VOLUME => read_f32(buf).map(SeqCmd::Volume),
+            _ => Ok(SeqCmd::Other(hdr)),
+        }
+    }
+}
+
+/// Reads a little endian float out of a command body
+fn read_f32(buf: &mut impl Read) -> Result<f32, SEADErr> {
+    let mut b = [0; 4];
+    buf.read_exact(&mut b).map_err(|e| SEADErr::SeqCmdRead(e))?;
+    Ok(f32::from_le_bytes(b))
+}
+
+/// Reads the command stream starting at the reader's position, up to the end command. Each
+/// command's header gives its full size, which is used to step to the next one whether or not
+/// its body was decoded.
+fn read_cmds(buf: &mut Cursor<&[u8]>) -> Result<Vec<SeqCmd>, SEADErr> {
+    let mut cmds = Vec::new();
+    loop {
+        let pos = buf.position();
+
         let mut hdr = SeqCmdHdr::zeroed();
-        buf.read_exact(bytes_of_mut(&mut hdr))?;
-
-        let mut cmdtrk = None;
-        let mut trk = None;
-        if hdr.kind == 2 {
-            // key on
-            let mut ct = CmdTrack::zeroed();
-            let mut t = Track::zeroed();
-            buf.read_exact(bytes_of_mut(&mut ct))
-                .map_err(|e| SEADErr::CmdTrackRead(e))?;
-            buf.read_exact(bytes_of_mut(&mut t))
-                .map_err(|e| SEADErr::TrackRead(e))?;
-            cmdtrk = Some(ct);
-            trk = Some(t);
+        buf.read_exact(bytes_of_mut(&mut hdr))
+            .map_err(|e| SEADErr::SeqCmdHdrRead(e))?;
+
+        if hdr.kind == CMD_END {
+            break;
+        }
+        // a command can't be smaller than its header, and stepping by 0 would never end
+        if (hdr.size as usize) < size_of::<SeqCmdHdr>() {
+            return Err(SEADErr::SeqCmdSize(pos));
         }
 
-        Ok(SeqCmd { hdr, cmdtrk, trk })
+        cmds.push(SeqCmd::new(hdr, buf)?);
+        buf.set_position(pos + hdr.size as u64);
     }
+
+    Ok(cmds)
 }
 
 /// SEAD sequence
 #[derive(Debug)]
 struct Sequence {
-    info: SeqInfo,
-    hdr: SeqHdr,
-    ver: SeqVer,
     cmds: Vec<SeqCmd>,
 }
 
 impl Sequence {
-    fn new(sead: &SEAD, buf: &mut impl Read) -> Result<Sequence, SEADErr> {
-        let mut c = Cursor::new(buf);
+    fn new(buf: &mut Cursor<&[u8]>) -> Result<Sequence, SEADErr> {
+        let start = buf.position();
 
         let mut info = SeqInfo::zeroed();
-        c.read_exact(bytes_of_mut(&mut info))
+        buf.read_exact(bytes_of_mut(&mut info))
             .map_err(|e| SEADErr::SeqInfoRead(e))?;
 
-        if let Some(offs) = sead.chunk_offs.get(ChunkType::Sequences) {
-            c.set_position(*offs);
+        // the sequence header follows the info block
+        buf.set_position(start + info.size as u64);
+        let mut hdr = SeqHdr::zeroed();
+        buf.read_exact(bytes_of_mut(&mut hdr))
+            .map_err(|e| SEADErr::SeqHdrRead(e))?;
+
+        let ver = SeqVer::new(hdr.ver, buf)?;
 
-            let mut hdr = SeqHdr::zeroed();
-            c.read_exact(bytes_of_mut(&mut hdr))
-                .map_err(|e| SEADErr::SeqHdrRead(e))?;
+        // commands start relative to the sequence header
+        let cmd_start = match ver {
+            SeqVer::Old(seq) => seq.cmd_start,
+            SeqVer::New(seq) => seq.cmd_start,
+        };
+        buf.set_position(start + info.size as u64 + cmd_start as u64);
+        let cmds = read_cmds(buf)?;
 
-            let ver = SeqVer::new(hdr.ver, &mut c)?;
-        }
+        Ok(Sequence { cmds })
     }
 }
 
 /// SEAD sound section
 #[derive(Debug)]
-struct Sound {
+pub struct Sound {
     hdr: SndHdr,
     name: String,
 }
 
+impl Sound {
+    fn new(buf: &mut Cursor<&[u8]>) -> Result<Sound, SEADErr> {
+        let start = buf.position();
+
+        let mut hdr = SndHdr::zeroed();
+        buf.read_exact(bytes_of_mut(&mut hdr))
+            .map_err(|e| SEADErr::SndHdrRead(e))?;
+
+        // the name follows the sound header
+        buf.set_position(start + hdr.size as u64);
+        let mut name = vec![0; hdr.name_size as usize];
+        buf.read_exact(&mut name[..])
+            .map_err(|e| SEADErr::NameRead(e))?;
+
+        Ok(Sound {
+            hdr,
+            name: decode_sjis(&name[..]).trim_end_matches('\0').to_owned(),
+        })
+    }
+
+    /// The human-readable sound name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of sequences this sound plays
+    pub fn num_sequences(&self) -> usize {
+        self.hdr.nseqs as usize
+    }
+
+    /// Indices of the sequences this sound plays
+    pub fn sequences(&self) -> Range<usize> {
+        let start = self.hdr.seq_start as usize;
+        start..(start + self.hdr.nseqs as usize)
+    }
+}
+
+/// Reads the entry offsets of the chunk at the given offset, relative to the start of the buffer
+fn read_entry_offsets(buf: &mut Cursor<&[u8]>, offs: u32) -> Result<Vec<u64>, SEADErr> {
+    buf.set_position(offs as u64);
+
+    let mut hdr = SndChkHdr::zeroed();
+    buf.read_exact(bytes_of_mut(&mut hdr))
+        .map_err(|e| SEADErr::SndChkHdrRead(e))?;
+
+    // entry offsets are relative to the start of the chunk
+    Ok(read_offsets(buf, hdr.nentries)?
+        .into_iter()
+        .map(|p| offs as u64 + p as u64)
+        .collect::<Vec<u64>>())
+}
+
+/// Reads a table of `n` little endian entry offsets
+fn read_offsets(buf: &mut Cursor<&[u8]>, n: u16) -> Result<Vec<u32>, SEADErr> {
+    (0..n)
+        .map(|_| {
+            let mut b = [0; 4];
+            buf.read_exact(&mut b)
+                .map_err(|e| SEADErr::EntryOffsetRead(e))?;
+            Ok(u32::from_le_bytes(b))
+        })
+        .collect()
+}
+
+/// Reads every sound entry of the `snd ` chunk at the given offset
+fn read_sounds(buf: &mut Cursor<&[u8]>, offs: u32) -> Result<Vec<Sound>, SEADErr> {
+    let ptrs = read_entry_offsets(buf, offs)?;
+
+    let mut sounds = Vec::with_capacity(ptrs.len());
+    for p in ptrs.iter() {
+        buf.set_position(*p);
+        sounds.push(Sound::new(buf)?);
+    }
+
+    Ok(sounds)
+}
+
+/// Reads every sequence entry of the `seq ` chunk at the given offset
+fn read_sequences(buf: &mut Cursor<&[u8]>, offs: u32) -> Result<Vec<Sequence>, SEADErr> {
+    let ptrs = read_entry_offsets(buf, offs)?;
+
+    let mut seqs = Vec::with_capacity(ptrs.len());
+    for p in ptrs.iter() {
+        buf.set_position(*p);
+        seqs.push(Sequence::new(buf)?);
+    }
+
+    Ok(seqs)
+}
+
+/// PCM stream codec
+const CODEC_PCM: u8 = 1;
+
+/// HCA stream codec
+const CODEC_HCA: u8 = 7;
+
+/// SEAD audio stream
+#[derive(Debug)]
+struct Stream {
+    hdr: StreamHdr,
+    extra: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Stream {
+    fn new(buf: &mut Cursor<&[u8]>) -> Result<Stream, SEADErr> {
+        let mut hdr = StreamHdr::zeroed();
+        buf.read_exact(bytes_of_mut(&mut hdr))
+            .map_err(|e| SEADErr::StreamHdrRead(e))?;
+
+        // codec-specific extra data sits between the header and the stream
+        let left = (buf.get_ref().len() as u64).saturating_sub(buf.position());
+        if hdr.extra_size as u64 + hdr.stream_size as u64 > left {
+            return Err(SEADErr::StreamRead(io::Error::from(
+                io::ErrorKind::UnexpectedEof,
+            )));
+        }
+        let mut extra = vec![0; hdr.extra_size as usize];
+        buf.read_exact(&mut extra[..])
+            .map_err(|e| SEADErr::StreamRead(e))?;
+        let mut data = vec![0; hdr.stream_size as usize];
+        buf.read_exact(&mut data[..])
+            .map_err(|e| SEADErr::StreamRead(e))?;
+
+        Ok(Stream { hdr, extra, data })
+    }
+
+    /// Decodes the stream to interleaved 16-bit PCM
+    fn samples(&self) -> Result<Vec<i16>, SEADErr> {
+        match self.hdr.codec {
+            CODEC_PCM => Ok(self
+                .data
+                .chunks_exact(2)
+                .map(|s| i16::from_le_bytes([s[0], s[1]]))
+                .collect::<Vec<i16>>()),
+            CODEC_HCA => {
+                // the HCA header is kept in the extra data, after a small header of its own
+                let start = self
+                    .extra
+                    .windows(4)
+                    .position(|m| m.iter().map(|b| b & 0x7F).eq(b"HCA\0".iter().copied()))
+                    .unwrap_or(self.extra.len());
+                let mut hca = self.extra[start..].to_vec();
+                hca.extend_from_slice(&self.data[..]);
+
+                hca::decode(&hca[..], None).map_err(|e| SEADErr::HCA(e))
+            }
+            c => Err(SEADErr::Unsupported(c)),
+        }
+    }
+
+    /// Loop start and end in samples, if the stream loops
+    fn loop_pts(&self) -> Option<(u32, u32)> {
+        match self.hdr.loop_end > self.hdr.loop_start {
+            true => Some((self.hdr.loop_start, self.hdr.loop_end)),
+            false => None,
+        }
+    }
+}
+
 /// SEAD material section
 #[derive(Debug)]
 struct Material {
-    hdr: MatHdr,
-    offs: Vec<u32>,
-    stream_hdr: StreamHdr,
+    streams: Vec<Stream>,
 }
 
 impl Material {
-    fn new<T: Buf + Read>(buf: &mut T) -> Result<Material, SEADErr> {
+    fn new(buf: &mut Cursor<&[u8]>, offs: u32) -> Result<Material, SEADErr> {
+        buf.set_position(offs as u64);
+
         let mut hdr = MatHdr::zeroed();
         buf.read_exact(bytes_of_mut(&mut hdr))
             .map_err(|e| SEADErr::MatHdrRead(e))?;
 
-        let offs = (0..hdr.nentries)
-            .into_iter()
-            .map(|i| buf.get_u32_le())
-            .collect::<Vec<u32>>();
+        // entry offsets are relative to the start of the chunk
+        let ents = read_offsets(buf, hdr.nentries)?;
 
-        let mut stream_hdr = StreamHdr::zeroed();
-        buf.read_exact(bytes_of_mut(&mut stream_hdr))
-            .map_err(|e| SEADErr::StreamHdrRead(e))?;
+        let mut streams = Vec::with_capacity(ents.len());
+        for e in ents.iter() {
+            buf.set_position(offs as u64 + *e as u64);
+            streams.push(Stream::new(buf)?);
+        }
 
-        Ok(Material {
-            hdr,
-            offs,
-            stream_hdr,
-        })
+        Ok(Material { streams })
     }
 }
 
+/// SEAD import error
 #[derive(Debug)]
 pub enum SEADErr {
+    Audio(AudioErr),
     ChunkEntryRead(io::Error),
     CmdTrackRead(io::Error),
+    EntryOffsetRead(io::Error),
+    HCA(HCAErr),
     HeaderRead(io::Error),
+    Magic(u32),
     MatHdrRead(io::Error),
     NameRead(io::Error),
     NewSeqRead(io::Error),
     OldSeqRead(io::Error),
     SeqCmdHdrRead(io::Error),
+    SeqCmdRead(io::Error),
+    SeqCmdSize(u64),
     SeqHdrRead(io::Error),
     SeqInfoRead(io::Error),
+    SndChkHdrRead(io::Error),
+    SndHdrRead(io::Error),
     StreamHdrRead(io::Error),
-    TrackRead(io::Error),
+    StreamIndex(usize),
+    StreamRead(io::Error),
+    Unsupported(u8),
 }
 
+/// SEAD sound bank
 #[derive(Debug)]
-struct SEAD {
-    hdr: Header,
+pub struct SEAD {
     name: String,
-    sect_offs: u32,
-    chunk_offs: HashMap<ChunkType, u32>,
     mat: Option<Material>,
+    seqs: Vec<Sequence>,
+    sounds: Vec<Sound>,
 }
 
 impl SEAD {
-    fn new(buf: &[u8]) -> Result<SEAD, SEADErr> {
+    /// Parses a SEAD sound bank from a buffer
+    pub fn new(buf: &[u8]) -> Result<SEAD, SEADErr> {
         let mut c = Cursor::new(buf);
 
         let mut hdr = Header::zeroed();
         c.read_exact(bytes_of_mut(&mut hdr))
             .map_err(|e| SEADErr::HeaderRead(e))?;
 
-        let mut name = [0; 16];
+        // sound banks and music banks share the layout
+        if hdr.id != fourcc(*b"sabf") && hdr.id != fourcc(*b"mabf") {
+            return Err(SEADErr::Magic(hdr.id));
+        }
+
+        let mut name = vec![0; hdr.filename_size as usize];
         c.read_exact(&mut name[..])
             .map_err(|e| SEADErr::NameRead(e))?;
 
-        let sect_offs = align_size_to_block(16 + hdr.filename_size + 1, 16);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            nchunks = hdr.nchunks,
+            file_size = hdr.file_size,
+            "read SEAD header"
+        );
+
+        // the chunk table follows the null-terminated name, aligned to 16 bytes
+        let sect_offs = align_size_to_block(16 + hdr.filename_size as u32 + 1, 16);
+        c.set_position(sect_offs as u64);
 
-        let chunk_info = vec![ChkTblEntry::zeroed(); hdr.nchunks as usize]
-            .iter_mut()
-            .for_each(|chk| {
-                c.read_exact(bytes_of_mut(chk))
-                    .map_err(|e| SEADErr::ChunkEntryRead(e))?
-            });
+        let mut chunk_info = vec![ChkTblEntry::zeroed(); hdr.nchunks as usize];
+        for chk in chunk_info.iter_mut() {
+            c.read_exact(bytes_of_mut(chk))
+                .map_err(|e| SEADErr::ChunkEntryRead(e))?;
+        }
 
         let mut chunk_offs = HashMap::with_capacity(hdr.nchunks as usize);
-        chunk_info.iter().for_each(|chk| {
-            if ChunkType::from(chk.id) != ChunkType::Unknown {
-                chunk_offs.insert(ChunkType::from(chk.id), chk.offs);
+        for chk in chunk_info.iter() {
+            if let Some(kind) = ChunkType::from_id(chk.id) {
+                chunk_offs.insert(kind, chk.offs);
             }
-        });
-
-        let mat: Option<Material>;
-        if let Some(offs) = chunk_offs.get(ChunkType::Materials) {
-            c.set_position(*offs as u64);
-            mat = Some(Material::new(&mut c)?);
         }
 
-        /*let mut chunks = vec![vec![], hdr.nchunks].enumerate().iter_mut().for_each(|(i, *chk)| {
-            chk.resize(chunk_info[i].size as usize, 0);
-            c.set_position(chunk_info[i].offs as u64);
-            let _ = c.read_exact(&mut chk[..])?;
-        });*/
+        let mat = match chunk_offs.get(&ChunkType::Materials) {
+            Some(offs) => Some(Material::new(&mut c, *offs)?),
+            None => None,
+        };
+
+        let seqs = match chunk_offs.get(&ChunkType::Sequences) {
+            Some(offs) => read_sequences(&mut c, *offs)?,
+            None => Vec::new(),
+        };
+
+        let sounds = match chunk_offs.get(&ChunkType::Sounds) {
+            Some(offs) => read_sounds(&mut c, *offs)?,
+            None => Vec::new(),
+        };
 
         Ok(SEAD {
-            hdr,
-            name: String::from_utf8(&name[..].to_vec())?,
-            sect_offs,
-            chunk_offs,
+            name: decode_sjis(&name[..]),
             mat,
+            seqs,
+            sounds,
         })
     }
+
+    /// The bank's file name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of sequences in this bank
+    pub fn num_sequences(&self) -> usize {
+        self.seqs.len()
+    }
+
+    /// Number of audio streams in this bank
+    pub fn num_streams(&self) -> usize {
+        self.mat.as_ref().map_or(0, |mat| mat.streams.len())
+    }
+
+    /// Writes the first audio stream to a WAV file
+    pub fn export_wav(&self, path: &str) -> Result<(), SEADErr> {
+        self.export_stream_wav(0, path)
+    }
+
+    /// Writes audio stream `idx` to a WAV file, keeping its loop points. PCM and unkeyed HCA
+    /// streams are supported.
+    pub fn export_stream_wav(&self, idx: usize, path: &str) -> Result<(), SEADErr> {
+        self.export_stream(idx, AudioFormat::Wav, path)
+    }
+
+    /// Writes audio stream `idx` to a file in `format`, keeping its loop points where the format
+    /// can hold them. PCM and unkeyed HCA streams are supported.
+    pub fn export_stream(
+        &self,
+        idx: usize,
+        format: AudioFormat,
+        path: &str,
+    ) -> Result<(), SEADErr> {
+        let stream = self
+            .mat
+            .as_ref()
+            .and_then(|mat| mat.streams.get(idx))
+            .ok_or(SEADErr::StreamIndex(idx))?;
+        let samples = stream.samples()?;
+
+        write_audio(
+            format,
+            &samples[..],
+            stream.hdr.sample_rate,
+            stream.hdr.nchannels,
+            stream.loop_pts(),
+            path,
+        )
+        .map_err(|e| SEADErr::Audio(e))
+    }
+
+    /// The named sounds in this bank
+    pub fn sounds(&self) -> &[Sound] {
+        &self.sounds[..]
+    }
+
+    /// Writes the sequences to a Standard MIDI File, one after another. Each SEAD track gets its
+    /// own MIDI track, with key on and off commands as notes. Commands with no MIDI equivalent
+    /// are skipped, and their kinds returned.
+    pub fn export_midi(&self, path: &str) -> Result<Vec<u8>, SEADErr> {
+        let (tracks, skipped) = self.midi_tracks();
+        write_midi(&tracks[..], MIDI_DIVISION, path).map_err(|e| SEADErr::Audio(e))?;
+
+        Ok(skipped)
+    }
+
+    /// Lays out the sequence commands as MIDI events: a conductor track holding tempo changes,
+    /// then a track per SEAD track index. A key on with a play length holds the sequence until
+    /// the note ends; one without is held until the next key off or the end of the sequence.
+    /// The kinds of unsupported commands are returned alongside, in the order they were skipped.
+    fn midi_tracks(&self) -> (Vec<Vec<MidiEvent>>, Vec<u8>) {
+        let mut conductor = vec![tempo_event(0, MIDI_TEMPO)];
+        let mut tracks = BTreeMap::<u32, Vec<MidiEvent>>::new();
+        let mut tempo = MIDI_TEMPO;
+        let mut velocity = 100;
+        let mut now = 0;
+        let mut skipped = Vec::new();
+
+        for seq in self.seqs.iter() {
+            let mut held = Vec::new();
+            for cmd in seq.cmds.iter() {
+                match cmd {
+                    SeqCmd::KeyOn(cmdtrk) => {
+                        let trk = tracks.entry(cmdtrk.trk_idx).or_default();
+                        trk.push(note_event(now, cmdtrk.trk_idx, true, velocity));
+                        if cmdtrk.play_len > 0.0 {
+                            now += secs_to_ticks(cmdtrk.play_len, tempo);
+                            trk.push(note_event(now, cmdtrk.trk_idx, false, 0));
+                        } else {
+                            held.push(cmdtrk.trk_idx);
+                        }
+                    }
+                    SeqCmd::KeyOff => release_notes(&mut tracks, &mut held, now),
+                    SeqCmd::Tempo(bpm) if *bpm > 0.0 => {
+                        tempo = (60_000_000.0 / bpm) as u32;
+                        conductor.push(tempo_event(now, tempo));
+                    }
+                    SeqCmd::Volume(vol) => {
+                        velocity = ((vol.clamp(0.0, 1.0) * 127.0).round() as u8).max(1)
+                    }
+                    SeqCmd::Other(hdr) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(?cmd, "skipping unsupported sequence command");
+
+                        skipped.push(hdr.kind);
+                    }
+                    SeqCmd::Tempo(_) => (),
+                }
+            }
+
+            release_notes(&mut tracks, &mut held, now);
+        }
+
+        let tracks = std::iter::once(conductor)
+            .chain(tracks.into_values())
+            .collect();
+
+        (tracks, skipped)
+    }
+}
+
+/// A tempo change to `tempo` microseconds per quarter note
+fn tempo_event(tick: u32, tempo: u32) -> MidiEvent {
+    let mut data = vec![0xFF, 0x51, 3];
+    data.extend_from_slice(&tempo.to_be_bytes()[1..]);
+    MidiEvent { tick, data }
+}
+
+/// A note on or off for SEAD track `trk_idx`, on the MIDI channel of the same number
+fn note_event(tick: u32, trk_idx: u32, on: bool, velocity: u8) -> MidiEvent {
+    let status = if on { 0x90 } else { 0x80 };
+    MidiEvent {
+        tick,
+        data: vec![status | (trk_idx % 16) as u8, MIDI_NOTE, velocity],
+    }
+}
+
+/// Ends the notes of every held track at `tick`
+fn release_notes(tracks: &mut BTreeMap<u32, Vec<MidiEvent>>, held: &mut Vec<u32>, tick: u32) {
+    for trk_idx in held.drain(..) {
+        if let Some(trk) = tracks.get_mut(&trk_idx) {
+            trk.push(note_event(tick, trk_idx, false, 0));
+        }
+    }
+}
+
+/// Converts a length in seconds to MIDI ticks at `tempo` microseconds per quarter note
+fn secs_to_ticks(secs: f32, tempo: u32) -> u32 {
+    (secs as f64 * 1_000_000.0 / tempo as f64 * MIDI_DIVISION as f64).round() as u32
 }
 
 const fn align_size_to_block(value: u32, block_align: u32) -> u32 {
@@ -408,3 +826,339 @@ const fn align_size_to_block(value: u32, block_align: u32) -> u32 {
 
     value + block_align - extra_size
 }
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use crate::fourcc;
+
+    use std::io::Cursor;
+
+    /// Builds a sound entry with the given name and sequence range
+    fn sound(name: &str, seq_start: u16, nseqs: u8) -> Vec<u8> {
+        let mut hdr = super::SndHdr::zeroed();
+        hdr.size = std::mem::size_of::<super::SndHdr>() as u16;
+        hdr.nseqs = nseqs;
+        hdr.seq_start = seq_start;
+        hdr.name_size = name.len() as u8 + 1;
+
+        let mut ent = bytemuck::bytes_of(&hdr).to_vec();
+        ent.extend_from_slice(name.as_bytes());
+        ent.push(0);
+        ent
+    }
+
+    /// Builds a new-style sequence entry running the given command stream, which should end
+    /// with an end command
+    fn sequence(cmds: &[u8]) -> Vec<u8> {
+        let mut info = super::SeqInfo::zeroed();
+        info.size = std::mem::size_of::<super::SeqInfo>() as u16;
+
+        let mut hdr = super::SeqHdr::zeroed();
+        hdr.ver = 3;
+
+        // the commands follow the sequence header
+        let mut seq = super::NewSeq::zeroed();
+        seq.cmd_start = 20;
+
+        let mut ent = bytemuck::bytes_of(&info).to_vec();
+        ent.extend_from_slice(bytemuck::bytes_of(&hdr));
+        ent.extend_from_slice(bytemuck::bytes_of(&seq));
+        ent.extend_from_slice(cmds);
+        ent
+    }
+
+    /// Builds a key on command for SEAD track `trk_idx`
+    fn key_on(trk_idx: u32, play_len: f32) -> Vec<u8> {
+        let mut cmdtrk = super::CmdTrack::zeroed();
+        cmdtrk.trk_idx = trk_idx;
+        cmdtrk.play_len = play_len;
+
+        // followed by a 14 byte track entry, which the parser skips
+        let mut cmd = vec![0, 30, 2, 0];
+        cmd.extend_from_slice(bytemuck::bytes_of(&cmdtrk));
+        cmd.resize(30, 7);
+        cmd
+    }
+
+    /// Builds a chunk holding the given entries behind an offset table
+    fn chunk(ents: &[Vec<u8>]) -> Vec<u8> {
+        let mut hdr = super::SndChkHdr::zeroed();
+        hdr.nentries = ents.len() as u16;
+
+        let mut chk = bytemuck::bytes_of(&hdr).to_vec();
+        let mut offs = (chk.len() + ents.len() * 4) as u32;
+        for ent in ents.iter() {
+            chk.extend_from_slice(&offs.to_le_bytes());
+            offs += ent.len() as u32;
+        }
+        ents.iter().for_each(|ent| chk.extend_from_slice(&ent[..]));
+        chk
+    }
+
+    /// Builds a SEAD file named "test" out of the given chunks
+    fn sead(chunks: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut hdr = super::Header::zeroed();
+        hdr.id = fourcc(*b"sabf");
+        hdr.nchunks = chunks.len() as u8;
+        hdr.filename_size = 4;
+
+        // the name pads out to the chunk table at 0x20
+        let mut sead = bytemuck::bytes_of(&hdr).to_vec();
+        sead.extend_from_slice(b"test");
+        sead.resize(0x20, 0);
+
+        let mut offs = (sead.len() + chunks.len() * 16) as u32;
+        for (id, chk) in chunks.iter() {
+            let mut ent = super::ChkTblEntry::zeroed();
+            ent.id = *id;
+            ent.offs = offs;
+            sead.extend_from_slice(bytemuck::bytes_of(&ent));
+            offs += chk.len() as u32;
+        }
+        chunks
+            .iter()
+            .for_each(|(_, chk)| sead.extend_from_slice(&chk[..]));
+        sead
+    }
+
+    #[test]
+    fn test_sead_new() {
+        let sead = sead(&[
+            (
+                fourcc(*b"seq "),
+                chunk(&[sequence(&[0, 4, 0, 0]), sequence(&[0, 4, 0, 0])]),
+            ),
+            (fourcc(*b"snd "), chunk(&[sound("bgm_title", 0, 2)])),
+        ]);
+
+        let sead = super::SEAD::new(&sead[..]).unwrap();
+        assert_eq!(sead.name(), "test");
+        assert_eq!(sead.num_sequences(), 2);
+        assert_eq!(sead.sounds().len(), 1);
+        assert_eq!(sead.sounds()[0].name(), "bgm_title");
+    }
+
+    #[test]
+    fn test_sead_magic() {
+        let mut buf = sead(&[(fourcc(*b"snd "), chunk(&[]))]);
+        buf[..4].copy_from_slice(b"mabf");
+        assert!(super::SEAD::new(&buf[..]).is_ok());
+
+        buf[..4].copy_from_slice(b"sab ");
+        assert!(matches!(
+            super::SEAD::new(&buf[..]),
+            Err(super::SEADErr::Magic(m)) if m == fourcc(*b"sab ")
+        ));
+    }
+
+    #[test]
+    fn test_sead_unknown_chunk() {
+        let sead = sead(&[
+            (fourcc(*b"xyz "), chunk(&[])),
+            (fourcc(*b"snd "), chunk(&[sound("se_cursor", 0, 0)])),
+        ]);
+
+        let sead = super::SEAD::new(&sead[..]).unwrap();
+        assert_eq!(sead.num_sequences(), 0);
+        assert_eq!(sead.sounds()[0].name(), "se_cursor");
+        assert_eq!(super::ChunkType::from_id(fourcc(*b"xyz ")), None);
+    }
+
+    /// Builds a command stream: key on track 3, tempo, an unknown command, volume, key off and
+    /// the end command
+    fn cmds() -> Vec<u8> {
+        let mut cmds = key_on(3, 0.0);
+        cmds.extend_from_slice(&[0, 8, 4, 0]);
+        cmds.extend_from_slice(&120f32.to_le_bytes());
+        cmds.extend_from_slice(&[0, 6, 9, 1, 0xAA, 0xBB]);
+        cmds.extend_from_slice(&[0, 8, 5, 0]);
+        cmds.extend_from_slice(&0.5f32.to_le_bytes());
+        cmds.extend_from_slice(&[0, 4, 3, 0, 0, 4, 0, 0]);
+        cmds
+    }
+
+    #[test]
+    fn test_seq_cmds() {
+        use super::{SEADErr, SeqCmd};
+
+        let blob = cmds();
+        let cmds = super::read_cmds(&mut Cursor::new(&blob[..])).unwrap();
+        assert_eq!(cmds.len(), 5);
+        assert!(matches!(
+            cmds[0],
+            SeqCmd::KeyOn(cmdtrk) if cmdtrk.trk_idx == 3
+        ));
+        assert!(matches!(cmds[1], SeqCmd::Tempo(t) if t == 120.0));
+        assert!(matches!(cmds[2], SeqCmd::Other(hdr) if hdr.kind == 9 && hdr.body == 1));
+        assert!(matches!(cmds[3], SeqCmd::Volume(v) if v == 0.5));
+        assert!(matches!(cmds[4], SeqCmd::KeyOff));
+
+        // commands smaller than their header would stall the loop
+        assert!(matches!(
+            super::read_cmds(&mut Cursor::new(&[0, 0, 9, 0][..])),
+            Err(SEADErr::SeqCmdSize(0))
+        ));
+        assert!(matches!(
+            super::read_cmds(&mut Cursor::new(&[0, 4, 3, 0][..])),
+            Err(SEADErr::SeqCmdHdrRead(_))
+        ));
+
+        // a sequence's commands start after its header
+        let seq = sequence(&blob[..]);
+        let seq = super::Sequence::new(&mut Cursor::new(&seq[..])).unwrap();
+        assert_eq!(seq.cmds.len(), 5);
+    }
+
+    #[test]
+    fn test_sead_export_midi() {
+        // two timed notes on track 0 and 1, then a held note on track 0 at 60 BPM
+        let mut first = key_on(0, 0.5);
+        first.extend(key_on(1, 0.25));
+        first.extend_from_slice(&[0, 8, 4, 0]);
+        first.extend_from_slice(&60f32.to_le_bytes());
+        first.extend(key_on(0, 0.0));
+        first.extend_from_slice(&[0, 4, 3, 0, 0, 4, 0, 0]);
+        // then an unsupported command and a note left held at the end of the sequence
+        let mut second = vec![0, 4, 9, 0];
+        second.extend(key_on(2, 0.0));
+        second.extend_from_slice(&[0, 4, 0, 0]);
+
+        let sead = sead(&[(
+            fourcc(*b"seq "),
+            chunk(&[sequence(&first[..]), sequence(&second[..])]),
+        )]);
+        let sead = super::SEAD::new(&sead[..]).unwrap();
+
+        let (tracks, skipped) = sead.midi_tracks();
+        assert_eq!(skipped, [9]);
+        assert_eq!(tracks.len(), 4);
+        assert_eq!(tracks[0].len(), 2);
+        assert_eq!(tracks[0][1].data, [0xFF, 0x51, 3, 0x0F, 0x42, 0x40]);
+
+        let notes = |trk: &[crate::audio::MidiEvent], status: u8| {
+            trk.iter()
+                .filter(|e| e.data[0] & 0xF0 == status)
+                .map(|e| e.tick)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(notes(&tracks[1][..], 0x90), [0, 720]);
+        assert_eq!(notes(&tracks[1][..], 0x80), [480, 720]);
+        assert_eq!(notes(&tracks[2][..], 0x90), [480]);
+        assert_eq!(notes(&tracks[2][..], 0x80), [720]);
+        assert_eq!(tracks[2][0].data, [0x91, 60, 100]);
+        assert_eq!(notes(&tracks[3][..], 0x90), [720]);
+        assert_eq!(notes(&tracks[3][..], 0x80), [720]);
+
+        let f = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(sead.export_midi(f.path().to_str().unwrap()).unwrap(), [9]);
+        let smf = std::fs::read(f.path()).unwrap();
+        assert_eq!(&smf[..4], b"MThd");
+        assert_eq!(&smf[10..12], &4u16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_sead_export_wav() {
+        let samples = [0i16, 1000, -1000, 32767, -32768, 0, 5, -5];
+
+        let mut hdr = super::StreamHdr::zeroed();
+        hdr.nchannels = 2;
+        hdr.codec = super::CODEC_PCM;
+        hdr.sample_rate = 22050;
+        hdr.loop_start = 1;
+        hdr.loop_end = 4;
+        hdr.extra_size = 4;
+        hdr.stream_size = samples.len() as u32 * 2;
+
+        let mut stream = bytemuck::bytes_of(&hdr).to_vec();
+        stream.extend_from_slice(&[0xFF; 4]);
+        samples
+            .iter()
+            .for_each(|s| stream.extend_from_slice(&s.to_le_bytes()));
+
+        let sead = sead(&[(fourcc(*b"mtrl"), chunk(&[stream]))]);
+        let sead = super::SEAD::new(&sead[..]).unwrap();
+        assert_eq!(sead.num_streams(), 1);
+
+        let f = tempfile::NamedTempFile::new().unwrap();
+        sead.export_wav(f.path().to_str().unwrap()).unwrap();
+
+        // RIFF header, the samples, then the loop
+        let wav = std::fs::read(f.path()).unwrap();
+        assert_eq!(wav.len(), 44 + 16 + 68);
+        assert_eq!(&wav[22..24], &2u16.to_le_bytes());
+        assert_eq!(&wav[24..28], &22050u32.to_le_bytes());
+        assert_eq!(&wav[44..46], &0i16.to_le_bytes());
+        assert_eq!(&wav[50..52], &32767i16.to_le_bytes());
+        assert_eq!(&wav[60..64], b"smpl");
+        assert_eq!(&wav[112..116], &1u32.to_le_bytes());
+        assert_eq!(&wav[116..120], &3u32.to_le_bytes());
+
+        assert!(matches!(
+            sead.export_stream_wav(1, f.path().to_str().unwrap()),
+            Err(super::SEADErr::StreamIndex(1))
+        ));
+    }
+
+    #[test]
+    fn test_stream_sizes() {
+        // sizes past the end of the buffer fail before anything is allocated for them
+        for (extra_size, stream_size) in [(u32::MAX, 0), (0, u32::MAX), (4, 5)] {
+            let mut hdr = super::StreamHdr::zeroed();
+            hdr.extra_size = extra_size;
+            hdr.stream_size = stream_size;
+            let mut stream = bytemuck::bytes_of(&hdr).to_vec();
+            stream.extend_from_slice(&[0; 8]);
+
+            assert!(matches!(
+                super::Stream::new(&mut Cursor::new(&stream[..])),
+                Err(super::SEADErr::StreamRead(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_truncated_offsets() {
+        use super::SEADErr;
+
+        // chunks claiming 5 entries, with no offset table
+        let mut hdr = super::SndChkHdr::zeroed();
+        hdr.nentries = 5;
+        assert!(matches!(
+            super::read_sounds(&mut Cursor::new(bytemuck::bytes_of(&hdr)), 0),
+            Err(SEADErr::EntryOffsetRead(_))
+        ));
+
+        let mut hdr = super::MatHdr::zeroed();
+        hdr.nentries = 5;
+        assert!(matches!(
+            super::Material::new(&mut Cursor::new(bytemuck::bytes_of(&hdr)), 0),
+            Err(SEADErr::EntryOffsetRead(_))
+        ));
+
+        // an offset past u32::MAX once added to the chunk's own
+        let mut chk = vec![0; 8];
+        let mut hdr = super::SndChkHdr::zeroed();
+        hdr.nentries = 1;
+        chk.extend_from_slice(bytemuck::bytes_of(&hdr));
+        chk.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(
+            super::read_entry_offsets(&mut Cursor::new(&chk[..]), 8).unwrap(),
+            [8 + u32::MAX as u64]
+        );
+    }
+
+    #[test]
+    fn test_sead_sounds() {
+        let names = ["bgm_title", "se_cursor"];
+        let chk = chunk(&[sound(names[0], 0, 2), sound(names[1], 2, 1)]);
+
+        let sounds = super::read_sounds(&mut Cursor::new(&chk[..]), 0).unwrap();
+        assert_eq!(sounds.len(), 2);
+        assert_eq!(sounds[0].name(), "bgm_title");
+        assert_eq!(sounds[0].sequences(), 0..2);
+        assert_eq!(sounds[1].name(), "se_cursor");
+        assert_eq!(sounds[1].sequences(), 2..3);
+    }
+}
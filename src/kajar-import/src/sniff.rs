@@ -0,0 +1,169 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+};
+
+#[cfg(feature = "cc_psx")]
+use crate::{
+    cc::{
+        drp::{load_drp, DRPErr, Drp},
+        tim::{TIMErr, TIMImage},
+    },
+    Image,
+};
+
+#[cfg(feature = "ct_win")]
+use crate::{
+    ct::resbin::{self, ResBin, ResBinErr},
+    sead::{SEADErr, SEAD},
+};
+
+use crate::fourcc;
+
+/// Most leading bytes `sniff` looks at
+const SNIFF_SIZE: usize = 8;
+
+/// Resource formats that can be told apart by their leading bytes
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileKind {
+    DRP,
+    ResBin,
+    SEAD,
+    TIM,
+}
+
+/// A resource file loaded by `load_any`
+pub enum LoadedResource {
+    #[cfg(feature = "cc_psx")]
+    DRP(Drp),
+    #[cfg(feature = "ct_win")]
+    ResBin(ResBin),
+    #[cfg(feature = "ct_win")]
+    SEAD(SEAD),
+    #[cfg(feature = "cc_psx")]
+    TIM(TIMImage),
+}
+
+/// `load_any` errors
+#[derive(Debug)]
+pub enum LoadErr {
+    #[cfg(feature = "cc_psx")]
+    DRP(DRPErr),
+    FileRead(io::Error),
+    #[cfg(feature = "ct_win")]
+    ResBin(ResBinErr),
+    #[cfg(feature = "ct_win")]
+    SEAD(SEADErr),
+    #[cfg(feature = "cc_psx")]
+    TIM(TIMErr),
+    Unknown,
+
+    /// The format was recognized, but support for it isn't enabled
+    Unsupported(FileKind),
+}
+
+/// Identifies a file's format from its leading bytes. resources.bin is only recognized with the
+/// `ct_win` feature, as its signature is encoded.
+pub fn sniff(data: &[u8]) -> Option<FileKind> {
+    let word = |i: usize| {
+        data.get(i..i + 4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+    };
+
+    let magic = word(0)?;
+    if magic == fourcc(*b"drp\0") {
+        return Some(FileKind::DRP);
+    }
+    if magic == fourcc(*b"sabf") || magic == fourcc(*b"mabf") {
+        return Some(FileKind::SEAD);
+    }
+
+    #[cfg(feature = "ct_win")]
+    if resbin::has_signature(data) {
+        return Some(FileKind::ResBin);
+    }
+
+    // TIM flags only use the low 4 bits
+    match word(4) {
+        Some(flags) if magic == 16 && flags & !0xF == 0 => Some(FileKind::TIM),
+        _ => None,
+    }
+}
+
+/// Loads a resource file of any format `sniff` recognizes
+pub fn load_any(path: &str) -> Result<LoadedResource, LoadErr> {
+    let mut head = Vec::with_capacity(SNIFF_SIZE);
+    File::open(path)
+        .and_then(|f| f.take(SNIFF_SIZE as u64).read_to_end(&mut head))
+        .map_err(|e| LoadErr::FileRead(e))?;
+
+    match sniff(&head[..]).ok_or(LoadErr::Unknown)? {
+        #[cfg(feature = "cc_psx")]
+        FileKind::DRP => load_drp(path)
+            .map(LoadedResource::DRP)
+            .map_err(|e| LoadErr::DRP(e)),
+        #[cfg(feature = "ct_win")]
+        FileKind::ResBin => ResBin::load(path, None)
+            .map(LoadedResource::ResBin)
+            .map_err(|e| LoadErr::ResBin(e)),
+        #[cfg(feature = "ct_win")]
+        FileKind::SEAD => {
+            let buf = std::fs::read(path).map_err(|e| LoadErr::FileRead(e))?;
+            SEAD::new(&buf[..])
+                .map(LoadedResource::SEAD)
+                .map_err(|e| LoadErr::SEAD(e))
+        }
+        #[cfg(feature = "cc_psx")]
+        FileKind::TIM => TIMImage::load(path)
+            .map(LoadedResource::TIM)
+            .map_err(|e| LoadErr::TIM(e)),
+        #[allow(unreachable_patterns)]
+        kind => Err(LoadErr::Unsupported(kind)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_any, sniff, FileKind, LoadErr};
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"drp\0\0\0\x40\0"), Some(FileKind::DRP));
+        assert_eq!(sniff(b"sabf\x02\0\x10\0"), Some(FileKind::SEAD));
+        assert_eq!(sniff(b"mabf"), Some(FileKind::SEAD));
+        assert_eq!(sniff(&[16, 0, 0, 0, 8, 0, 0, 0]), Some(FileKind::TIM));
+
+        // TIM magic with flags that don't make sense
+        assert_eq!(sniff(&[16, 0, 0, 0, 0, 1, 0, 0]), None);
+        assert_eq!(sniff(b"RIFF\x24\0\0\0WAVE"), None);
+        assert_eq!(sniff(b"drp"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[cfg(feature = "ct_win")]
+    #[test]
+    fn test_load_any() {
+        use super::LoadedResource;
+        use crate::ct::resbin::ResBinBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let arc = dir.path().join("resources.bin");
+        let mut builder = ResBinBuilder::new();
+        builder.add("string_1.bin", b"strings".to_vec());
+        builder.build().save(arc.to_str().unwrap()).unwrap();
+
+        let head = std::fs::read(&arc).unwrap();
+        assert_eq!(sniff(&head[..8]), Some(FileKind::ResBin));
+        assert!(matches!(
+            load_any(arc.to_str().unwrap()),
+            Ok(LoadedResource::ResBin(resb)) if resb.len() == 1
+        ));
+
+        let junk = dir.path().join("junk.bin");
+        std::fs::write(&junk, b"not a resource").unwrap();
+        assert!(matches!(
+            load_any(junk.to_str().unwrap()),
+            Err(LoadErr::Unknown)
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_mod_scripting::{api::lua::bevy::LuaEntity, prelude::*};
+
+use std::sync::Mutex;
+
+/// Battle actions defined by Lua scripts, by name. Each action's function is kept in the
+/// registry of the Lua context that defined it, upvalues and all, and runs in that context.
+#[derive(Debug, Default, Resource)]
+pub struct BattleActionRegistry {
+	actions: HashMap<String, LuaRegistryKey>,
+}
+
+impl BattleActionRegistry {
+	/// Whether an action is registered under `name`
+	pub fn contains(&self, name: &str) -> bool {
+		self.actions.contains_key(name)
+	}
+}
+
+/// Lua API for battle actions:
+///
+/// - `register_action(name, function(attacker, target) ... end)` adds or replaces an action
+/// - `run_action(name, attacker, target)` runs it on two entities
+///
+/// Actions change stats through the reflected components, e.g.
+/// `world:get_component(target, world:get_type_by_name("HitPoints"))`.
+#[derive(Default)]
+pub struct BattleActionAPI;
+
+impl APIProvider for BattleActionAPI {
+	type APITarget = Mutex<Lua>;
+	type ScriptContext = Mutex<Lua>;
+	type DocTarget = LuaDocFragment;
+
+	fn attach_api(&mut self, ctx: &mut Self::APITarget) -> Result<(), ScriptError> {
+		let ctx = ctx.get_mut().expect("Lua context poisoned");
+		let globals = ctx.globals();
+
+		globals
+			.set(
+				"register_action",
+				ctx.create_function(register_action)
+					.map_err(|e| ScriptError::new_other(e))?,
+			)
+			.map_err(|e| ScriptError::new_other(e))?;
+		globals
+			.set(
+				"run_action",
+				ctx.create_function(run_action)
+					.map_err(|e| ScriptError::new_other(e))?,
+			)
+			.map_err(|e| ScriptError::new_other(e))?;
+
+		Ok(())
+	}
+}
+
+/// Stores `action` under `name`
+fn register_action(lua: &Lua, (name, action): (String, LuaFunction)) -> LuaResult<()> {
+	let key = lua.create_registry_value(action)?;
+	let world = lua.get_world()?;
+	let mut world = world.write();
+
+	world
+		.get_resource_or_insert_with(BattleActionRegistry::default)
+		.actions
+		.insert(name, key);
+
+	Ok(())
+}
+
+/// Runs the action named `name` with `attacker` acting on `target`
+fn run_action(
+	lua: &Lua,
+	(name, attacker, target): (String, LuaEntity, LuaEntity),
+) -> LuaResult<()> {
+	// the world lock has to be released before the action takes it again
+	let action = {
+		let world = lua.get_world()?;
+		let world = world.read();
+
+		for entity in [&attacker, &target] {
+			let entity = entity.inner()?;
+			if world.get_entity(entity).is_none() {
+				return Err(LuaError::RuntimeError(format!(
+					"no such entity: {:?}",
+					entity
+				)));
+			}
+		}
+
+		let key = world
+			.get_resource::<BattleActionRegistry>()
+			.and_then(|r| r.actions.get(&name))
+			.ok_or_else(|| LuaError::RuntimeError(format!("unknown battle action: {}", name)))?;
+		lua.registry_value::<LuaFunction>(key)?
+	};
+
+	action.call((attacker, target))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bevy_mod_scripting::{api::lua::bevy::LuaWorld, core::world::WorldPointer};
+
+	use crate::battle::{BattleStat, HitPoints};
+
+	#[test]
+	fn test_lua_action_damage() {
+		let mut world = World::new();
+		world.init_resource::<AppTypeRegistry>();
+		world
+			.resource::<AppTypeRegistry>()
+			.write()
+			.register::<HitPoints>();
+
+		let attacker = world.spawn(HitPoints::new(50, 50)).id();
+		let target = world.spawn(HitPoints::new(40, 100)).id();
+		let gone = world.spawn_empty().id();
+		world.despawn(gone);
+
+		let mut lua = Mutex::new(Lua::new());
+		BattleActionAPI.attach_api(&mut lua).unwrap();
+		let lua = lua.into_inner().unwrap();
+
+		let globals = lua.globals();
+		globals
+			.set(
+				"world",
+				LuaWorld::new(unsafe { WorldPointer::new(&mut world) }),
+			)
+			.unwrap();
+		globals.set("attacker", LuaEntity::new(attacker)).unwrap();
+		globals.set("target", LuaEntity::new(target)).unwrap();
+		globals.set("gone", LuaEntity::new(gone)).unwrap();
+
+		lua.load(
+			r#"
+			local function strike(power)
+				return function(attacker, target)
+					local hp = world:get_component(target, world:get_type_by_name("HitPoints"))
+					hp.current = hp.current - power
+				end
+			end
+			register_action("cyclone", strike(25))
+
+			run_action("cyclone", attacker, target)
+		"#,
+		)
+		.exec()
+		.unwrap();
+
+		assert!(lua
+			.load(r#"run_action("slash", attacker, target)"#)
+			.exec()
+			.is_err());
+		assert!(lua
+			.load(r#"run_action("cyclone", attacker, gone)"#)
+			.exec()
+			.is_err());
+		drop(globals);
+		drop(lua);
+
+		assert!(world.resource::<BattleActionRegistry>().contains("cyclone"));
+		assert_eq!(world.get::<HitPoints>(target).unwrap().current(), 15);
+		assert_eq!(world.get::<HitPoints>(attacker).unwrap().current(), 50);
+	}
+}
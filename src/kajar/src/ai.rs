@@ -0,0 +1,283 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_mod_scripting::prelude::*;
+use kajar_import::ai::AICondition;
+
+use crate::battle::{Enemy, HitPoints, ReadyToAct, StatusEffects};
+
+/// Position of an entity in battle, which AI scripts use to refer to it
+#[derive(Clone, Copy, Component, Debug, Default, Eq, Hash, PartialEq, Reflect)]
+#[reflect(Component, LuaProxyable)]
+pub struct BattleSlot(pub u8);
+
+/// Marks an entity that has moved since its last turn
+#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+#[reflect(Component, LuaProxyable)]
+pub struct Moved;
+
+/// An action taken once all of its conditions pass
+#[derive(Clone, Debug)]
+pub struct AIRule {
+	pub conditions: Vec<AICondition>,
+	pub action: u8,
+}
+
+/// Enemy AI script. Rules are tried in order, so a rule without conditions at the end acts as
+/// the fallback.
+#[derive(Clone, Component, Debug, Default)]
+pub struct AIScript {
+	pub rules: Vec<AIRule>,
+}
+
+/// Sent when an enemy has picked its action
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EnemyAction {
+	pub enemy: Entity,
+	pub action: u8,
+}
+
+/// What an AI script can see of a battle entity
+#[derive(Clone, Copy, Debug)]
+struct UnitView {
+	hp: HitPoints,
+	status: StatusEffects,
+	moved: bool,
+}
+
+/// Snapshot of the battle for AI scripts to check conditions against
+#[derive(Clone, Debug, Default)]
+pub struct BattleView {
+	living_enemies: usize,
+	units: HashMap<u8, UnitView>,
+}
+
+impl BattleView {
+	/// Adds an entity to the view
+	pub fn insert(
+		&mut self,
+		slot: BattleSlot,
+		hp: HitPoints,
+		status: StatusEffects,
+		moved: bool,
+		enemy: bool,
+	) {
+		if enemy && hp.is_alive() {
+			self.living_enemies += 1;
+		}
+
+		self.units.insert(slot.0, UnitView { hp, status, moved });
+	}
+
+	/// Number of enemies still standing
+	pub fn living_enemies(&self) -> usize {
+		self.living_enemies
+	}
+
+	/// Status effects on the entity in `slot`
+	pub fn status(&self, slot: u8) -> Option<StatusEffects> {
+		self.units.get(&slot).map(|u| u.status)
+	}
+
+	/// HP of the entity in `slot` as a fraction of its max HP
+	pub fn hp_ratio(&self, slot: u8) -> Option<f32> {
+		self.units.get(&slot).map(|u| u.hp.ratio())
+	}
+
+	/// Whether the entity in `slot` is standing. Empty slots count as dead.
+	pub fn is_alive(&self, slot: u8) -> bool {
+		self.units.get(&slot).is_some_and(|u| u.hp.is_alive())
+	}
+
+	/// Whether the entity in `slot` has moved since its last turn
+	pub fn has_moved(&self, slot: u8) -> bool {
+		self.units.get(&slot).is_some_and(|u| u.moved)
+	}
+}
+
+/// Checks an AI condition against the battle
+pub fn evaluate(cond: &AICondition, world: &BattleView) -> bool {
+	match cond {
+		AICondition::HPLessThanHalf(c) => world
+			.units
+			.get(&c.target)
+			.is_some_and(|u| u.hp.is_below_half()),
+		AICondition::CheckForStatus(c) => world.status(c.target).is_some_and(|s| {
+			// the offset picks which byte of the status word to test
+			s.bits()
+				.checked_shr(c.offs as u32 * 8)
+				.is_some_and(|b| b as u8 & c.check_bits != 0)
+		}),
+		AICondition::CheckIfMoved(c) => world.has_moved(c.entity),
+		AICondition::CheckEntityStatus(c) => world.is_alive(c.entity) != c.is_dead(),
+		AICondition::CheckMaxLivingEntities(c) => world.living_enemies() <= c.n as usize,
+	}
+}
+
+/// Picks an action for each enemy whose turn has come, from the first rule of its script whose
+/// conditions all pass
+#[allow(clippy::type_complexity)]
+pub fn choose_enemy_action(
+	mut ready: EventReader<ReadyToAct>,
+	scripts: Query<&AIScript>,
+	units: Query<(
+		&BattleSlot,
+		&HitPoints,
+		Option<&StatusEffects>,
+		Option<&Moved>,
+		Option<&Enemy>,
+	)>,
+	mut actions: EventWriter<EnemyAction>,
+) {
+	let ready = ready
+		.iter()
+		.filter_map(|r| scripts.get(r.0).ok().map(|s| (r.0, s)))
+		.collect::<Vec<_>>();
+	if ready.is_empty() {
+		return;
+	}
+
+	let mut view = BattleView::default();
+	for (slot, hp, status, moved, enemy) in units.iter() {
+		view.insert(
+			*slot,
+			*hp,
+			status.copied().unwrap_or_default(),
+			moved.is_some(),
+			enemy.is_some(),
+		);
+	}
+
+	for (enemy, script) in ready {
+		let rule = script
+			.rules
+			.iter()
+			.find(|r| r.conditions.iter().all(|c| evaluate(c, &view)));
+
+		if let Some(rule) = rule {
+			actions.send(EnemyAction {
+				enemy,
+				action: rule.action,
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Decodes a condition from its opcode and argument bytes
+	fn cond(kind: u8, args: [u8; 3]) -> AICondition {
+		AICondition::parse(kind, &mut &args[..]).unwrap()
+	}
+
+	fn view() -> BattleView {
+		let mut view = BattleView::default();
+		view.insert(
+			BattleSlot(0),
+			HitPoints::new(40, 100),
+			StatusEffects::POISON,
+			true,
+			false,
+		);
+		view.insert(
+			BattleSlot(3),
+			HitPoints::new(80, 100),
+			StatusEffects::empty(),
+			false,
+			true,
+		);
+		view.insert(
+			BattleSlot(4),
+			HitPoints::new(0, 100),
+			StatusEffects::empty(),
+			false,
+			true,
+		);
+		view
+	}
+
+	#[test]
+	fn test_hp_less_than_half() {
+		let view = view();
+		assert!(evaluate(&cond(0, [0, 0, 0]), &view));
+		assert!(!evaluate(&cond(0, [3, 0, 0]), &view));
+		assert!(!evaluate(&cond(0, [9, 0, 0]), &view));
+		assert_eq!(view.hp_ratio(3), Some(0.8));
+	}
+
+	#[test]
+	fn test_check_for_status() {
+		let view = view();
+		let poison = StatusEffects::POISON.bits() as u8;
+		assert!(evaluate(&cond(1, [0, 0, poison]), &view));
+		assert!(!evaluate(&cond(1, [0, 1, poison]), &view));
+		assert!(!evaluate(&cond(1, [0, 2, 0xFF]), &view));
+		assert!(!evaluate(&cond(1, [3, 0, poison]), &view));
+	}
+
+	#[test]
+	fn test_check_if_moved() {
+		let view = view();
+		assert!(evaluate(&cond(2, [0, 0, 0]), &view));
+		assert!(!evaluate(&cond(2, [0, 3, 0]), &view));
+	}
+
+	#[test]
+	fn test_check_entity_status() {
+		let view = view();
+		assert!(evaluate(&cond(3, [0, 3, 0]), &view));
+		assert!(!evaluate(&cond(3, [0, 3, 1]), &view));
+		assert!(evaluate(&cond(3, [0, 4, 1]), &view));
+		assert!(evaluate(&cond(3, [0, 9, 1]), &view));
+	}
+
+	#[test]
+	fn test_check_max_living_entities() {
+		let view = view();
+		assert_eq!(view.living_enemies(), 1);
+		assert!(evaluate(&cond(4, [1, 0, 0]), &view));
+		assert!(!evaluate(&cond(4, [0, 0, 0]), &view));
+	}
+
+	#[test]
+	fn test_choose_enemy_action() {
+		let mut app = App::new();
+		app.add_event::<ReadyToAct>()
+			.add_event::<EnemyAction>()
+			.add_system(choose_enemy_action);
+
+		let script = AIScript {
+			rules: vec![
+				AIRule {
+					conditions: vec![cond(0, [3, 0, 0]), cond(4, [1, 0, 0])],
+					action: 1,
+				},
+				AIRule {
+					conditions: vec![],
+					action: 0,
+				},
+			],
+		};
+		let enemy = app
+			.world
+			.spawn((
+				BattleSlot(3),
+				HitPoints::new(80, 100),
+				Enemy::default(),
+				script,
+			))
+			.id();
+
+		let mut turn = |app: &mut App| {
+			app.world.send_event(ReadyToAct(enemy));
+			app.update();
+			let events = app.world.resource::<Events<EnemyAction>>();
+			events.iter_current_update_events().next().copied()
+		};
+
+		assert_eq!(turn(&mut app), Some(EnemyAction { enemy, action: 0 }));
+
+		*app.world.get_mut::<HitPoints>(enemy).unwrap() = HitPoints::new(30, 100);
+		assert_eq!(turn(&mut app), Some(EnemyAction { enemy, action: 1 }));
+	}
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+use kajar_import::ct::resbin::{ResBin, ResBinErr};
+
+use std::{collections::HashMap, path::PathBuf};
+
+/// Archives opened on first lookup and kept around for later ones
+#[derive(Resource)]
+pub struct ArchiveCache {
+	ctexe: Option<String>,
+	archives: HashMap<String, ResBin>,
+}
+
+impl ArchiveCache {
+	/// Creates an empty cache, decrypting archives with the key from `ctexe` if given
+	pub fn new(ctexe: Option<&str>) -> Self {
+		Self {
+			ctexe: ctexe.map(|s| s.to_string()),
+			archives: HashMap::new(),
+		}
+	}
+
+	/// Fetches an entry's data, opening `archive` if it hasn't been already
+	pub fn get_entry(&mut self, archive: &str, entry: &str) -> Result<&[u8], ResBinErr> {
+		if !self.archives.contains_key(archive) {
+			let res = ResBin::load(archive, self.ctexe.as_deref())?;
+			self.archives.insert(archive.to_string(), res);
+		}
+
+		self.archives[archive]
+			.get(entry)
+			.ok_or_else(|| ResBinErr::EntryPath(PathBuf::from(entry)))
+	}
+
+	/// Drops an opened archive, freeing its decompressed entries
+	pub fn evict(&mut self, archive: &str) {
+		self.archives.remove(archive);
+	}
+}
@@ -1,10 +1,20 @@
 use bevy::prelude::*;
 use bevy_mod_scripting::prelude::*;
 use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+
+use std::time::Duration;
+
+use crate::{
+	ai::{choose_enemy_action, EnemyAction},
+	tech::{update_combos, AvailableCombos, TechList},
+	time::GameTime,
+};
 
 bitflags! {
 	/// Enemy attribute flags
-	#[derive(Clone, Copy, Debug, Ord, PartialOrd, Eq, PartialEq)]
+	#[derive(Clone, Copy, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Reflect)]
+	#[reflect_value]
 	pub struct EnemyFlags: u16 {
 		const BOSS = 1;
 
@@ -13,18 +23,38 @@ bitflags! {
 	}
 }
 
+bitflags! {
+	/// Status ailments afflicting a battle entity
+	#[derive(Clone, Copy, Component, Debug, Default, Eq, PartialEq, Reflect)]
+	#[reflect_value(Component, LuaProxyable)]
+	pub struct StatusEffects: u16 {
+		/// Loses HP every turn
+		const POISON = 1;
+		const SLEEP = 2;
+		const STOP = 4;
+		const CHAOS = 8;
+		const BLIND = 16;
+		const SLOW = 32;
+		const LOCK = 64;
+	}
+}
+
+/// Fraction of max HP lost to poison each turn
+const POISON_DIVISOR: i16 = 16;
+
+/// ATB gauge points gained per second for each point of speed
+const ATB_RATE: f32 = 10.0;
+
 /// Attack stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Attack {
 	current: i16,
 	normal: i16,
 }
 
-impl
-
 /// Defense stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Defense {
 	current: i16,
@@ -32,7 +62,7 @@ pub struct Defense {
 }
 
 /// Strength stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Strength {
 	current: i16,
@@ -40,7 +70,7 @@ pub struct Strength {
 }
 
 /// Speed stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Speed {
 	current: i16,
@@ -48,7 +78,7 @@ pub struct Speed {
 }
 
 /// Accuracy stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Accuracy {
 	current: i16,
@@ -56,7 +86,7 @@ pub struct Accuracy {
 }
 
 /// Evasion stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Evasion {
 	current: i16,
@@ -64,7 +94,7 @@ pub struct Evasion {
 }
 
 /// Magic stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Magic {
 	current: i16,
@@ -72,7 +102,7 @@ pub struct Magic {
 }
 
 /// Stamina stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct Stamina {
 	current: i16,
@@ -80,7 +110,7 @@ pub struct Stamina {
 }
 
 /// Magic defense stat for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct MagicDefense {
 	current: i16,
@@ -88,24 +118,302 @@ pub struct MagicDefense {
 }
 
 /// Hit points for battle entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct HitPoints {
 	current: i16,
 	max: i16,
 }
 
+impl HitPoints {
+	/// Hit points at `current` out of `max`
+	pub fn new(current: i16, max: i16) -> Self {
+		Self { current, max }
+	}
+
+	/// Whether the entity is still standing
+	pub fn is_alive(&self) -> bool {
+		self.current > 0
+	}
+
+	/// Whether the entity is down to less than half its max HP
+	pub fn is_below_half(&self) -> bool {
+		(self.current as i32) * 2 < self.max as i32
+	}
+
+	/// Current HP as a fraction of max HP
+	pub fn ratio(&self) -> f32 {
+		if self.max > 0 {
+			self.current as f32 / self.max as f32
+		} else {
+			0.0
+		}
+	}
+}
+
 /// Magic points for player entities
-#[derive(Clone, Copy, Component, Debug, Reflect)]
+#[derive(Clone, Copy, Component, Debug, Default, Deserialize, Reflect, Serialize)]
 #[reflect(Component, LuaProxyable)]
 pub struct MagicPoints {
 	current: i16,
 	max: i16,
 }
 
-/// Enemy entity
+/// A stat that can be read and permanently raised or lowered, e.g. by equipment
+pub trait BattleStat {
+	/// The stat's value after temporary effects
+	fn current(&self) -> i16;
+
+	/// Raises or lowers the stat by `delta`
+	fn shift(&mut self, delta: i16);
+}
+
+macro_rules! battle_stat {
+	($($stat: ty),*) => {
+		$(
+			impl BattleStat for $stat {
+				fn current(&self) -> i16 {
+					self.current
+				}
+
+				fn shift(&mut self, delta: i16) {
+					self.current = self.current.saturating_add(delta);
+					self.normal = self.normal.saturating_add(delta);
+				}
+			}
+		)*
+	};
+}
+
+battle_stat!(
+	Accuracy,
+	Attack,
+	Defense,
+	Evasion,
+	Magic,
+	MagicDefense,
+	Speed,
+	Stamina,
+	Strength
+);
+
+impl BattleStat for HitPoints {
+	fn current(&self) -> i16 {
+		self.current
+	}
+
+	/// Shifts max HP, keeping current HP within it
+	fn shift(&mut self, delta: i16) {
+		self.max = self.max.saturating_add(delta);
+		self.current = self.current.min(self.max);
+	}
+}
+
+impl BattleStat for MagicPoints {
+	fn current(&self) -> i16 {
+		self.current
+	}
+
+	/// Shifts max MP, keeping current MP within it
+	fn shift(&mut self, delta: i16) {
+		self.max = self.max.saturating_add(delta);
+		self.current = self.current.min(self.max);
+	}
+}
+
+/// Active time battle gauge, which fills with speed until the entity can act
+#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+#[reflect(Component, LuaProxyable)]
+pub struct AtbGauge {
+	current: f32,
+	max: f32,
+}
+
+impl AtbGauge {
+	/// An empty gauge that fills at `max`
+	pub fn new(max: f32) -> Self {
+		Self { current: 0.0, max }
+	}
+
+	/// Whether the gauge is full
+	pub fn is_full(&self) -> bool {
+		self.current >= self.max
+	}
+
+	/// Empties the gauge after the entity acts
+	pub fn reset(&mut self) {
+		self.current = 0.0;
+	}
+}
+
+/// Sent when an enemy is defeated, carrying its rewards
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EnemyDefeated {
+	pub enemy: Entity,
+	pub xp: u32,
+	pub tp: u16,
+}
+
+/// Sent when a boss is defeated
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BossDefeated(pub Entity);
+
+/// Marks a defeated enemy that stays on the map
+#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+#[reflect(Component, LuaProxyable)]
+pub struct Defeated;
+
+/// Sent when an entity's ATB gauge fills
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReadyToAct(pub Entity);
+
+/// Turns left on each status effect, indexed by the effect's bit position. An effect with no
+/// turns left on it, such as one set on `StatusEffects` directly, lasts until it is cured.
+#[derive(Clone, Copy, Component, Debug, Default, Reflect)]
+#[reflect(Component, LuaProxyable)]
+pub struct StatusTimers {
+	turns: [u8; 16],
+}
+
+impl StatusTimers {
+	/// Inflicts `effect` for `turns` turns, replacing any time left on it. 0 turns never expires.
+	pub fn inflict(&mut self, effects: &mut StatusEffects, effect: StatusEffects, turns: u8) {
+		for bit in effect.iter() {
+			self.turns[bit.bits().trailing_zeros() as usize] = turns;
+		}
+		effects.insert(effect);
+	}
+
+	/// Turns left on `effect`
+	pub fn remaining(&self, effect: StatusEffects) -> u8 {
+		self.turns
+			.get(effect.bits().trailing_zeros() as usize)
+			.copied()
+			.unwrap_or(0)
+	}
+}
+
+/// Battle stats that modifiers can target
+#[derive(Clone, Copy, Debug, Deserialize, Eq, FromReflect, PartialEq, Reflect, Serialize)]
+pub enum Stat {
+	Accuracy,
+	Attack,
+	Defense,
+	Evasion,
+	Magic,
+	MagicDefense,
+	Speed,
+	Stamina,
+	Strength,
+}
+
+/// How a modifier changes a stat
+#[derive(Clone, Copy, Debug, Deserialize, FromReflect, PartialEq, Reflect, Serialize)]
+pub enum Delta {
+	/// Percentage of the stat's normal value
+	Percent(i16),
+	Flat(i16),
+}
+
+/// A temporary buff or debuff on one stat
+#[derive(Clone, Copy, Debug, Deserialize, FromReflect, PartialEq, Reflect, Serialize)]
+pub struct StatModifier {
+	pub stat: Stat,
+	pub delta: Delta,
+
+	/// Entity that applied the modifier. Entities don't outlive the session, so this isn't saved.
+	#[serde(skip)]
+	pub source: Option<Entity>,
+
+	/// Turns the modifier is in effect for. Stats are recomputed before modifiers count down,
+	/// so a modifier applied with 2 turns applies for 2 turns.
+	pub turns: u8,
+}
+
+/// Active stat modifiers on a battle entity. Percentages stack additively.
+#[derive(Clone, Component, Debug, Default, Deserialize, Reflect, Serialize)]
+#[reflect(Component, LuaProxyable)]
+pub struct StatModifiers {
+	mods: Vec<StatModifier>,
+}
+
+impl StatModifiers {
+	/// Adds a modifier
+	pub fn push(&mut self, modifier: StatModifier) {
+		self.mods.push(modifier);
+	}
+
+	/// Counts down each modifier by a turn, dropping those that wear off
+	pub fn tick(&mut self) {
+		self.mods.retain_mut(|m| {
+			m.turns = m.turns.saturating_sub(1);
+			m.turns > 0
+		});
+	}
+
+	/// `normal` with every modifier on `stat` applied, clamped to the stat's range
+	pub fn apply(&self, stat: Stat, normal: i16) -> i16 {
+		let (pct, flat) =
+			self.mods
+				.iter()
+				.filter(|m| m.stat == stat)
+				.fold((0, 0), |(pct, flat), m| match m.delta {
+					Delta::Percent(p) => (pct + p as i32, flat),
+					Delta::Flat(f) => (pct, flat + f as i32),
+				});
+		let normal = normal as i32;
+
+		(normal + normal * pct / 100 + flat).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+	}
+}
+
+/// Spell elements
+#[derive(Clone, Copy, Debug, Eq, FromReflect, Hash, PartialEq, Reflect)]
+pub enum Element {
+	Fire,
+	Lightning,
+	Shadow,
+	Water,
+}
+
+/// How strongly a battle entity takes each element's damage, as a multiplier indexed by
+/// `Element`. A negative multiplier absorbs the element, healing instead.
 #[derive(Clone, Copy, Component, Debug, Reflect)]
 #[reflect(Component, LuaProxyable)]
+pub struct Affinity {
+	mults: [f32; 4],
+}
+
+impl Default for Affinity {
+	fn default() -> Self {
+		Self { mults: [1.0; 4] }
+	}
+}
+
+impl Affinity {
+	/// Multiplier for damage of `element`
+	pub fn get(&self, element: Element) -> f32 {
+		self.mults[element as usize]
+	}
+
+	/// Sets the multiplier for damage of `element`
+	pub fn set(&mut self, element: Element, mult: f32) {
+		self.mults[element as usize] = mult;
+	}
+}
+
+/// Sent when a spell hits a battle entity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpellHit {
+	pub caster: Entity,
+	pub target: Entity,
+	pub element: Element,
+	pub power: i16,
+}
+
+/// Enemy entity
+#[derive(Clone, Component, Debug, Default, Reflect)]
+#[reflect(Component, LuaProxyable)]
 pub struct Enemy {
 	flags: EnemyFlags,
 	name: String,
@@ -128,3 +436,510 @@ pub struct BattleUnit {
 	atk: Attack,
 	sta: Stamina,
 }
+
+/// Advances status effects by a turn: poison drains HP, timers count down, and effects whose
+/// time is up are removed. Effects without a timer are left alone.
+pub fn tick_status(
+	mut units: Query<(
+		&mut StatusEffects,
+		&mut StatusTimers,
+		Option<&mut HitPoints>,
+	)>,
+) {
+	for (mut effects, mut timers, hp) in units.iter_mut() {
+		if effects.is_empty() {
+			continue;
+		}
+
+		if let Some(mut hp) = hp {
+			if effects.contains(StatusEffects::POISON) {
+				let dmg = (hp.max / POISON_DIVISOR).max(1);
+				hp.current = (hp.current - dmg).max(0);
+			}
+		}
+
+		let current = *effects;
+		for bit in current.iter() {
+			let timer = &mut timers.turns[bit.bits().trailing_zeros() as usize];
+			if *timer == 0 {
+				continue;
+			}
+
+			*timer -= 1;
+			if *timer == 0 {
+				effects.remove(bit);
+			}
+		}
+	}
+}
+
+/// Fills ATB gauges in proportion to speed for the play time elapsed since the last run,
+/// announcing each entity whose gauge fills up
+pub fn charge_atb(
+	time: Res<GameTime>,
+	mut last: Local<Option<Duration>>,
+	mut units: Query<(Entity, &mut AtbGauge, &Speed)>,
+	mut ready: EventWriter<ReadyToAct>,
+) {
+	let dt = last.map_or(Duration::ZERO, |last| time.0.saturating_sub(last));
+	*last = Some(time.0);
+
+	for (entity, mut gauge, spd) in units.iter_mut() {
+		if gauge.is_full() {
+			continue;
+		}
+
+		gauge.current += spd.current.max(0) as f32 * ATB_RATE * dt.as_secs_f32();
+		if gauge.is_full() {
+			gauge.current = gauge.max;
+			ready.send(ReadyToAct(entity));
+		}
+	}
+}
+
+/// Counts down stat modifiers by a turn
+pub fn tick_modifiers(mut units: Query<&mut StatModifiers>) {
+	for mut mods in units.iter_mut() {
+		mods.tick();
+	}
+}
+
+/// Sets each stat's current value from its normal value and the entity's active modifiers
+#[allow(clippy::type_complexity)]
+pub fn recompute_stats(
+	mut units: Query<(
+		&StatModifiers,
+		Option<&mut Accuracy>,
+		Option<&mut Attack>,
+		Option<&mut Defense>,
+		Option<&mut Evasion>,
+		Option<&mut Magic>,
+		Option<&mut MagicDefense>,
+		Option<&mut Speed>,
+		Option<&mut Stamina>,
+		Option<&mut Strength>,
+	)>,
+) {
+	for (mods, acc, atk, def, eva, mag, mdef, spd, sta, strength) in units.iter_mut() {
+		if let Some(mut s) = acc {
+			s.current = mods.apply(Stat::Accuracy, s.normal);
+		}
+		if let Some(mut s) = atk {
+			s.current = mods.apply(Stat::Attack, s.normal);
+		}
+		if let Some(mut s) = def {
+			s.current = mods.apply(Stat::Defense, s.normal);
+		}
+		if let Some(mut s) = eva {
+			s.current = mods.apply(Stat::Evasion, s.normal);
+		}
+		if let Some(mut s) = mag {
+			s.current = mods.apply(Stat::Magic, s.normal);
+		}
+		if let Some(mut s) = mdef {
+			s.current = mods.apply(Stat::MagicDefense, s.normal);
+		}
+		if let Some(mut s) = spd {
+			s.current = mods.apply(Stat::Speed, s.normal);
+		}
+		if let Some(mut s) = sta {
+			s.current = mods.apply(Stat::Stamina, s.normal);
+		}
+		if let Some(mut s) = strength {
+			s.current = mods.apply(Stat::Strength, s.normal);
+		}
+	}
+}
+
+/// Deals spell damage, reduced by the target's magic defense as a percentage and then scaled by
+/// its affinity for the spell's element. Damage that comes out negative heals.
+pub fn apply_spell_damage(
+	mut hits: EventReader<SpellHit>,
+	mut targets: Query<(&mut HitPoints, Option<&MagicDefense>, Option<&Affinity>)>,
+) {
+	for hit in hits.iter() {
+		let Ok((mut hp, mdef, affinity)) = targets.get_mut(hit.target) else {
+			continue;
+		};
+
+		let mdef = mdef.map_or(0, |m| m.current.clamp(0, 100)) as f32;
+		let mult = affinity.copied().unwrap_or_default().get(hit.element);
+		let dmg = (hit.power as f32 * (100.0 - mdef) / 100.0 * mult).round() as i32;
+
+		hp.current = (hp.current as i32 - dmg).clamp(0, hp.max as i32) as i16;
+	}
+}
+
+/// Handles enemies whose HP has run out: hands out their rewards, then despawns them, or marks
+/// them `Defeated` if they should stay on the map
+pub fn handle_enemy_death(
+	mut commands: Commands,
+	enemies: Query<(Entity, &Enemy, &HitPoints), Without<Defeated>>,
+	mut defeated: EventWriter<EnemyDefeated>,
+	mut boss_defeated: EventWriter<BossDefeated>,
+) {
+	for (entity, enemy, hp) in enemies.iter() {
+		if hp.is_alive() {
+			continue;
+		}
+
+		defeated.send(EnemyDefeated {
+			enemy: entity,
+			xp: enemy.xp,
+			tp: enemy.tp,
+		});
+		if enemy.flags.contains(EnemyFlags::BOSS) {
+			boss_defeated.send(BossDefeated(entity));
+		}
+
+		if enemy.flags.contains(EnemyFlags::NO_DESPAWN) {
+			commands.entity(entity).insert(Defeated);
+		} else {
+			commands.entity(entity).despawn();
+		}
+	}
+}
+
+/// Battle events and systems. A turn passes whenever an entity becomes ready to act, counting
+/// down status effects and stat modifiers.
+pub struct BattlePlugin;
+
+impl Plugin for BattlePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_event::<ReadyToAct>()
+			.add_event::<SpellHit>()
+			.add_event::<EnemyDefeated>()
+			.add_event::<BossDefeated>()
+			.add_event::<EnemyAction>()
+			.init_resource::<TechList>()
+			.init_resource::<AvailableCombos>()
+			.add_system(charge_atb)
+			.add_system(
+				tick_status
+					.after(charge_atb)
+					.run_if(on_event::<ReadyToAct>()),
+			)
+			// modifiers apply before they count down, so one lasting a turn is felt for it
+			.add_systems(
+				(
+					recompute_stats,
+					tick_modifiers.run_if(on_event::<ReadyToAct>()),
+				)
+					.chain()
+					.after(charge_atb),
+			)
+			.add_systems((choose_enemy_action, update_combos).after(charge_atb))
+			.add_systems((apply_spell_damage, handle_enemy_death).chain());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_poison() {
+		let mut app = App::new();
+		app.add_system(tick_status);
+
+		let mut effects = StatusEffects::empty();
+		let mut timers = StatusTimers::default();
+		timers.inflict(&mut effects, StatusEffects::POISON, 3);
+		let unit = app
+			.world
+			.spawn((
+				effects,
+				timers,
+				HitPoints {
+					current: 160,
+					max: 160,
+				},
+			))
+			.id();
+
+		for hp in [150, 140, 130] {
+			assert!(app
+				.world
+				.get::<StatusEffects>(unit)
+				.unwrap()
+				.contains(StatusEffects::POISON));
+			app.update();
+			assert_eq!(app.world.get::<HitPoints>(unit).unwrap().current, hp);
+		}
+
+		assert!(app.world.get::<StatusEffects>(unit).unwrap().is_empty());
+		assert_eq!(
+			app.world
+				.get::<StatusTimers>(unit)
+				.unwrap()
+				.remaining(StatusEffects::POISON),
+			0
+		);
+
+		app.update();
+		assert_eq!(app.world.get::<HitPoints>(unit).unwrap().current, 130);
+	}
+
+	#[test]
+	fn test_status_no_expiry() {
+		let mut app = App::new();
+		app.add_system(tick_status);
+
+		// set directly, and inflicted without a duration
+		let mut effects = StatusEffects::SLEEP;
+		let mut timers = StatusTimers::default();
+		timers.inflict(&mut effects, StatusEffects::LOCK, 0);
+		timers.inflict(&mut effects, StatusEffects::SLOW, 1);
+		let unit = app.world.spawn((effects, timers)).id();
+
+		for _ in 0..3 {
+			app.update();
+		}
+		assert_eq!(
+			*app.world.get::<StatusEffects>(unit).unwrap(),
+			StatusEffects::SLEEP | StatusEffects::LOCK
+		);
+	}
+
+	#[test]
+	fn test_battle_plugin() {
+		let mut app = App::new();
+		app.insert_resource(GameTime(Duration::ZERO))
+			.add_plugin(BattlePlugin);
+
+		let mut effects = StatusEffects::empty();
+		let mut timers = StatusTimers::default();
+		timers.inflict(&mut effects, StatusEffects::POISON, 2);
+		let mut mods = StatModifiers::default();
+		mods.push(StatModifier {
+			stat: Stat::Attack,
+			delta: Delta::Flat(10),
+			source: None,
+			turns: 1,
+		});
+		let unit = app
+			.world
+			.spawn((
+				AtbGauge::new(100.0),
+				Speed {
+					current: 10,
+					normal: 10,
+				},
+				Attack {
+					current: 20,
+					normal: 20,
+				},
+				HitPoints::new(160, 160),
+				effects,
+				timers,
+				mods,
+			))
+			.id();
+		let hp = |app: &App| app.world.get::<HitPoints>(unit).unwrap().current;
+		let atk = |app: &App| app.world.get::<Attack>(unit).unwrap().current;
+
+		// no turn has passed yet, but the modifier already applies
+		app.update();
+		assert_eq!((hp(&app), atk(&app)), (160, 30));
+
+		// the gauge fills, which passes a turn
+		app.world.resource_mut::<GameTime>().0 += Duration::from_secs(1);
+		app.update();
+		assert!(app.world.get::<AtbGauge>(unit).unwrap().is_full());
+		assert_eq!(hp(&app), 150);
+
+		// nothing ticks while the gauge stays full
+		for _ in 0..3 {
+			app.world.resource_mut::<GameTime>().0 += Duration::from_secs(1);
+			app.update();
+		}
+		assert_eq!((hp(&app), atk(&app)), (150, 20));
+		assert_eq!(
+			app.world
+				.get::<StatusTimers>(unit)
+				.unwrap()
+				.remaining(StatusEffects::POISON),
+			1
+		);
+	}
+
+	#[test]
+	fn test_atb_order() {
+		let mut app = App::new();
+		app.add_event::<ReadyToAct>()
+			.insert_resource(GameTime(Duration::from_secs(60)))
+			.add_system(charge_atb);
+
+		let slow = app
+			.world
+			.spawn((
+				AtbGauge::new(100.0),
+				Speed {
+					current: 5,
+					normal: 5,
+				},
+			))
+			.id();
+		let fast = app
+			.world
+			.spawn((
+				AtbGauge::new(100.0),
+				Speed {
+					current: 10,
+					normal: 10,
+				},
+			))
+			.id();
+
+		let mut reader = app.world.resource::<Events<ReadyToAct>>().get_reader();
+		let mut order = Vec::new();
+		for _ in 0..5 {
+			app.update();
+			app.world.resource_mut::<GameTime>().0 += Duration::from_millis(500);
+
+			let events = app.world.resource::<Events<ReadyToAct>>();
+			order.extend(reader.iter(events).map(|e| e.0));
+		}
+
+		assert_eq!(order, [fast, slow]);
+		assert!(app.world.get::<AtbGauge>(fast).unwrap().is_full());
+		assert_eq!(app.world.get::<AtbGauge>(slow).unwrap().current, 100.0);
+	}
+
+	#[test]
+	fn test_stat_modifiers() {
+		let mut app = App::new();
+		app.add_systems((recompute_stats, tick_modifiers).chain());
+
+		let buff = StatModifier {
+			stat: Stat::Attack,
+			delta: Delta::Percent(50),
+			source: None,
+			turns: 2,
+		};
+		let mut mods = StatModifiers::default();
+		mods.push(buff);
+		mods.push(buff);
+		mods.push(StatModifier {
+			stat: Stat::Defense,
+			delta: Delta::Flat(i16::MAX),
+			..buff
+		});
+
+		let unit = app
+			.world
+			.spawn((
+				mods,
+				Attack {
+					current: 33,
+					normal: 33,
+				},
+				Defense {
+					current: 90,
+					normal: 90,
+				},
+			))
+			.id();
+
+		// the buffs last their 2 turns
+		for _ in 0..2 {
+			app.update();
+			assert_eq!(app.world.get::<Attack>(unit).unwrap().current, 66);
+			assert_eq!(app.world.get::<Defense>(unit).unwrap().current, i16::MAX);
+		}
+
+		app.update();
+		assert_eq!(app.world.get::<Attack>(unit).unwrap().current, 33);
+		assert_eq!(app.world.get::<Defense>(unit).unwrap().current, 90);
+	}
+
+	#[test]
+	fn test_spell_affinity() {
+		let mut app = App::new();
+		app.add_event::<SpellHit>().add_system(apply_spell_damage);
+
+		let mut absorb = Affinity::default();
+		absorb.set(Element::Fire, -1.0);
+		absorb.set(Element::Water, 2.0);
+
+		let caster = app.world.spawn_empty().id();
+		let target = app
+			.world
+			.spawn((
+				absorb,
+				HitPoints::new(100, 200),
+				MagicDefense {
+					current: 50,
+					normal: 50,
+				},
+			))
+			.id();
+		let plain = app.world.spawn(HitPoints::new(100, 200)).id();
+
+		let mut cast = |target: Entity, element: Element, power: i16| {
+			app.world.send_event(SpellHit {
+				caster,
+				target,
+				element,
+				power,
+			});
+			app.update();
+			app.world.get::<HitPoints>(target).unwrap().current
+		};
+
+		assert_eq!(cast(target, Element::Fire, 40), 120);
+		assert_eq!(cast(target, Element::Fire, 400), 200);
+		assert_eq!(cast(target, Element::Water, 40), 160);
+		assert_eq!(cast(target, Element::Lightning, 40), 140);
+		assert_eq!(cast(plain, Element::Fire, 40), 60);
+	}
+
+	#[test]
+	fn test_enemy_death() {
+		let mut app = App::new();
+		app.add_event::<EnemyDefeated>()
+			.add_event::<BossDefeated>()
+			.add_system(handle_enemy_death);
+
+		let mut spawn = |flags: EnemyFlags, hp: i16| {
+			app.world
+				.spawn((
+					Enemy {
+						flags,
+						name: "Nu".to_owned(),
+						tp: 1,
+						xp: 10,
+					},
+					HitPoints::new(hp, 10),
+				))
+				.id()
+		};
+		let alive = spawn(EnemyFlags::empty(), 5);
+		let grunt = spawn(EnemyFlags::empty(), 0);
+		let boss = spawn(EnemyFlags::BOSS | EnemyFlags::NO_DESPAWN, 0);
+
+		app.update();
+		app.update();
+
+		assert!(app.world.get_entity(alive).is_some());
+		assert!(app.world.get_entity(grunt).is_none());
+		assert!(app.world.get::<Defeated>(boss).is_some());
+
+		let events = app.world.resource::<Events<EnemyDefeated>>();
+		let mut rewards = events
+			.get_reader()
+			.iter(events)
+			.map(|e| e.enemy)
+			.collect::<Vec<_>>();
+		rewards.sort();
+		assert_eq!(rewards, [grunt, boss]);
+
+		let events = app.world.resource::<Events<BossDefeated>>();
+		let bosses = events
+			.get_reader()
+			.iter(events)
+			.copied()
+			.collect::<Vec<_>>();
+		assert_eq!(bosses, [BossDefeated(boss)]);
+	}
+}
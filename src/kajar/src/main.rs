@@ -1,20 +1,45 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bevy::prelude::*;
 
-mod resbin;
-use resbin::*;
+mod action;
+mod ai;
+mod archive;
+mod battle;
+mod player;
+mod save;
+mod tech;
+mod time;
 
-mod util;
+use archive::ArchiveCache;
+use battle::BattlePlugin;
+use kajar_import::ct::resbin::ResBin;
+use player::PlayerPlugin;
+use save::{SavePlugin, SaveSlot};
+use time::PlaytimePlugin;
 
-use std::{
-    env,
-    fs::read
-};
+use std::{env, path::PathBuf};
 
 fn main() -> Result<()> {
     let args = env::args().collect::<Vec<String>>();
-    let buf = read(&args[1])?;
-    let res = ResBin::new(&buf[..]);
+    let path = args
+        .get(1)
+        .context("usage: kajar <resources.bin> [exe] [save]")?;
+    let exe = args.get(2).map(|s| s.as_str());
+    let res = ResBin::load(path, exe)?;
 
-    println!("{:?}", res);
+    println!("{:?}", res.stats());
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(PlaytimePlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(BattlePlugin)
+        .add_plugin(SavePlugin)
+        .insert_resource(ArchiveCache::new(exe));
+    if let Some(save) = args.get(3) {
+        app.insert_resource(SaveSlot(PathBuf::from(save)));
+    }
+
+    app.run();
     Ok(())
 }
@@ -1,26 +1,141 @@
 use bevy::prelude::*;
 
+use bevy::{ecs::system::Command, utils::HashMap};
+use bevy_mod_scripting::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::battle::{
+	Accuracy, Attack, BattleStat, Defense, EnemyDefeated, Evasion, HitPoints, Magic, MagicDefense,
+	MagicPoints, Speed, Stamina, Strength,
+};
+
 /// Playable character experience points
-#[derive(Component)]
+#[derive(Clone, Component, Copy, Debug, Deserialize, Serialize)]
 pub struct Experience {
 	current: i32,
 	next: i32,
 }
 
+/// Most gold the player can hold
+const GOLD_MAX: u32 = 9_999_999;
+
+/// Most silver points the player can hold
+const SILVER_POINTS_MAX: u16 = 9_999;
+
 /// Player gold
-#[derive(Resource)]
+#[derive(Clone, Copy, Debug, Deserialize, Resource, Serialize)]
 pub struct Gold(u32);
 
+/// Refusal to spend more gold than is held
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NotEnoughGold;
+
+impl Gold {
+	/// Adds gold, stopping at the cap
+	pub fn add(&mut self, amount: u32) {
+		self.0 = self.0.saturating_add(amount).min(GOLD_MAX);
+	}
+
+	/// Spends gold, leaving it untouched if there isn't enough
+	pub fn spend(&mut self, amount: u32) -> Result<(), NotEnoughGold> {
+		self.0 = self.0.checked_sub(amount).ok_or(NotEnoughGold)?;
+		Ok(())
+	}
+
+	/// Gold held
+	pub fn get(&self) -> u32 {
+		self.0
+	}
+}
+
+/// Most of one item the player can carry
+const STACK_MAX: u8 = 99;
+
+/// Item identifier
+#[derive(
+	Clone, Copy, Debug, Default, Deserialize, Eq, FromReflect, Hash, PartialEq, Reflect, Serialize,
+)]
+pub struct ItemId(pub u16);
+
+/// Inventory errors
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InventoryErr {
+	/// Tried to remove more of an item than is held, which is given
+	Underflow(ItemId, u8),
+}
+
+/// Items carried by the player
+#[derive(Clone, Debug, Default, Deserialize, Reflect, Resource, Serialize)]
+#[reflect(Resource, LuaProxyable)]
+pub struct Inventory {
+	items: HashMap<ItemId, u8>,
+}
+
+impl Inventory {
+	/// Adds items, stopping once the stack is full
+	pub fn add(&mut self, item: ItemId, qty: u8) {
+		let count = self.items.entry(item).or_insert(0);
+		*count = count.saturating_add(qty).min(STACK_MAX);
+	}
+
+	/// Removes items, leaving the inventory untouched if there aren't enough
+	pub fn remove(&mut self, item: ItemId, qty: u8) -> Result<(), InventoryErr> {
+		let held = self.count(item);
+		if held < qty {
+			return Err(InventoryErr::Underflow(item, held));
+		}
+
+		if held == qty {
+			self.items.remove(&item);
+		} else {
+			self.items.insert(item, held - qty);
+		}
+
+		Ok(())
+	}
+
+	/// How many of an item are held
+	pub fn count(&self, item: ItemId) -> u8 {
+		self.items.get(&item).copied().unwrap_or(0)
+	}
+}
+
 /// Player silver points for Millennial Faire
-#[derive(Resource)]
+#[derive(Clone, Copy, Debug, Deserialize, Resource, Serialize)]
 pub struct SilverPoints(u16);
 
+/// Refusal to spend more silver points than are held
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NotEnoughSilverPoints;
+
+impl SilverPoints {
+	/// Adds silver points, stopping at the cap
+	pub fn add(&mut self, amount: u16) {
+		self.0 = self.0.saturating_add(amount).min(SILVER_POINTS_MAX);
+	}
+
+	/// Spends silver points, leaving them untouched if there aren't enough
+	pub fn spend(&mut self, amount: u16) -> Result<(), NotEnoughSilverPoints> {
+		self.0 = self.0.checked_sub(amount).ok_or(NotEnoughSilverPoints)?;
+		Ok(())
+	}
+
+	/// Silver points held
+	pub fn get(&self) -> u16 {
+		self.0
+	}
+}
+
+/// Sent when a character's experience reaches the next level
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LevelUp(pub Entity);
+
 /// Player character talent points
-#[derive(Component)]
+#[derive(Clone, Component, Copy, Debug, Deserialize, Serialize)]
 pub struct TalentPoints(u16);
 
 /// Playable character weapon
-#[derive(Component)]
+#[derive(Clone, Component, Copy, Debug, Default, Deserialize, Serialize)]
 pub struct Weapon {
 	class: u8,
 	hp: i16,
@@ -36,18 +151,19 @@ pub struct Weapon {
 	sta: i16,
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialHash)]
+/// Which characters can wear a piece of armor
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
 #[repr(u8)]
 pub enum ArmorClass {
 	Male = 0,
-	Female,
+	Female = 1,
 
 	#[default]
-	Unisex,
+	Unisex = 2,
 }
 
 /// Playable character armor
-#[derive(Component)]
+#[derive(Clone, Component, Copy, Debug, Deserialize, Serialize)]
 pub struct Armor {
 	class: ArmorClass,
 	hp: i16,
@@ -63,9 +179,470 @@ pub struct Armor {
 	sta: i16,
 }
 
+/// Playable character gender, which limits the armor they can wear
+#[derive(Clone, Component, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Gender {
+	Male,
+	Female,
+}
+
+/// What a character has equipped
+#[derive(Clone, Component, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Equipment {
+	weapon: Option<Weapon>,
+	armor: Option<Armor>,
+}
+
+/// Equipment errors
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EquipErr {
+	Entity(Entity),
+	WrongGender,
+}
+
+/// A change to a character's equipment; `None` unequips the slot
+#[derive(Clone, Copy, Debug)]
+pub enum EquipChange {
+	Weapon(Option<Weapon>),
+	Armor(Option<Armor>),
+}
+
+/// Asks for a character's equipment to be changed
+#[derive(Clone, Copy, Debug)]
+pub struct EquipRequest {
+	pub entity: Entity,
+	pub change: EquipChange,
+}
+
+impl Command for EquipRequest {
+	fn write(self, world: &mut World) {
+		if let Err(err) = apply_equip(world, self.entity, self.change) {
+			world.send_event(EquipFailed {
+				entity: self.entity,
+				err,
+			});
+		}
+	}
+}
+
+/// Sent when an equipment change is refused
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EquipFailed {
+	pub entity: Entity,
+	pub err: EquipErr,
+}
+
+/// Battle stat changes granted by a piece of equipment
+struct StatDeltas([i16; 11]);
+
+impl Weapon {
+	fn deltas(&self) -> StatDeltas {
+		StatDeltas([
+			self.hp,
+			self.mp,
+			self.strength,
+			self.spd,
+			self.eva,
+			self.acc,
+			self.def,
+			self.mdef,
+			self.mag,
+			self.atk,
+			self.sta,
+		])
+	}
+}
+
+impl Armor {
+	fn deltas(&self) -> StatDeltas {
+		StatDeltas([
+			self.hp,
+			self.mp,
+			self.strength,
+			self.spd,
+			self.eva,
+			self.acc,
+			self.def,
+			self.mdef,
+			self.mag,
+			self.atk,
+			self.sta,
+		])
+	}
+
+	/// Whether a character of `gender` can wear this
+	fn fits(&self, gender: Option<&Gender>) -> bool {
+		match self.class {
+			ArmorClass::Unisex => true,
+			ArmorClass::Male => gender == Some(&Gender::Male),
+			ArmorClass::Female => gender == Some(&Gender::Female),
+		}
+	}
+}
+
+/// Adds (`sign` = 1) or removes (`sign` = -1) equipment stat changes on an entity's stats
+fn shift_stats(world: &mut World, entity: Entity, deltas: &StatDeltas, sign: i16) {
+	fn shift<T: BattleStat + Component>(world: &mut World, entity: Entity, delta: i16) {
+		if let Some(mut stat) = world.get_mut::<T>(entity) {
+			stat.shift(delta);
+		}
+	}
+
+	let [hp, mp, strength, spd, eva, acc, def, mdef, mag, atk, sta] = deltas.0.map(|d| d * sign);
+	shift::<HitPoints>(world, entity, hp);
+	shift::<MagicPoints>(world, entity, mp);
+	shift::<Strength>(world, entity, strength);
+	shift::<Speed>(world, entity, spd);
+	shift::<Evasion>(world, entity, eva);
+	shift::<Accuracy>(world, entity, acc);
+	shift::<Defense>(world, entity, def);
+	shift::<MagicDefense>(world, entity, mdef);
+	shift::<Magic>(world, entity, mag);
+	shift::<Attack>(world, entity, atk);
+	shift::<Stamina>(world, entity, sta);
+}
+
+/// Changes a character's equipment, taking the previous item's stat changes off before applying
+/// the new one's
+pub fn apply_equip(world: &mut World, entity: Entity, change: EquipChange) -> Result<(), EquipErr> {
+	let Some(ent) = world.get_entity(entity) else {
+		return Err(EquipErr::Entity(entity));
+	};
+
+	if let EquipChange::Armor(Some(armor)) = change {
+		if !armor.fits(ent.get::<Gender>()) {
+			return Err(EquipErr::WrongGender);
+		}
+	}
+
+	let mut equipment = ent.get::<Equipment>().copied().unwrap_or_default();
+	let (old, new) = match change {
+		EquipChange::Weapon(weapon) => (
+			std::mem::replace(&mut equipment.weapon, weapon).map(|w| w.deltas()),
+			weapon.map(|w| w.deltas()),
+		),
+		EquipChange::Armor(armor) => (
+			std::mem::replace(&mut equipment.armor, armor).map(|a| a.deltas()),
+			armor.map(|a| a.deltas()),
+		),
+	};
+
+	if let Some(old) = old {
+		shift_stats(world, entity, &old, -1);
+	}
+	if let Some(new) = new {
+		shift_stats(world, entity, &new, 1);
+	}
+	world.entity_mut(entity).insert(equipment);
+
+	Ok(())
+}
+
+/// Equips a weapon, replacing the current one
+pub fn equip_weapon(commands: &mut Commands, entity: Entity, weapon: Weapon) {
+	commands.add(EquipRequest {
+		entity,
+		change: EquipChange::Weapon(Some(weapon)),
+	});
+}
+
+/// Equips armor, replacing the current armor. Armor the character's gender can't wear is
+/// refused with an `EquipFailed` event.
+pub fn equip_armor(commands: &mut Commands, entity: Entity, armor: Armor) {
+	commands.add(EquipRequest {
+		entity,
+		change: EquipChange::Armor(Some(armor)),
+	});
+}
+
+/// Carries out equipment change requests sent as events
+pub fn handle_equip_requests(mut requests: EventReader<EquipRequest>, mut commands: Commands) {
+	for req in requests.iter() {
+		commands.add(*req);
+	}
+}
+
 /// Playable character
 #[derive(Bundle)]
 pub struct PlayerCharacter {
 	tp: TalentPoints,
 	xp: Experience,
 }
+
+/// Splits each defeated enemy's experience evenly among the living characters and gives each of
+/// them its talent points. Nothing is handed out if every character is down.
+pub fn distribute_rewards(
+	mut defeated: EventReader<EnemyDefeated>,
+	mut party: Query<(Entity, &mut Experience, &mut TalentPoints, &HitPoints)>,
+	mut level_up: EventWriter<LevelUp>,
+) {
+	for enemy in defeated.iter() {
+		let living = party.iter().filter(|(.., hp)| hp.is_alive()).count() as u32;
+		if living == 0 {
+			continue;
+		}
+
+		let xp = (enemy.xp / living).min(i32::MAX as u32) as i32;
+		for (entity, mut exp, mut tp, hp) in party.iter_mut() {
+			if !hp.is_alive() {
+				continue;
+			}
+
+			let was_below = exp.current < exp.next;
+			exp.current = exp.current.saturating_add(xp);
+			tp.0 = tp.0.saturating_add(enemy.tp);
+
+			if was_below && exp.current >= exp.next {
+				level_up.send(LevelUp(entity));
+			}
+		}
+	}
+}
+
+/// Party resources, rewards and equipment changes
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+	fn build(&self, app: &mut App) {
+		app.insert_resource(Gold(0))
+			.insert_resource(SilverPoints(0))
+			.init_resource::<Inventory>()
+			.add_event::<EnemyDefeated>()
+			.add_event::<LevelUp>()
+			.add_event::<EquipRequest>()
+			.add_event::<EquipFailed>()
+			.add_system(distribute_rewards)
+			.add_system(handle_equip_requests);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_distribute_rewards() {
+		let mut app = App::new();
+		app.add_event::<EnemyDefeated>()
+			.add_event::<LevelUp>()
+			.add_system(distribute_rewards);
+
+		let mut spawn = |hp: i16, xp: i32| {
+			app.world
+				.spawn((
+					Experience {
+						current: xp,
+						next: 100,
+					},
+					TalentPoints(0),
+					HitPoints::new(hp, 50),
+				))
+				.id()
+		};
+		let crono = spawn(50, 0);
+		let marle = spawn(10, 60);
+		let lucca = spawn(0, 0);
+		let enemy = app.world.spawn_empty().id();
+
+		app.world.send_event(EnemyDefeated {
+			enemy,
+			xp: 81,
+			tp: 3,
+		});
+		app.update();
+
+		let party = |e: Entity| {
+			let exp = app.world.get::<Experience>(e).unwrap();
+			(exp.current, app.world.get::<TalentPoints>(e).unwrap().0)
+		};
+		assert_eq!(party(crono), (40, 3));
+		assert_eq!(party(marle), (100, 3));
+		assert_eq!(party(lucca), (0, 0));
+
+		let events = app.world.resource::<Events<LevelUp>>();
+		let ups = events
+			.get_reader()
+			.iter(events)
+			.copied()
+			.collect::<Vec<_>>();
+		assert_eq!(ups, [LevelUp(marle)]);
+	}
+
+	#[test]
+	fn test_rewards_party_down() {
+		let mut app = App::new();
+		app.add_event::<EnemyDefeated>()
+			.add_event::<LevelUp>()
+			.add_system(distribute_rewards);
+
+		let crono = app
+			.world
+			.spawn((
+				Experience {
+					current: 0,
+					next: 100,
+				},
+				TalentPoints(0),
+				HitPoints::new(0, 50),
+			))
+			.id();
+		let enemy = app.world.spawn_empty().id();
+
+		app.world.send_event(EnemyDefeated {
+			enemy,
+			xp: 500,
+			tp: 3,
+		});
+		app.update();
+
+		assert_eq!(app.world.get::<Experience>(crono).unwrap().current, 0);
+		assert_eq!(app.world.get::<TalentPoints>(crono).unwrap().0, 0);
+	}
+
+	#[test]
+	fn test_equip() {
+		let mut app = App::new();
+		app.add_event::<EquipRequest>()
+			.add_event::<EquipFailed>()
+			.add_system(handle_equip_requests);
+
+		let crono = app
+			.world
+			.spawn((
+				Gender::Male,
+				HitPoints::new(100, 100),
+				Attack::default(),
+				Defense::default(),
+			))
+			.id();
+		let stats = |app: &App| {
+			(
+				app.world.get::<Attack>(crono).unwrap().current(),
+				app.world.get::<Defense>(crono).unwrap().current(),
+			)
+		};
+
+		let sword = Weapon {
+			atk: 10,
+			..Default::default()
+		};
+		let armor = |class: ArmorClass, def: i16| Armor {
+			class,
+			hp: 5,
+			mp: 0,
+			strength: 0,
+			spd: 0,
+			eva: 0,
+			acc: 0,
+			def,
+			mdef: 0,
+			mag: 0,
+			atk: 0,
+			sta: 0,
+		};
+		let mut request = |app: &mut App, change: EquipChange| {
+			app.world.send_event(EquipRequest {
+				entity: crono,
+				change,
+			});
+			app.update();
+		};
+
+		request(&mut app, EquipChange::Weapon(Some(sword)));
+		request(
+			&mut app,
+			EquipChange::Armor(Some(armor(ArmorClass::Unisex, 3))),
+		);
+		assert_eq!(stats(&app), (10, 3));
+
+		// swapping armor removes the old armor's bonus first
+		request(
+			&mut app,
+			EquipChange::Armor(Some(armor(ArmorClass::Male, 7))),
+		);
+		assert_eq!(stats(&app), (10, 7));
+
+		request(
+			&mut app,
+			EquipChange::Armor(Some(armor(ArmorClass::Female, 20))),
+		);
+		assert_eq!(stats(&app), (10, 7));
+		let events = app.world.resource::<Events<EquipFailed>>();
+		let failed = events
+			.get_reader()
+			.iter(events)
+			.copied()
+			.collect::<Vec<_>>();
+		assert_eq!(
+			failed,
+			[EquipFailed {
+				entity: crono,
+				err: EquipErr::WrongGender,
+			}]
+		);
+
+		request(&mut app, EquipChange::Weapon(None));
+		request(&mut app, EquipChange::Armor(None));
+		assert_eq!(stats(&app), (0, 0));
+		assert_eq!(app.world.get::<HitPoints>(crono).unwrap().current(), 100);
+	}
+
+	#[test]
+	fn test_armor_class() {
+		use std::collections::HashMap;
+
+		assert_eq!(ArmorClass::default(), ArmorClass::Unisex);
+		assert_eq!(ArmorClass::Unisex as u8, 2);
+
+		let mut counts = HashMap::new();
+		for class in [ArmorClass::Male, ArmorClass::Unisex, ArmorClass::Male] {
+			*counts.entry(class).or_insert(0) += 1;
+		}
+		assert_eq!(counts[&ArmorClass::Male], 2);
+		assert_eq!(counts[&ArmorClass::Unisex], 1);
+		assert!(!counts.contains_key(&ArmorClass::Female));
+	}
+
+	#[test]
+	fn test_inventory() {
+		let tonic = ItemId(0xBD);
+		let ether = ItemId(0xC2);
+		let mut inv = Inventory::default();
+
+		inv.add(tonic, 90);
+		inv.add(tonic, 20);
+		assert_eq!(inv.count(tonic), STACK_MAX);
+		inv.add(tonic, 255);
+		assert_eq!(inv.count(tonic), STACK_MAX);
+
+		inv.add(ether, 2);
+		assert_eq!(inv.remove(ether, 3), Err(InventoryErr::Underflow(ether, 2)));
+		assert_eq!(inv.count(ether), 2);
+		assert_eq!(inv.remove(ether, 2), Ok(()));
+		assert_eq!(inv.count(ether), 0);
+		assert_eq!(inv.remove(ether, 1), Err(InventoryErr::Underflow(ether, 0)));
+	}
+
+	#[test]
+	fn test_gold() {
+		let mut gold = Gold(GOLD_MAX - 10);
+		gold.add(u32::MAX);
+		assert_eq!(gold.get(), GOLD_MAX);
+
+		assert_eq!(gold.spend(GOLD_MAX - 200), Ok(()));
+		assert_eq!(gold.spend(201), Err(NotEnoughGold));
+		assert_eq!(gold.get(), 200);
+		assert_eq!(gold.spend(200), Ok(()));
+		assert_eq!(gold.get(), 0);
+
+		let mut silver = SilverPoints(15);
+		silver.add(u16::MAX);
+		assert_eq!(silver.get(), SILVER_POINTS_MAX);
+		assert_eq!(
+			silver.spend(SILVER_POINTS_MAX + 1),
+			Err(NotEnoughSilverPoints)
+		);
+		assert_eq!(silver.spend(SILVER_POINTS_MAX), Ok(()));
+		assert_eq!(silver.get(), 0);
+	}
+}
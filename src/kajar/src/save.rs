@@ -0,0 +1,315 @@
+use bevy::{ecs::world::EntityMut, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use std::{
+	fs::{self, File},
+	io::{self, BufWriter},
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use crate::{
+	battle::{
+		Accuracy, Attack, Defense, Evasion, HitPoints, Magic, MagicDefense, MagicPoints, Speed,
+		Stamina, StatModifiers, Strength,
+	},
+	player::{Equipment, Experience, Gender, Gold, Inventory, SilverPoints, TalentPoints},
+	tech::CharacterId,
+	time::GameTime,
+};
+
+/// Save file error
+#[derive(Debug)]
+pub enum SaveErr {
+	Deserialize(serde_json::Error),
+	FileRead(io::Error),
+	FileWrite(io::Error),
+	Serialize(serde_json::Error),
+}
+
+/// Where `load_save` and `write_save` read and write the save file
+#[derive(Clone, Debug, Resource)]
+pub struct SaveSlot(pub PathBuf);
+
+/// A playable character's saved components. Components the character doesn't have are `None`.
+/// Active stat modifiers are kept so buffed stats still wear off after a load.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CharacterSave {
+	id: Option<CharacterId>,
+	xp: Experience,
+	tp: Option<TalentPoints>,
+	hp: Option<HitPoints>,
+	mp: Option<MagicPoints>,
+	strength: Option<Strength>,
+	spd: Option<Speed>,
+	eva: Option<Evasion>,
+	acc: Option<Accuracy>,
+	def: Option<Defense>,
+	mdef: Option<MagicDefense>,
+	mag: Option<Magic>,
+	atk: Option<Attack>,
+	sta: Option<Stamina>,
+	mods: Option<StatModifiers>,
+	equipment: Option<Equipment>,
+	gender: Option<Gender>,
+}
+
+/// Snapshot of the player's progress: the party's resources and every playable character, which
+/// is any entity with `Experience`. Resources missing from the world are left out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SaveState {
+	gold: Option<Gold>,
+	silver: Option<SilverPoints>,
+	inventory: Option<Inventory>,
+	playtime: Option<Duration>,
+	party: Vec<CharacterSave>,
+}
+
+impl SaveState {
+	/// Takes a snapshot of the world
+	pub fn snapshot(world: &mut World) -> SaveState {
+		let party = world
+			.query_filtered::<Entity, With<Experience>>()
+			.iter(world)
+			.collect::<Vec<Entity>>()
+			.into_iter()
+			.map(|e| {
+				let e = world.entity(e);
+				CharacterSave {
+					id: e.get().copied(),
+					xp: *e.get::<Experience>().unwrap(),
+					tp: e.get().copied(),
+					hp: e.get().copied(),
+					mp: e.get().copied(),
+					strength: e.get().copied(),
+					spd: e.get().copied(),
+					eva: e.get().copied(),
+					acc: e.get().copied(),
+					def: e.get().copied(),
+					mdef: e.get().copied(),
+					mag: e.get().copied(),
+					atk: e.get().copied(),
+					sta: e.get().copied(),
+					mods: e.get().cloned(),
+					equipment: e.get().copied(),
+					gender: e.get().copied(),
+				}
+			})
+			.collect();
+
+		SaveState {
+			gold: world.get_resource().copied(),
+			silver: world.get_resource().copied(),
+			inventory: world.get_resource().cloned(),
+			playtime: world.get_resource::<GameTime>().map(|t| t.0),
+			party,
+		}
+	}
+
+	/// Replaces the party and its resources with the snapshot's
+	pub fn restore(self, world: &mut World) {
+		let old = world
+			.query_filtered::<Entity, With<Experience>>()
+			.iter(world)
+			.collect::<Vec<Entity>>();
+		for e in old {
+			world.despawn(e);
+		}
+
+		for c in self.party {
+			let mut e = world.spawn(c.xp);
+			insert(&mut e, c.id);
+			insert(&mut e, c.tp);
+			insert(&mut e, c.hp);
+			insert(&mut e, c.mp);
+			insert(&mut e, c.strength);
+			insert(&mut e, c.spd);
+			insert(&mut e, c.eva);
+			insert(&mut e, c.acc);
+			insert(&mut e, c.def);
+			insert(&mut e, c.mdef);
+			insert(&mut e, c.mag);
+			insert(&mut e, c.atk);
+			insert(&mut e, c.sta);
+			insert(&mut e, c.mods);
+			insert(&mut e, c.equipment);
+			insert(&mut e, c.gender);
+		}
+
+		if let Some(gold) = self.gold {
+			world.insert_resource(gold);
+		}
+		if let Some(silver) = self.silver {
+			world.insert_resource(silver);
+		}
+		if let Some(inventory) = self.inventory {
+			world.insert_resource(inventory);
+		}
+		if let Some(playtime) = self.playtime {
+			world.insert_resource(GameTime(playtime));
+		}
+	}
+
+	/// Reads a JSON save file
+	pub fn load(path: &Path) -> Result<SaveState, SaveErr> {
+		let buf = fs::read(path).map_err(|e| SaveErr::FileRead(e))?;
+		serde_json::from_slice(&buf[..]).map_err(|e| SaveErr::Deserialize(e))
+	}
+
+	/// Writes a JSON save file
+	pub fn save(&self, path: &Path) -> Result<(), SaveErr> {
+		let file = File::create(path).map_err(|e| SaveErr::FileWrite(e))?;
+		serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(|e| SaveErr::Serialize(e))
+	}
+}
+
+/// Adds a component to an entity if there is one to add
+fn insert<T: Component>(e: &mut EntityMut, c: Option<T>) {
+	if let Some(c) = c {
+		e.insert(c);
+	}
+}
+
+/// Restores the game from the file in `SaveSlot`
+pub fn load_save(world: &mut World) {
+	let Some(SaveSlot(path)) = world.get_resource::<SaveSlot>().cloned() else {
+		return;
+	};
+
+	match SaveState::load(&path) {
+		Ok(state) => state.restore(world),
+		Err(e) => warn!("couldn't load {}: {:?}", path.display(), e),
+	}
+}
+
+/// Saves the game to the file in `SaveSlot`
+pub fn write_save(world: &mut World) {
+	let Some(SaveSlot(path)) = world.get_resource::<SaveSlot>().cloned() else {
+		return;
+	};
+
+	if let Err(e) = SaveState::snapshot(world).save(&path) {
+		warn!("couldn't save {}: {:?}", path.display(), e);
+	}
+}
+
+/// Restores the game from `SaveSlot`, if one is set, on startup
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_startup_system(load_save);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use serde::de::DeserializeOwned;
+	use serde_json::json;
+	use tempfile::NamedTempFile;
+
+	use crate::{
+		battle::{
+			recompute_stats, tick_modifiers, BattleStat, Delta, Stat, StatModifier, StatModifiers,
+		},
+		player::ItemId,
+	};
+
+	/// Builds a value whose fields are private to its module
+	fn de<T: DeserializeOwned>(v: serde_json::Value) -> T {
+		serde_json::from_value(v).unwrap()
+	}
+
+	#[test]
+	fn test_save_round_trip() {
+		let mut app = App::new();
+		app.add_systems((recompute_stats, tick_modifiers).chain());
+
+		let mut inventory = Inventory::default();
+		inventory.add(ItemId(0xBD), 5);
+		app.world.insert_resource(de::<Gold>(json!(1200)));
+		app.world.insert_resource(inventory);
+		app.world
+			.insert_resource(GameTime(Duration::from_secs(3661)));
+
+		for (id, hp, gender) in [(0, 120, Gender::Male), (1, 90, Gender::Female)] {
+			let mut atk = Attack::default();
+			atk.shift(10);
+			let mut mods = StatModifiers::default();
+			mods.push(StatModifier {
+				stat: Stat::Attack,
+				delta: Delta::Flat(5),
+				source: None,
+				turns: 3,
+			});
+
+			app.world.spawn((
+				CharacterId(id),
+				de::<Experience>(json!({ "current": 40, "next": 100 })),
+				de::<TalentPoints>(json!(3)),
+				HitPoints::new(hp, 150),
+				atk,
+				mods,
+				gender,
+			));
+		}
+		app.update();
+
+		let f = NamedTempFile::new().unwrap();
+		app.world.insert_resource(SaveSlot(f.path().to_path_buf()));
+		write_save(&mut app.world);
+		let before = serde_json::to_value(SaveState::snapshot(&mut app.world)).unwrap();
+
+		let party = app
+			.world
+			.query_filtered::<Entity, With<Experience>>()
+			.iter(&app.world)
+			.collect::<Vec<Entity>>();
+		for e in party {
+			app.world.despawn(e);
+		}
+		app.world.remove_resource::<Gold>();
+		app.world.insert_resource(Inventory::default());
+
+		load_save(&mut app.world);
+		let after = serde_json::to_value(SaveState::snapshot(&mut app.world)).unwrap();
+		assert_eq!(before, after);
+		assert_eq!(before["party"][0]["atk"]["current"], 15);
+		assert_eq!(before["party"][0]["atk"]["normal"], 10);
+
+		let mut party = app
+			.world
+			.query::<(&CharacterId, &HitPoints, &Gender)>()
+			.iter(&app.world)
+			.map(|(id, hp, gender)| (*id, hp.current(), *gender))
+			.collect::<Vec<_>>();
+		party.sort_by_key(|c| c.0);
+		assert_eq!(
+			party,
+			[
+				(CharacterId(0), 120, Gender::Male),
+				(CharacterId(1), 90, Gender::Female)
+			]
+		);
+
+		// the buff still has two turns to run, and wears off after them
+		let atks = |world: &mut World| {
+			world
+				.query::<&Attack>()
+				.iter(world)
+				.map(|a| a.current())
+				.collect::<Vec<i16>>()
+		};
+		assert_eq!(atks(&mut app.world), [15, 15]);
+		app.update();
+		app.update();
+		assert_eq!(atks(&mut app.world), [15, 15]);
+		app.update();
+		assert_eq!(atks(&mut app.world), [10, 10]);
+		assert_eq!(app.world.resource::<Gold>().get(), 1200);
+		assert_eq!(app.world.resource::<Inventory>().count(ItemId(0xBD)), 5);
+		assert_eq!(app.world.resource::<GameTime>().to_string(), "01:01:01");
+	}
+}
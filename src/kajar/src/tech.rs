@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::battle::{AtbGauge, Element, ReadyToAct};
+
+/// Which playable character an entity is, which techs refer to
+#[derive(
+	Clone, Copy, Component, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+pub struct CharacterId(pub u8);
+
+/// What a tech hits
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TargetShape {
+	/// One enemy
+	Single,
+
+	/// Enemies around the target
+	Area,
+
+	/// Enemies in a line from the user
+	Line,
+	AllEnemies,
+
+	/// One party member
+	Ally,
+	AllAllies,
+}
+
+/// A tech, performed by one character or, for dual and triple techs, several at once
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tech {
+	pub name: String,
+	pub participants: Vec<CharacterId>,
+
+	/// `None` for physical techs
+	pub element: Option<Element>,
+	pub power: i16,
+	pub target: TargetShape,
+}
+
+impl Tech {
+	/// Whether more than one character performs the tech
+	pub fn is_combo(&self) -> bool {
+		self.participants.len() > 1
+	}
+}
+
+/// Every tech the party knows
+#[derive(Clone, Debug, Default, Resource)]
+pub struct TechList(pub Vec<Tech>);
+
+/// Combo techs the party can perform right now
+#[derive(Clone, Debug, Default, Resource)]
+pub struct AvailableCombos(pub Vec<Tech>);
+
+/// The combo techs in `techs` whose participants are all in `ready`
+pub fn resolve_combo(techs: &[Tech], ready: &[CharacterId]) -> Vec<Tech> {
+	techs
+		.iter()
+		.filter(|t| t.is_combo() && t.participants.iter().all(|p| ready.contains(p)))
+		.cloned()
+		.collect()
+}
+
+/// Works out the available combo techs again whenever a character becomes ready to act. Only
+/// characters whose ATB gauge is still full count, so one who has since acted drops out.
+pub fn update_combos(
+	mut ready: EventReader<ReadyToAct>,
+	party: Query<(&CharacterId, &AtbGauge)>,
+	techs: Res<TechList>,
+	mut combos: ResMut<AvailableCombos>,
+) {
+	if ready.iter().count() == 0 {
+		return;
+	}
+
+	let ready = party
+		.iter()
+		.filter(|(_, gauge)| gauge.is_full())
+		.map(|(id, _)| *id)
+		.collect::<Vec<CharacterId>>();
+	combos.0 = resolve_combo(&techs.0, &ready[..]);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CRONO: CharacterId = CharacterId(0);
+	const MARLE: CharacterId = CharacterId(1);
+	const LUCCA: CharacterId = CharacterId(2);
+
+	fn tech(name: &str, participants: &[CharacterId], element: Option<Element>) -> Tech {
+		Tech {
+			name: name.to_owned(),
+			participants: participants.to_vec(),
+			element,
+			power: 30,
+			target: TargetShape::Single,
+		}
+	}
+
+	fn techs() -> Vec<Tech> {
+		vec![
+			tech("Cyclone", &[CRONO], None),
+			tech("Aura Whirl", &[CRONO, MARLE], None),
+			tech("Ice Sword", &[MARLE, CRONO], Some(Element::Water)),
+			tech("Fire Whirl", &[CRONO, LUCCA], Some(Element::Fire)),
+			tech("Antipode", &[MARLE, LUCCA], None),
+			tech("Delta Force", &[CRONO, MARLE, LUCCA], Some(Element::Shadow)),
+		]
+	}
+
+	fn names(techs: &[Tech]) -> Vec<&str> {
+		techs.iter().map(|t| t.name.as_str()).collect()
+	}
+
+	#[test]
+	fn test_resolve_combo() {
+		let techs = techs();
+
+		assert_eq!(
+			names(&resolve_combo(&techs, &[MARLE, CRONO])),
+			["Aura Whirl", "Ice Sword"]
+		);
+		assert_eq!(
+			names(&resolve_combo(&techs, &[CRONO, MARLE, LUCCA])).len(),
+			5
+		);
+		assert!(resolve_combo(&techs, &[CRONO]).is_empty());
+		assert!(resolve_combo(&techs, &[]).is_empty());
+	}
+
+	#[test]
+	fn test_update_combos() {
+		let mut app = App::new();
+		app.add_event::<ReadyToAct>()
+			.insert_resource(TechList(techs()))
+			.init_resource::<AvailableCombos>()
+			.add_system(update_combos);
+
+		// a gauge that fills at zero is always full
+		let full = AtbGauge::new(0.0);
+		let crono = app.world.spawn((CRONO, full)).id();
+		let marle = app.world.spawn((MARLE, full)).id();
+		app.world.spawn((LUCCA, AtbGauge::new(100.0)));
+
+		app.world.send_event(ReadyToAct(crono));
+		app.world.send_event(ReadyToAct(marle));
+		app.update();
+		assert_eq!(
+			names(&app.world.resource::<AvailableCombos>().0),
+			["Aura Whirl", "Ice Sword"]
+		);
+	}
+}
@@ -1,6 +1,79 @@
 use bevy::prelude::*;
-use std::time::Duration;
+use std::{fmt, time::Duration};
 
 /// Game play time
 #[derive(Resource)]
-pub struct GameTime(Duration);
+pub struct GameTime(pub Duration);
+
+impl GameTime {
+	/// Play time in hours, minutes and seconds
+	pub fn as_hms(&self) -> (u64, u8, u8) {
+		let secs = self.0.as_secs();
+		(secs / 3600, (secs / 60 % 60) as u8, (secs % 60) as u8)
+	}
+}
+
+impl fmt::Display for GameTime {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let (h, m, s) = self.as_hms();
+		write!(f, "{:02}:{:02}:{:02}", h, m, s)
+	}
+}
+
+/// Advances play time by the frame's duration
+pub fn tick_playtime(time: Res<Time>, mut playtime: ResMut<GameTime>) {
+	playtime.0 += time.delta();
+}
+
+/// Play time, starting from zero until a save is loaded. Needs bevy's `TimePlugin`.
+pub struct PlaytimePlugin;
+
+impl Plugin for PlaytimePlugin {
+	fn build(&self, app: &mut App) {
+		app.insert_resource(GameTime(Duration::ZERO))
+			.add_system(tick_playtime);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bevy::utils::Instant;
+
+	#[test]
+	fn test_playtime_format() {
+		let t = |secs| GameTime(Duration::from_secs(secs)).to_string();
+
+		assert_eq!(t(0), "00:00:00");
+		assert_eq!(t(59), "00:00:59");
+		assert_eq!(t(60), "00:01:00");
+		assert_eq!(t(3599), "00:59:59");
+		assert_eq!(t(3600), "01:00:00");
+		assert_eq!(t(100 * 3600 + 61), "100:01:01");
+		assert_eq!(
+			GameTime(Duration::from_millis(3_599_999)).as_hms(),
+			(0, 59, 59)
+		);
+	}
+
+	#[test]
+	fn test_tick_playtime() {
+		let mut app = App::new();
+		app.insert_resource(GameTime(Duration::from_secs(3599)))
+			.add_system(tick_playtime);
+
+		let start = Instant::now();
+		let mut time = Time::new(start);
+		time.update_with_instant(start);
+		app.insert_resource(time);
+		app.update();
+		assert_eq!(app.world.resource::<GameTime>().to_string(), "00:59:59");
+
+		app.world
+			.resource_mut::<Time>()
+			.update_with_instant(start + Duration::from_millis(1500));
+		app.update();
+		assert_eq!(app.world.resource::<GameTime>().to_string(), "01:00:00");
+	}
+}